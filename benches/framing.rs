@@ -0,0 +1,205 @@
+//! Framing and parsing throughput.
+//!
+//! `cargo bench --bench framing`, or `cargo bench --bench framing -- parse` for one group. Run
+//! `magic_search` with and without `--features memchr` to compare the two searches.
+
+use criterion::{BatchSize, BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use stream_framer::{
+    FrameParser, FrameWriter, InspectEvent, MAGIC_PREFIX, ParsedStreamData, ParserState, inspect,
+};
+
+const MESSAGE_SIZES: [usize; 4] = [16, 256, 4096, 65536];
+// a tiny packet (smaller than a header), an Ethernet-ish MTU and a large read
+const PACKET_SIZES: [usize; 3] = [7, 1200, 65536];
+// bytes of messages per parse iteration
+const STREAM_LEN: usize = 1 << 20;
+
+fn prepend_frame(c: &mut Criterion) {
+    let mut group = c.benchmark_group("prepend_frame");
+    for size in MESSAGE_SIZES {
+        group.throughput(Throughput::Bytes(size as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(size), &size, |b, &size| {
+            b.iter_batched(
+                || vec![0xab; size],
+                FrameWriter::prepend_frame,
+                BatchSize::SmallInput,
+            );
+        });
+    }
+    group.finish();
+}
+
+fn prepend_frame_in_place(c: &mut Criterion) {
+    let mut group = c.benchmark_group("prepend_frame_in_place");
+    for size in MESSAGE_SIZES {
+        group.throughput(Throughput::Bytes(size as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(size), &size, |b, &size| {
+            b.iter_batched_ref(
+                || vec![0xab; size],
+                |message| message.prepend_frame_in_place(),
+                BatchSize::SmallInput,
+            );
+        });
+    }
+    group.finish();
+}
+
+/// `STREAM_LEN` bytes worth of `message_size` messages, cut in `packet_size` packets.
+fn packets(message_size: usize, packet_size: usize) -> Vec<Vec<u8>> {
+    let count = (STREAM_LEN / message_size).max(1);
+    let stream: Vec<u8> = (0..count)
+        .flat_map(|_| vec![0xab; message_size].prepend_frame().unwrap())
+        .collect();
+    stream.chunks(packet_size).map(<[u8]>::to_vec).collect()
+}
+
+fn parse_frame_header(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parse_frame_header");
+    group.sample_size(20);
+    for message_size in MESSAGE_SIZES {
+        for packet_size in PACKET_SIZES {
+            let packets = packets(message_size, packet_size);
+            group.throughput(Throughput::Bytes(
+                packets.iter().map(|packet| packet.len() as u64).sum(),
+            ));
+            group.bench_with_input(
+                BenchmarkId::new(
+                    format!("message_{message_size}"),
+                    format!("packet_{packet_size}"),
+                ),
+                &packets,
+                |b, packets| {
+                    b.iter_batched(
+                        || packets.clone(),
+                        |packets| {
+                            let mut incomplete = None;
+                            let mut truncated = None;
+                            let mut completed = 0;
+                            for packet in packets {
+                                for parsed in packet
+                                    .parse_frame_header(incomplete.take(), truncated.take())
+                                    .unwrap()
+                                {
+                                    match parsed {
+                                        ParsedStreamData::Incompleted(size, data) => {
+                                            incomplete = Some((size, data));
+                                        }
+                                        ParsedStreamData::TruncatedHeader(hdr) => {
+                                            truncated = Some(hdr);
+                                        }
+                                        _ => completed += 1,
+                                    }
+                                }
+                            }
+                            completed
+                        },
+                        BatchSize::LargeInput,
+                    );
+                },
+            );
+        }
+    }
+    group.finish();
+}
+
+fn parser_state(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parser_state");
+    group.sample_size(20);
+    for message_size in MESSAGE_SIZES {
+        let packets = packets(message_size, 1200);
+        group.throughput(Throughput::Bytes(
+            packets.iter().map(|packet| packet.len() as u64).sum(),
+        ));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(message_size),
+            &packets,
+            |b, packets| {
+                b.iter_batched(
+                    || packets.clone(),
+                    |packets| {
+                        let mut state = ParserState::new();
+                        packets
+                            .into_iter()
+                            .map(|packet| state.parse(packet).unwrap().len())
+                            .sum::<usize>()
+                    },
+                    BatchSize::LargeInput,
+                );
+            },
+        );
+    }
+    group.finish();
+}
+
+/// `len` bytes of `filler` with a frame every MiB, the rest of the capture is garbage to skip.
+fn sparse_capture(len: usize, filler: impl FnMut(usize) -> u8) -> Vec<u8> {
+    let mut capture: Vec<u8> = (0..len).map(filler).collect();
+    let frame = b"sparse".to_vec().prepend_frame().unwrap();
+    for start in (0..len - frame.len()).step_by(1 << 20).skip(1) {
+        capture[start..start + frame.len()].copy_from_slice(&frame);
+    }
+    capture
+}
+
+/// What `find_magic_prefix` used to be, for comparison.
+fn windows_search(capture: &[u8]) -> usize {
+    let mut found = 0;
+    let mut rest = capture;
+    while let Some(position) = rest
+        .windows(MAGIC_PREFIX.len())
+        .position(|window| window == MAGIC_PREFIX)
+    {
+        found += 1;
+        rest = &rest[position + 1..];
+    }
+    found
+}
+
+fn magic_search(c: &mut Criterion) {
+    let mut group = c.benchmark_group("magic_search");
+    group.sample_size(20);
+    let len = 8 << 20;
+    let captures = [
+        // xorshift noise, the magic bytes are as frequent as any other
+        ("noise", {
+            let mut state = 0x2545_f491_4f6c_dd1du64;
+            sparse_capture(len, move |_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+        }),
+        // worst case for a search keyed on the leading 0x00
+        ("zeroes", sparse_capture(len, |_| 0)),
+        (
+            "text",
+            sparse_capture(len, |i| b"lorem ipsum dolor "[i % 18]),
+        ),
+    ];
+    for (name, capture) in &captures {
+        group.throughput(Throughput::Bytes(capture.len() as u64));
+        group.bench_with_input(BenchmarkId::new("inspect", name), capture, |b, capture| {
+            b.iter(|| {
+                inspect(capture)
+                    .iter()
+                    .filter(|event| matches!(event, InspectEvent::Resync { .. }))
+                    .count()
+            });
+        });
+        group.bench_with_input(BenchmarkId::new("windows", name), capture, |b, capture| {
+            b.iter(|| windows_search(capture));
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    prepend_frame,
+    prepend_frame_in_place,
+    parse_frame_header,
+    parser_state,
+    magic_search
+);
+criterion_main!(benches);
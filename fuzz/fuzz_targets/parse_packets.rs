@@ -0,0 +1,31 @@
+//! Arbitrary packets fed to every decoder : errors are fine, panics and hangs are not.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use stream_framer::{HDR_SIZE, ParserState, RingFrameDecoder, SliceFrameDecoder, inspect};
+
+fuzz_target!(|input: (bool, Vec<Vec<u8>>)| {
+    let (resync, packets) = input;
+    let mut state = ParserState::new();
+    let mut buf = [0u8; 256];
+    let mut slice_decoder = SliceFrameDecoder::new(&mut buf);
+    let mut ring_decoder = RingFrameDecoder::with_capacity(256 + HDR_SIZE);
+    if resync {
+        state = state.with_resync();
+        slice_decoder = slice_decoder.with_resync();
+        ring_decoder = ring_decoder.with_resync();
+    }
+
+    for packet in &packets {
+        let _ = state.parse(packet.clone());
+        let _ = slice_decoder.decode(packet, |_| {});
+
+        let mut packet = &packet[..];
+        while !packet.is_empty() {
+            packet = &packet[ring_decoder.push(packet)..];
+            while let Ok(Some(_)) = ring_decoder.next_frame() {}
+        }
+    }
+    let _ = inspect(&packets.concat());
+});
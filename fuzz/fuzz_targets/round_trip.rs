@@ -0,0 +1,86 @@
+//! Arbitrary messages cut in arbitrary packets : the decoders hand back exactly the messages.
+
+#![no_main]
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use stream_framer::{
+    FrameWriter, HDR_SIZE, ParsedStreamData, ParserState, RingFrameDecoder, SliceFrameDecoder,
+};
+
+const CAPACITY: usize = 1024;
+
+#[derive(Debug, Arbitrary)]
+struct Input {
+    messages: Vec<Vec<u8>>,
+    // packet sizes, cycled through
+    sizes: Vec<u8>,
+}
+
+fuzz_target!(|input: Input| {
+    let messages: Vec<Vec<u8>> = input
+        .messages
+        .into_iter()
+        .map(|mut message| {
+            message.truncate(CAPACITY);
+            message
+        })
+        .collect();
+    let stream = messages
+        .iter()
+        .map(|message| message.clone().prepend_frame().unwrap())
+        .collect::<Vec<_>>()
+        .concat();
+    let sizes: Vec<usize> = input
+        .sizes
+        .iter()
+        .map(|size| usize::from(*size).max(1))
+        .collect();
+    let sizes = if sizes.is_empty() {
+        vec![HDR_SIZE]
+    } else {
+        sizes
+    };
+
+    let mut packets = vec![];
+    let mut rest = &stream[..];
+    for size in sizes.iter().cycle() {
+        if rest.is_empty() {
+            break;
+        }
+        let (packet, tail) = rest.split_at((*size).min(rest.len()));
+        packets.push(packet);
+        rest = tail;
+    }
+
+    let mut state = ParserState::new();
+    let mut buf = [0u8; CAPACITY];
+    let mut slice_decoder = SliceFrameDecoder::new(&mut buf);
+    let mut ring_decoder = RingFrameDecoder::with_capacity(CAPACITY + HDR_SIZE);
+    let (mut from_state, mut from_slice, mut from_ring) = (vec![], vec![], vec![]);
+
+    for packet in packets {
+        for parsed in state.parse(packet.to_vec()).unwrap() {
+            match parsed {
+                ParsedStreamData::Completed(message) => from_state.push(message),
+                _ => panic!("only plain frames were sent"),
+            }
+        }
+        slice_decoder
+            .decode(packet, |message| from_slice.push(message.to_vec()))
+            .unwrap();
+
+        let mut packet = packet;
+        while !packet.is_empty() {
+            packet = &packet[ring_decoder.push(packet)..];
+            while let Some(frame) = ring_decoder.next_frame().unwrap() {
+                from_ring.push(frame.to_vec());
+            }
+        }
+    }
+
+    assert_eq!(from_state, messages);
+    assert_eq!(from_slice, messages);
+    assert_eq!(from_ring, messages);
+    assert!(state.is_empty());
+});
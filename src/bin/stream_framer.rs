@@ -0,0 +1,697 @@
+//! `stream-framer` : command line tools for captured streams.
+//!
+//! ```text
+//! stream-framer inspect [--json] [--preview BYTES] [FILE]
+//! stream-framer encode [--lines] [-o OUT] [FILE...]
+//! stream-framer split --mtu N [--out-dir DIR] [FILE]
+//! stream-framer join [--lines] [--out-dir DIR] PACKET...
+//! stream-framer pcap [--flow N] [--json] [--preview BYTES] [FILE]
+//! ```
+//!
+//! `FILE` defaults to stdin (also selected with `-`). The exit code is 0 on success, 1 if some
+//! anomaly was found in the stream and 2 on usage or io errors.
+
+use std::{
+    fmt::Write as _,
+    fs,
+    io::{self, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use stream_framer::{
+    Capture, ControlFrame, Flow, FrameError, FrameTags, FrameWriter, HDR_SIZE, InspectEvent,
+    ParsedStreamData, ParserState, Topic, Transport, inspect, wireshark_dissector,
+};
+
+const USAGE: &str = "\
+usage: stream-framer <command> [options]
+
+commands:
+  inspect [--json] [--preview BYTES] [FILE]
+      print every frame of a captured stream (offset, header, length, payload preview) and
+      point out bad magic prefixes, resyncs and truncation at EOF. FILE defaults to stdin.
+      --json           one JSON object per line
+      --preview BYTES  payload bytes shown per frame (default 32)
+
+  encode [--lines] [-o OUT] [FILE...]
+      wrap each input file (or each line with --lines) in a frame. Writes to stdout unless
+      -o is given. FILE defaults to stdin.
+
+  split --mtu N [--out-dir DIR] [FILE]
+      cut a framed stream into packets of at most N bytes, written as DIR/packet-NNNNNN.bin
+      (DIR defaults to the current directory). FILE defaults to stdin.
+
+  join [--lines] [--out-dir DIR] PACKET...
+      feed packet files (directories are expanded in name order) through the decoder and
+      write out the messages : concatenated on stdout, one per line with --lines, or as
+      DIR/message-NNNNNN.bin with --out-dir. Control frames are reported on stderr.
+
+  pcap [--flow N] [--json] [--preview BYTES] [FILE]
+      list the TCP and UDP flows of a pcap or pcapng file, or inspect the reassembled bytes
+      of flow N as the inspect command does. FILE defaults to stdin.
+
+  dissector [-o OUT]
+      write a Wireshark Lua dissector for the frames (to stdout unless -o is given), to be
+      copied in the Wireshark personal Lua plugins folder.
+";
+
+const DEFAULT_PREVIEW: usize = 32;
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match run(&args) {
+        Ok(code) => code,
+        // `stream-framer inspect capture | head`
+        Err(CliError::Io(e)) if e.kind() == io::ErrorKind::BrokenPipe => ExitCode::SUCCESS,
+        Err(e @ CliError::Frame(_)) => {
+            eprintln!("stream-framer: {e}");
+            ExitCode::from(1)
+        }
+        Err(e) => {
+            eprintln!("stream-framer: {e}");
+            ExitCode::from(2)
+        }
+    }
+}
+
+enum CliError {
+    Usage(String),
+    Io(io::Error),
+    Frame(FrameError),
+}
+
+impl std::fmt::Display for CliError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CliError::Usage(msg) => write!(f, "{msg}\n\n{USAGE}"),
+            CliError::Io(e) => write!(f, "io error : [{e}]"),
+            CliError::Frame(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl From<io::Error> for CliError {
+    fn from(value: io::Error) -> Self {
+        CliError::Io(value)
+    }
+}
+
+impl From<FrameError> for CliError {
+    fn from(value: FrameError) -> Self {
+        CliError::Frame(value)
+    }
+}
+
+fn run(args: &[String]) -> Result<ExitCode, CliError> {
+    let Some((command, args)) = args.split_first() else {
+        return Err(CliError::Usage("missing command".to_string()));
+    };
+    match command.as_str() {
+        "inspect" => inspect_command(args),
+        "encode" => encode_command(args),
+        "split" => split_command(args),
+        "join" => join_command(args),
+        "pcap" => pcap_command(args),
+        "dissector" => dissector_command(args),
+        "help" | "-h" | "--help" => {
+            print!("{USAGE}");
+            Ok(ExitCode::SUCCESS)
+        }
+        other => Err(CliError::Usage(format!("unknown command [{other}]"))),
+    }
+}
+
+fn inspect_command(args: &[String]) -> Result<ExitCode, CliError> {
+    let mut json = false;
+    let mut preview = DEFAULT_PREVIEW;
+    let mut input = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--json" => json = true,
+            "--preview" => preview = number_value(args.next(), "--preview")?,
+            flag if flag.starts_with("--") => {
+                return Err(CliError::Usage(format!("unknown option [{flag}]")));
+            }
+            path if input.is_none() => input = Some(path),
+            extra => return Err(CliError::Usage(format!("unexpected argument [{extra}]"))),
+        }
+    }
+
+    report(&read_input(input)?, json, preview)
+}
+
+/// Print what `inspect` finds in `stream`, exit code 1 if something is wrong.
+fn report(stream: &[u8], json: bool, preview: usize) -> Result<ExitCode, CliError> {
+    let events = inspect(stream);
+
+    let mut out = BufWriter::new(io::stdout().lock());
+    for event in &events {
+        let line = if json {
+            event_json(event, preview)
+        } else {
+            event_text(event, preview)
+        };
+        writeln!(out, "{line}")?;
+    }
+
+    let frames = events
+        .iter()
+        .filter(|event| matches!(event, InspectEvent::Frame { .. }))
+        .count();
+    let anomalies = events.iter().filter(|event| event.is_anomaly()).count();
+    if !json {
+        writeln!(
+            out,
+            "{} bytes, {frames} frames, {anomalies} anomalies",
+            stream.len()
+        )?;
+    }
+    out.flush()?;
+
+    Ok(if anomalies == 0 {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::from(1)
+    })
+}
+
+fn pcap_command(args: &[String]) -> Result<ExitCode, CliError> {
+    let mut json = false;
+    let mut preview = DEFAULT_PREVIEW;
+    let mut flow = None;
+    let mut input = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--json" => json = true,
+            "--preview" => preview = number_value(args.next(), "--preview")?,
+            "--flow" => flow = Some(number_value(args.next(), "--flow")?),
+            flag if flag.starts_with("--") => {
+                return Err(CliError::Usage(format!("unknown option [{flag}]")));
+            }
+            path if input.is_none() => input = Some(path),
+            extra => return Err(CliError::Usage(format!("unexpected argument [{extra}]"))),
+        }
+    }
+
+    let capture = Capture::parse(&read_input(input)?)?;
+
+    let Some(index) = flow else {
+        let mut out = BufWriter::new(io::stdout().lock());
+        for (index, flow) in capture.flows().iter().enumerate() {
+            let line = if json {
+                flow_json(index, flow)
+            } else {
+                flow_text(index, flow)
+            };
+            writeln!(out, "{line}")?;
+        }
+        if !json {
+            writeln!(
+                out,
+                "{} packets, {} flows, {} packets without TCP or UDP payload",
+                capture.packets(),
+                capture.flows().len(),
+                capture.skipped()
+            )?;
+        }
+        out.flush()?;
+        return Ok(ExitCode::SUCCESS);
+    };
+
+    let flow = capture.flows().get(index).ok_or_else(|| {
+        CliError::Usage(format!(
+            "no flow [{index}], the capture has {} flows",
+            capture.flows().len()
+        ))
+    })?;
+    let stream = flow.stream();
+    for gap in &stream.gaps {
+        // before the report, which shows the resync after the hole
+        eprintln!(
+            "stream-framer: {} bytes missing at offset {}",
+            gap.missing, gap.offset
+        );
+    }
+    report(&stream.data, json, preview)
+}
+
+fn transport_name(flow: &Flow) -> &'static str {
+    match flow.key.transport {
+        Transport::Tcp => "tcp",
+        Transport::Udp => "udp",
+    }
+}
+
+fn flow_text(index: usize, flow: &Flow) -> String {
+    let stream = flow.stream();
+    let mut line = format!(
+        "flow {index:<3} {} {} -> {}  {} packets  {} bytes",
+        transport_name(flow),
+        flow.key.src,
+        flow.key.dst,
+        flow.packets().len(),
+        stream.data.len()
+    );
+    if !stream.gaps.is_empty() {
+        let missing: usize = stream.gaps.iter().map(|gap| gap.missing).sum();
+        let _ = write!(
+            line,
+            "  {} gaps ({missing} bytes missing)",
+            stream.gaps.len()
+        );
+    }
+    line
+}
+
+fn flow_json(index: usize, flow: &Flow) -> String {
+    let stream = flow.stream();
+    let gaps: Vec<String> = stream
+        .gaps
+        .iter()
+        .map(|gap| format!(r#"{{"offset":{},"missing":{}}}"#, gap.offset, gap.missing))
+        .collect();
+    format!(
+        r#"{{"flow":{index},"transport":"{}","src":"{}","dst":"{}","packets":{},"bytes":{},"gaps":[{}]}}"#,
+        transport_name(flow),
+        flow.key.src,
+        flow.key.dst,
+        flow.packets().len(),
+        stream.data.len(),
+        gaps.join(",")
+    )
+}
+
+fn encode_command(args: &[String]) -> Result<ExitCode, CliError> {
+    let mut lines = false;
+    let mut output = None;
+    let mut inputs = vec![];
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--lines" => lines = true,
+            "-o" => output = Some(path_value(args.next(), "-o")?),
+            flag if flag.starts_with("--") => {
+                return Err(CliError::Usage(format!("unknown option [{flag}]")));
+            }
+            path => inputs.push(path),
+        }
+    }
+    if inputs.is_empty() {
+        inputs.push("-");
+    }
+
+    let mut messages = vec![];
+    for input in inputs {
+        let data = read_input(Some(input))?;
+        if lines {
+            messages.extend(split_lines(&data).map(<[u8]>::to_vec));
+        } else {
+            messages.push(data);
+        }
+    }
+
+    let mut out: BufWriter<Box<dyn Write>> = BufWriter::new(match output {
+        Some(path) => Box::new(fs::File::create(path)?),
+        None => Box::new(io::stdout().lock()),
+    });
+    for message in messages {
+        out.write_all(&message.prepend_frame()?)?;
+    }
+    out.flush()?;
+    Ok(ExitCode::SUCCESS)
+}
+
+fn split_command(args: &[String]) -> Result<ExitCode, CliError> {
+    let mut mtu = None;
+    let mut out_dir = PathBuf::from(".");
+    let mut input = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--mtu" => mtu = Some(number_value(args.next(), "--mtu")?),
+            "--out-dir" => out_dir = path_value(args.next(), "--out-dir")?,
+            flag if flag.starts_with("--") => {
+                return Err(CliError::Usage(format!("unknown option [{flag}]")));
+            }
+            path if input.is_none() => input = Some(path),
+            extra => return Err(CliError::Usage(format!("unexpected argument [{extra}]"))),
+        }
+    }
+    let Some(mtu) = mtu.filter(|mtu| *mtu > 0) else {
+        return Err(CliError::Usage("split needs a --mtu > 0".to_string()));
+    };
+
+    let stream = read_input(input)?;
+    fs::create_dir_all(&out_dir)?;
+
+    // the same cut as the test harness : fixed size chunks, the last one shorter
+    let packets = stream.len().div_ceil(mtu);
+    for (i, packet) in stream.chunks(mtu).enumerate() {
+        fs::write(out_dir.join(numbered_name("packet", i, packets)), packet)?;
+    }
+    println!(
+        "{packets} packets of at most {mtu} bytes written in {}",
+        out_dir.display()
+    );
+    Ok(ExitCode::SUCCESS)
+}
+
+fn join_command(args: &[String]) -> Result<ExitCode, CliError> {
+    let mut lines = false;
+    let mut out_dir = None;
+    let mut inputs = vec![];
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--lines" => lines = true,
+            "--out-dir" => out_dir = Some(path_value(args.next(), "--out-dir")?),
+            flag if flag.starts_with("--") => {
+                return Err(CliError::Usage(format!("unknown option [{flag}]")));
+            }
+            path => inputs.push(Path::new(path)),
+        }
+    }
+
+    let mut packets = vec![];
+    for input in inputs {
+        if input.is_dir() {
+            let mut entries = fs::read_dir(input)?
+                .map(|entry| entry.map(|entry| entry.path()))
+                .collect::<io::Result<Vec<_>>>()?;
+            entries.retain(|path| path.is_file());
+            entries.sort();
+            packets.extend(entries);
+        } else {
+            packets.push(input.to_path_buf());
+        }
+    }
+    if packets.is_empty() {
+        return Err(CliError::Usage("join needs packet files".to_string()));
+    }
+
+    let mut messages = vec![];
+    let mut state = ParserState::new();
+    for packet in &packets {
+        for parsed in state.parse(fs::read(packet)?)? {
+            match parsed {
+                ParsedStreamData::Completed(message) | ParsedStreamData::Tagged(_, message) => {
+                    messages.push(message);
+                }
+                ParsedStreamData::Control(control) => {
+                    eprintln!("control frame : {}", describe_control(&control));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    match &out_dir {
+        Some(out_dir) => {
+            fs::create_dir_all(out_dir)?;
+            for (i, message) in messages.iter().enumerate() {
+                fs::write(
+                    out_dir.join(numbered_name("message", i, messages.len())),
+                    message,
+                )?;
+            }
+        }
+        None => {
+            let mut out = BufWriter::new(io::stdout().lock());
+            for message in &messages {
+                out.write_all(message)?;
+                if lines {
+                    out.write_all(b"\n")?;
+                }
+            }
+            out.flush()?;
+        }
+    }
+
+    if !state.is_empty() {
+        eprintln!(
+            "stream-framer: the packets end inside a frame ({} body bytes of {} received)",
+            state.received().len(),
+            state
+                .announced_len()
+                .map_or_else(|| "?".to_string(), |len| len.to_string())
+        );
+        return Ok(ExitCode::from(1));
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn dissector_command(args: &[String]) -> Result<ExitCode, CliError> {
+    let mut output = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" => output = Some(path_value(args.next(), "-o")?),
+            other => return Err(CliError::Usage(format!("unexpected argument [{other}]"))),
+        }
+    }
+
+    let lua = wireshark_dissector();
+    match output {
+        Some(path) => fs::write(path, lua)?,
+        None => io::stdout().lock().write_all(lua.as_bytes())?,
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn number_value(value: Option<&String>, flag: &str) -> Result<usize, CliError> {
+    value
+        .and_then(|n| n.parse().ok())
+        .ok_or_else(|| CliError::Usage(format!("{flag} expects a number")))
+}
+
+fn path_value(value: Option<&String>, flag: &str) -> Result<PathBuf, CliError> {
+    value
+        .map(PathBuf::from)
+        .ok_or_else(|| CliError::Usage(format!("{flag} expects a path")))
+}
+
+/// `packet-000042.bin`, zero padded so that the name order is the stream order.
+fn numbered_name(prefix: &str, i: usize, count: usize) -> String {
+    let width = count.to_string().len().max(6);
+    format!("{prefix}-{i:0width$}.bin")
+}
+
+/// Lines without their `\n` (or `\r\n`), a trailing newline doesn't start an empty line.
+fn split_lines(data: &[u8]) -> impl Iterator<Item = &[u8]> {
+    let data = data.strip_suffix(b"\n").unwrap_or(data);
+    data.split(|byte| *byte == b'\n')
+        .filter(move |_| !data.is_empty())
+        .map(|line| line.strip_suffix(b"\r").unwrap_or(line))
+}
+
+fn read_input(path: Option<&str>) -> io::Result<Vec<u8>> {
+    match path {
+        None | Some("-") => {
+            let mut data = vec![];
+            io::stdin().lock().read_to_end(&mut data)?;
+            Ok(data)
+        }
+        Some(path) => fs::read(path),
+    }
+}
+
+/// (kind, detail, payload) of a parsed frame.
+fn describe(
+    parsed: &Result<ParsedStreamData, FrameError>,
+) -> (&'static str, Option<String>, Option<&[u8]>) {
+    match parsed {
+        Ok(ParsedStreamData::Completed(data)) => ("data", None, Some(data)),
+        Ok(ParsedStreamData::Tagged(tags, data)) => {
+            ("tagged", Some(describe_tags(tags)), Some(data))
+        }
+        Ok(ParsedStreamData::Control(control)) => {
+            ("control", Some(describe_control(control)), None)
+        }
+        Ok(ParsedStreamData::Chunk {
+            stream,
+            continued,
+            piece,
+        }) => {
+            let detail = format!(
+                "stream={stream}{}",
+                if *continued { " continued" } else { "" }
+            );
+            ("chunk", Some(detail), Some(piece))
+        }
+        Ok(ParsedStreamData::Incompleted(..) | ParsedStreamData::TruncatedHeader(_)) => {
+            ("invalid", Some("partial frame".to_string()), None)
+        }
+        Ok(_) => ("unknown", None, None),
+        Err(e) => ("invalid", Some(e.to_string()), None),
+    }
+}
+
+fn describe_tags(tags: &FrameTags) -> String {
+    let mut detail = vec![];
+    if let Some(id) = tags.correlation_id {
+        detail.push(format!("id={id}"));
+    }
+    if tags.reply {
+        detail.push("reply".to_string());
+    }
+    match &tags.topic {
+        Some(Topic::Name(name)) => detail.push(format!("topic={name}")),
+        Some(Topic::Id(id)) => detail.push(format!("topic_id={id:#010x}")),
+        None => {}
+    }
+    detail.join(" ")
+}
+
+fn describe_control(control: &ControlFrame) -> String {
+    match control {
+        ControlFrame::Ping(nonce) => format!("ping nonce={nonce}"),
+        ControlFrame::Pong(nonce) => format!("pong nonce={nonce}"),
+        ControlFrame::Close { code, reason } => format!("close code={code} reason={reason:?}"),
+        ControlFrame::Credit {
+            channel,
+            frames,
+            bytes,
+        } => format!("credit channel={channel} frames={frames} bytes={bytes}"),
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut out, byte| {
+        let _ = write!(out, "{byte:02x}");
+        out
+    })
+}
+
+/// Printable characters as is, control characters and invalid UTF-8 as dots.
+fn printable(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes)
+        .chars()
+        .map(|c| {
+            if c.is_control() || c == '\u{fffd}' {
+                '.'
+            } else {
+                c
+            }
+        })
+        .collect()
+}
+
+fn event_text(event: &InspectEvent, preview: usize) -> String {
+    let offset = event.offset();
+    match event {
+        InspectEvent::Frame {
+            header,
+            len,
+            parsed,
+            ..
+        } => {
+            let (kind, detail, payload) = describe(parsed);
+            let mut line = format!(
+                "{offset:08x}  frame    hdr {} {}  len {len}  {kind}",
+                hex(&header[..8]),
+                hex(&header[8..])
+            );
+            if let Some(detail) = detail {
+                line.push_str(&format!("  {detail}"));
+            }
+            if let Some(payload) = payload {
+                let shown = &payload[..payload.len().min(preview)];
+                let more = if shown.len() < payload.len() {
+                    "…"
+                } else {
+                    ""
+                };
+                line.push_str(&format!(
+                    "  {}{more}  |{}{more}|",
+                    hex(shown),
+                    printable(shown)
+                ));
+            }
+            line
+        }
+        InspectEvent::BadMagic { .. } => format!("{offset:08x}  bad magic, no header here"),
+        InspectEvent::Resync { skipped, .. } => {
+            format!("{offset:08x}  resync   next magic prefix after {skipped} skipped bytes")
+        }
+        InspectEvent::Unsynced { len, .. } => {
+            format!("{offset:08x}  unsynced no magic prefix in the last {len} bytes")
+        }
+        InspectEvent::TruncatedHeader { available, .. } => {
+            format!("{offset:08x}  truncated header at EOF, {available} bytes of {HDR_SIZE}")
+        }
+        InspectEvent::TruncatedBody { len, available, .. } => {
+            format!("{offset:08x}  truncated body at EOF, {available} bytes of {len}")
+        }
+    }
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if c.is_control() => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn event_json(event: &InspectEvent, preview: usize) -> String {
+    let offset = event.offset();
+    match event {
+        InspectEvent::Frame {
+            header,
+            len,
+            parsed,
+            ..
+        } => {
+            let (kind, detail, payload) = describe(parsed);
+            let mut line = format!(
+                r#"{{"event":"frame","offset":{offset},"header":"{}","len":{len},"kind":"{kind}""#,
+                hex(header)
+            );
+            if let Some(detail) = detail {
+                line.push_str(&format!(r#","detail":{}"#, json_string(&detail)));
+            }
+            if let Some(payload) = payload {
+                let shown = &payload[..payload.len().min(preview)];
+                line.push_str(&format!(
+                    r#","preview_hex":"{}","preview_utf8":{},"preview_truncated":{}"#,
+                    hex(shown),
+                    json_string(&String::from_utf8_lossy(shown)),
+                    shown.len() < payload.len()
+                ));
+            }
+            line.push('}');
+            line
+        }
+        InspectEvent::BadMagic { .. } => format!(r#"{{"event":"bad_magic","offset":{offset}}}"#),
+        InspectEvent::Resync { skipped, .. } => {
+            format!(r#"{{"event":"resync","offset":{offset},"skipped":{skipped}}}"#)
+        }
+        InspectEvent::Unsynced { len, .. } => {
+            format!(r#"{{"event":"unsynced","offset":{offset},"len":{len}}}"#)
+        }
+        InspectEvent::TruncatedHeader { available, .. } => {
+            format!(r#"{{"event":"truncated_header","offset":{offset},"available":{available}}}"#)
+        }
+        InspectEvent::TruncatedBody { len, available, .. } => format!(
+            r#"{{"event":"truncated_body","offset":{offset},"len":{len},"available":{available}}}"#
+        ),
+    }
+}
@@ -0,0 +1,219 @@
+//! `FrameWriter` and `FrameParser` impls for the buffer types other than `Vec<u8>`.
+//!
+//! The parser reads the packet as a borrowed `&[u8]` (which is a `FrameParser` too) : whatever
+//! the buffer type, the packet is never copied, only the completed bodies and the bytes of a
+//! frame still incomplete are. `&[u8]` can only be parsed : to frame a borrowed slice use
+//! `Cow::Borrowed` or [`encode_into`](crate::encode_into).
+
+use alloc::{borrow::Cow, boxed::Box, collections::VecDeque, vec::Vec};
+
+use crate::{
+    error::FrameError,
+    extended::PaddingPolicy,
+    stream_frame::{
+        AnnouncedLen, FrameParser, FrameWriter, HDR_SIZE, ParsedStreamData, encode_header,
+        frame_from_slices, padded_frame_from_slices,
+    },
+};
+
+macro_rules! parse_as_slice {
+    ($($buffer:ty),* $(,)?) => {
+        $(
+            impl FrameParser for $buffer {
+                fn parse_frame_header(
+                    self,
+                    last_incomplete_reception: Option<(AnnouncedLen, Vec<u8>)>,
+                    is_last_header_truncated: Option<Vec<u8>>,
+                ) -> Result<Vec<ParsedStreamData>, FrameError> {
+                    AsRef::<[u8]>::as_ref(&self)
+                        .parse_frame_header(last_incomplete_reception, is_last_header_truncated)
+                }
+                fn parse_frame_header_resyncing(
+                    self,
+                    last_incomplete_reception: Option<(AnnouncedLen, Vec<u8>)>,
+                    is_last_header_truncated: Option<Vec<u8>>,
+                ) -> Result<Vec<ParsedStreamData>, FrameError> {
+                    AsRef::<[u8]>::as_ref(&self).parse_frame_header_resyncing(
+                        last_incomplete_reception,
+                        is_last_header_truncated,
+                    )
+                }
+            }
+        )*
+    };
+}
+
+parse_as_slice!(Box<[u8]>, Cow<'_, [u8]>);
+
+impl FrameParser for VecDeque<u8> {
+    fn parse_frame_header(
+        mut self,
+        last_incomplete_reception: Option<(AnnouncedLen, Vec<u8>)>,
+        is_last_header_truncated: Option<Vec<u8>>,
+    ) -> Result<Vec<ParsedStreamData>, FrameError> {
+        // rotates the two halves in place, without allocating
+        self.make_contiguous()
+            .parse_frame_header(last_incomplete_reception, is_last_header_truncated)
+    }
+    fn parse_frame_header_resyncing(
+        mut self,
+        last_incomplete_reception: Option<(AnnouncedLen, Vec<u8>)>,
+        is_last_header_truncated: Option<Vec<u8>>,
+    ) -> Result<Vec<ParsedStreamData>, FrameError> {
+        self.make_contiguous()
+            .parse_frame_header_resyncing(last_incomplete_reception, is_last_header_truncated)
+    }
+}
+
+impl FrameWriter for Box<[u8]> {
+    fn prepend_frame_in_place(&mut self) -> Result<(), FrameError> {
+        *self = frame_from_slices(self, &[])?.into_boxed_slice();
+        Ok(())
+    }
+    fn prepend_frame(self) -> Result<Vec<u8>, FrameError> {
+        frame_from_slices(&self, &[])
+    }
+    fn prepend_frame_padded(self, policy: PaddingPolicy) -> Result<Vec<u8>, FrameError> {
+        padded_frame_from_slices(&self, &[], policy)
+    }
+}
+
+impl FrameWriter for Cow<'_, [u8]> {
+    fn prepend_frame_in_place(&mut self) -> Result<(), FrameError> {
+        match self {
+            Cow::Owned(owned) => owned.prepend_frame_in_place(),
+            Cow::Borrowed(borrowed) => {
+                *self = Cow::Owned(frame_from_slices(borrowed, &[])?);
+                Ok(())
+            }
+        }
+    }
+    fn prepend_frame(self) -> Result<Vec<u8>, FrameError> {
+        match self {
+            Cow::Owned(owned) => owned.prepend_frame(),
+            Cow::Borrowed(borrowed) => frame_from_slices(borrowed, &[]),
+        }
+    }
+    fn prepend_frame_padded(self, policy: PaddingPolicy) -> Result<Vec<u8>, FrameError> {
+        padded_frame_from_slices(&self, &[], policy)
+    }
+}
+
+impl FrameWriter for VecDeque<u8> {
+    fn prepend_frame_in_place(&mut self) -> Result<(), FrameError> {
+        let hdr = encode_header(self.len())?;
+
+        self.reserve(HDR_SIZE);
+        for byte in hdr.into_iter().rev() {
+            self.push_front(byte);
+        }
+        Ok(())
+    }
+    fn prepend_frame(self) -> Result<Vec<u8>, FrameError> {
+        let (first, second) = self.as_slices();
+        frame_from_slices(first, second)
+    }
+    fn prepend_frame_padded(self, policy: PaddingPolicy) -> Result<Vec<u8>, FrameError> {
+        let (first, second) = self.as_slices();
+        padded_frame_from_slices(first, second, policy)
+    }
+}
+
+#[cfg(feature = "bytes")]
+mod bytes_impls {
+    use alloc::vec::Vec;
+
+    use bytes::{BufMut, Bytes, BytesMut};
+
+    use crate::{
+        error::FrameError,
+        extended::PaddingPolicy,
+        stream_frame::{
+            AnnouncedLen, FrameParser, FrameWriter, ParsedStreamData, encode_header, encoded_len,
+            frame_from_slices, padded_frame_from_slices,
+        },
+    };
+
+    parse_as_slice!(Bytes, BytesMut);
+
+    impl FrameWriter for Bytes {
+        fn prepend_frame_in_place(&mut self) -> Result<(), FrameError> {
+            *self = Bytes::from(frame_from_slices(self, &[])?);
+            Ok(())
+        }
+        fn prepend_frame(self) -> Result<Vec<u8>, FrameError> {
+            frame_from_slices(&self, &[])
+        }
+        fn prepend_frame_padded(self, policy: PaddingPolicy) -> Result<Vec<u8>, FrameError> {
+            padded_frame_from_slices(&self, &[], policy)
+        }
+    }
+
+    impl FrameWriter for BytesMut {
+        fn prepend_frame_in_place(&mut self) -> Result<(), FrameError> {
+            let hdr = encode_header(self.len())?;
+
+            let mut frame = BytesMut::with_capacity(encoded_len(self.len()));
+            frame.put_slice(&hdr);
+            frame.put_slice(self);
+            *self = frame;
+            Ok(())
+        }
+        fn prepend_frame(self) -> Result<Vec<u8>, FrameError> {
+            frame_from_slices(&self, &[])
+        }
+        fn prepend_frame_padded(self, policy: PaddingPolicy) -> Result<Vec<u8>, FrameError> {
+            padded_frame_from_slices(&self, &[], policy)
+        }
+    }
+}
+
+#[cfg(feature = "smallvec")]
+mod smallvec_impls {
+    use alloc::vec::Vec;
+
+    use smallvec::{Array, SmallVec};
+
+    use crate::{
+        error::FrameError,
+        extended::PaddingPolicy,
+        stream_frame::{
+            AnnouncedLen, FrameParser, FrameWriter, ParsedStreamData, encode_header,
+            frame_from_slices, padded_frame_from_slices,
+        },
+    };
+
+    impl<A: Array<Item = u8>> FrameParser for SmallVec<A> {
+        fn parse_frame_header(
+            self,
+            last_incomplete_reception: Option<(AnnouncedLen, Vec<u8>)>,
+            is_last_header_truncated: Option<Vec<u8>>,
+        ) -> Result<Vec<ParsedStreamData>, FrameError> {
+            self.as_slice()
+                .parse_frame_header(last_incomplete_reception, is_last_header_truncated)
+        }
+        fn parse_frame_header_resyncing(
+            self,
+            last_incomplete_reception: Option<(AnnouncedLen, Vec<u8>)>,
+            is_last_header_truncated: Option<Vec<u8>>,
+        ) -> Result<Vec<ParsedStreamData>, FrameError> {
+            self.as_slice()
+                .parse_frame_header_resyncing(last_incomplete_reception, is_last_header_truncated)
+        }
+    }
+
+    impl<A: Array<Item = u8>> FrameWriter for SmallVec<A> {
+        fn prepend_frame_in_place(&mut self) -> Result<(), FrameError> {
+            let hdr = encode_header(self.len())?;
+
+            self.insert_from_slice(0, &hdr);
+            Ok(())
+        }
+        fn prepend_frame(self) -> Result<Vec<u8>, FrameError> {
+            frame_from_slices(&self, &[])
+        }
+        fn prepend_frame_padded(self, policy: PaddingPolicy) -> Result<Vec<u8>, FrameError> {
+            padded_frame_from_slices(&self, &[], policy)
+        }
+    }
+}
@@ -0,0 +1,78 @@
+//! Consistent Overhead Byte Stuffing.
+//!
+//! Rewrites bytes so that `0x00` never appears in them, for at most one extra byte per 254 (and
+//! one more). Each block starts with a code byte `n` : `n - 1` bytes without zero follow, then
+//! an implied `0x00` unless `n` is `0xFF` or the block is the last one.
+
+use alloc::{string::ToString, vec::Vec};
+
+use crate::error::FrameError;
+
+/// Longest run of non-zero bytes a block carries.
+const MAX_RUN: usize = 0xFE;
+
+/// Encoded len of `len` bytes, at most (reached when they hold no zero).
+pub(crate) const fn max_encoded_len(len: usize) -> usize {
+    len + len / MAX_RUN + 1
+}
+
+/// Append the encoding of `data` to `out`, it holds no zero.
+pub(crate) fn encode_to(data: &[u8], out: &mut Vec<u8>) {
+    out.reserve(max_encoded_len(data.len()));
+    let mut code_at = out.len();
+    out.push(0);
+    let mut run = 0;
+    for (i, &byte) in data.iter().enumerate() {
+        if byte == 0 {
+            out[code_at] = run as u8 + 1;
+            code_at = out.len();
+            out.push(0);
+            run = 0;
+            continue;
+        }
+        out.push(byte);
+        run += 1;
+        // a full block ending the data isn't followed by an empty one
+        if run == MAX_RUN && i + 1 < data.len() {
+            out[code_at] = MAX_RUN as u8 + 1;
+            code_at = out.len();
+            out.push(0);
+            run = 0;
+        }
+    }
+    out[code_at] = run as u8 + 1;
+}
+
+/// Reverse `encode_to`. Never panics.
+/// # Errors
+/// `ParsingError` if `encoded` is empty, holds a zero or ends in the middle of a block.
+pub(crate) fn decode(encoded: &[u8]) -> Result<Vec<u8>, FrameError> {
+    if encoded.is_empty() {
+        return Err(FrameError::ParsingError("empty COBS data".to_string()));
+    }
+    let mut data = Vec::with_capacity(encoded.len());
+    let mut rest = encoded;
+    while let Some((&code, after)) = rest.split_first() {
+        if code == 0 {
+            return Err(zero_inside());
+        }
+        let Some((run, after)) = after.split_at_checked(code as usize - 1) else {
+            return Err(FrameError::ParsingError(
+                "COBS block longer than the data left".to_string(),
+            ));
+        };
+        if run.contains(&0) {
+            return Err(zero_inside());
+        }
+        data.extend_from_slice(run);
+        if code as usize != MAX_RUN + 1 && !after.is_empty() {
+            data.push(0);
+        }
+        rest = after;
+    }
+    Ok(data)
+}
+
+fn zero_inside() -> FrameError {
+    FrameError::ParsingError("zero byte inside COBS data".to_string())
+}
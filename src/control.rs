@@ -0,0 +1,125 @@
+use alloc::{format, string::String, vec::Vec};
+
+use crate::{
+    error::FrameError,
+    extended::{KIND_CLOSE, KIND_CREDIT, KIND_PING, KIND_PONG, NO_TAGS, encode_extended},
+};
+
+/// Control frames, sent as extended frames and surfaced by the parser as
+/// `ParsedStreamData::Control`, apart from the data frames.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ControlFrame {
+    /// Liveness probe, the peer answers with a `Pong` carrying the same nonce.
+    Ping(u64),
+    Pong(u64),
+    /// The peer is closing the stream, no data frame follows. Payload : `code: u16 | UTF-8
+    /// reason`, see the `CLOSE_*` codes.
+    Close {
+        code: u16,
+        reason: String,
+    },
+    /// The receiver grants more frames and payload bytes on a channel (0 when the stream isn't
+    /// multiplexed), added to what is left. Payload : `channel: u32 | frames: u32 | bytes: u32`.
+    Credit {
+        channel: u32,
+        frames: u32,
+        bytes: u32,
+    },
+}
+
+impl ControlFrame {
+    /// Clean shutdown, nothing went wrong.
+    pub const CLOSE_NORMAL: u16 = 0;
+    /// The endpoint is going away (restart, migration...).
+    pub const CLOSE_GOING_AWAY: u16 = 1;
+    /// The peer broke the application protocol.
+    pub const CLOSE_PROTOCOL_ERROR: u16 = 2;
+    /// The endpoint hit an unexpected condition.
+    pub const CLOSE_INTERNAL_ERROR: u16 = 3;
+    /// Codes from here on are free for the application.
+    pub const CLOSE_APPLICATION_MIN: u16 = 0x1000;
+
+    /// Close frame with an optional UTF-8 message (can be empty).
+    pub fn close(code: u16, reason: impl Into<String>) -> Self {
+        ControlFrame::Close {
+            code,
+            reason: reason.into(),
+        }
+    }
+
+    /// Build the frame (header included), ready to be sent on the stream.
+    /// # Errors
+    /// This returns an error if a close reason is > to `MAX_BODY_LEN`.
+    pub fn encode(&self) -> Result<Vec<u8>, FrameError> {
+        match self {
+            ControlFrame::Ping(nonce) => encode_extended(KIND_PING, &NO_TAGS, &nonce.to_be_bytes()),
+            ControlFrame::Pong(nonce) => encode_extended(KIND_PONG, &NO_TAGS, &nonce.to_be_bytes()),
+            ControlFrame::Close { code, reason } => {
+                let mut payload = Vec::with_capacity(2 + reason.len());
+                payload.extend_from_slice(&code.to_be_bytes());
+                payload.extend_from_slice(reason.as_bytes());
+                encode_extended(KIND_CLOSE, &NO_TAGS, &payload)
+            }
+            ControlFrame::Credit {
+                channel,
+                frames,
+                bytes,
+            } => {
+                let mut payload = [0u8; 12];
+                payload[..4].copy_from_slice(&channel.to_be_bytes());
+                payload[4..8].copy_from_slice(&frames.to_be_bytes());
+                payload[8..].copy_from_slice(&bytes.to_be_bytes());
+                encode_extended(KIND_CREDIT, &NO_TAGS, &payload)
+            }
+        }
+    }
+
+    pub(crate) fn decode(kind: u8, payload: &[u8]) -> Result<Self, FrameError> {
+        match kind {
+            KIND_PING | KIND_PONG => {
+                let nonce: [u8; 8] = payload.try_into().map_err(|_e| {
+                    FrameError::ParsingError(format!(
+                        "ping/pong payload of [{}] bytes, 8 expected",
+                        payload.len()
+                    ))
+                })?;
+                let nonce = u64::from_be_bytes(nonce);
+                Ok(if kind == KIND_PING {
+                    ControlFrame::Ping(nonce)
+                } else {
+                    ControlFrame::Pong(nonce)
+                })
+            }
+            KIND_CLOSE => {
+                let [code_0, code_1, reason @ ..] = payload else {
+                    return Err(FrameError::ParsingError(
+                        "close payload without code".into(),
+                    ));
+                };
+                let reason = core::str::from_utf8(reason).map_err(|e| {
+                    FrameError::ParsingError(format!("close reason is not UTF-8 [{e}]"))
+                })?;
+                Ok(ControlFrame::Close {
+                    code: u16::from_be_bytes([*code_0, *code_1]),
+                    reason: reason.into(),
+                })
+            }
+            KIND_CREDIT => {
+                let [c0, c1, c2, c3, f0, f1, f2, f3, b0, b1, b2, b3] = payload else {
+                    return Err(FrameError::ParsingError(format!(
+                        "credit payload of [{}] bytes, 12 expected",
+                        payload.len()
+                    )));
+                };
+                Ok(ControlFrame::Credit {
+                    channel: u32::from_be_bytes([*c0, *c1, *c2, *c3]),
+                    frames: u32::from_be_bytes([*f0, *f1, *f2, *f3]),
+                    bytes: u32::from_be_bytes([*b0, *b1, *b2, *b3]),
+                })
+            }
+            _ => Err(FrameError::ParsingError(format!(
+                "unknown control frame kind [{kind}]"
+            ))),
+        }
+    }
+}
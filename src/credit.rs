@@ -0,0 +1,226 @@
+//! Credit based flow control.
+//!
+//! The receiver grants frames and payload bytes per channel with `ControlFrame::Credit`, the
+//! sender only frames a message when its channel has credit left. Both sides are sans-io : they
+//! produce and consume frames, sending them is up to the caller's transport.
+
+use alloc::{
+    collections::{BTreeMap, VecDeque},
+    vec,
+    vec::Vec,
+};
+
+use crate::{
+    control::ControlFrame,
+    error::FrameError,
+    stream_frame::{FrameWriter, HDR_SIZE},
+};
+
+/// What a [`CreditSender`] does with a message sent on a channel out of credit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OnNoCredit {
+    /// `send` returns `FrameError::NoCredit`.
+    Refuse,
+    /// The message waits until a credit frame releases it.
+    Queue,
+}
+
+#[derive(Debug, Default)]
+struct SenderChannel {
+    frames: u64,
+    bytes: u64,
+    // framed messages waiting for credit, in order
+    queue: VecDeque<Vec<u8>>,
+}
+
+impl SenderChannel {
+    fn take_credit(&mut self, payload_len: usize) -> bool {
+        if self.frames == 0 || self.bytes < payload_len as u64 {
+            return false;
+        }
+        self.frames -= 1;
+        self.bytes -= payload_len as u64;
+        true
+    }
+}
+
+/// Writer side : frames messages while their channel has credit.
+///
+/// ```rust
+/// use stream_framer::{ControlFrame, CreditSender, OnNoCredit};
+///
+/// let mut sender = CreditSender::new(OnNoCredit::Queue);
+///
+/// // no credit yet, the message is queued
+/// assert!(sender.send(0, b"hello".to_vec()).unwrap().is_none());
+///
+/// let credit = ControlFrame::Credit { channel: 0, frames: 10, bytes: 1024 };
+/// let released = sender.on_credit(&credit);
+/// assert_eq!(released.len(), 1);
+/// ```
+#[derive(Debug)]
+pub struct CreditSender {
+    channels: BTreeMap<u32, SenderChannel>,
+    on_no_credit: OnNoCredit,
+}
+
+impl CreditSender {
+    #[must_use]
+    pub fn new(on_no_credit: OnNoCredit) -> Self {
+        Self {
+            channels: BTreeMap::new(),
+            on_no_credit,
+        }
+    }
+
+    /// Frame `payload` if `channel` has a frame credit and enough byte credit left. Returns the
+    /// frame to write, or `None` if it was queued.
+    /// # Errors
+    /// `NoCredit` with `OnNoCredit::Refuse`, or the `FrameWriter` errors.
+    pub fn send(&mut self, channel: u32, payload: Vec<u8>) -> Result<Option<Vec<u8>>, FrameError> {
+        let state = self.channels.entry(channel).or_default();
+
+        // queued messages go first
+        if state.queue.is_empty() && state.take_credit(payload.len()) {
+            return payload.prepend_frame().map(Some);
+        }
+        match self.on_no_credit {
+            OnNoCredit::Refuse => Err(FrameError::NoCredit { channel }),
+            OnNoCredit::Queue => {
+                state.queue.push_back(payload.prepend_frame()?);
+                Ok(None)
+            }
+        }
+    }
+
+    /// Add the credit granted by the receiver, returns the queued frames it releases in order.
+    /// Other control frames are ignored.
+    #[must_use]
+    pub fn on_credit(&mut self, frame: &ControlFrame) -> Vec<Vec<u8>> {
+        let ControlFrame::Credit {
+            channel,
+            frames,
+            bytes,
+        } = frame
+        else {
+            return vec![];
+        };
+        let state = self.channels.entry(*channel).or_default();
+        state.frames = state.frames.saturating_add(u64::from(*frames));
+        state.bytes = state.bytes.saturating_add(u64::from(*bytes));
+
+        let mut released = vec![];
+        while let Some(frame) = state.queue.front() {
+            let payload_len = frame.len() - HDR_SIZE;
+            if !state.take_credit(payload_len) {
+                break;
+            }
+            released.extend(state.queue.pop_front());
+        }
+        released
+    }
+
+    /// (frames, bytes) credit left on `channel`.
+    #[must_use]
+    pub fn credit(&self, channel: u32) -> (u64, u64) {
+        self.channels
+            .get(&channel)
+            .map_or((0, 0), |state| (state.frames, state.bytes))
+    }
+
+    /// Messages waiting for credit on `channel`.
+    #[must_use]
+    pub fn queued(&self, channel: u32) -> usize {
+        self.channels
+            .get(&channel)
+            .map_or(0, |state| state.queue.len())
+    }
+}
+
+#[derive(Debug, Default)]
+struct ReceiverChannel {
+    // credit the sender still has, as seen from here
+    frames: u64,
+    bytes: u64,
+    // consumed since the last grant
+    consumed_frames: u32,
+    consumed_bytes: u32,
+}
+
+/// Reader side : grants a window of frames and bytes per channel, and renews it as the
+/// application consumes the messages.
+#[derive(Debug)]
+pub struct CreditReceiver {
+    window_frames: u32,
+    window_bytes: u32,
+    channels: BTreeMap<u32, ReceiverChannel>,
+}
+
+impl CreditReceiver {
+    /// Every channel gets a window of `window_frames` frames and `window_bytes` payload bytes.
+    /// A message larger than `window_bytes` can never be sent.
+    #[must_use]
+    pub fn new(window_frames: u32, window_bytes: u32) -> Self {
+        Self {
+            window_frames,
+            window_bytes,
+            channels: BTreeMap::new(),
+        }
+    }
+
+    /// The credit frame granting the whole window on `channel`, to send before the peer starts
+    /// sending on it.
+    pub fn open(&mut self, channel: u32) -> ControlFrame {
+        let state = self.channels.entry(channel).or_default();
+        state.frames += u64::from(self.window_frames);
+        state.bytes += u64::from(self.window_bytes);
+
+        ControlFrame::Credit {
+            channel,
+            frames: self.window_frames,
+            bytes: self.window_bytes,
+        }
+    }
+
+    /// Account a message of `payload_len` bytes received on `channel`.
+    /// # Errors
+    /// `CreditExceeded` if the sender went over the credit it was granted.
+    pub fn on_received(&mut self, channel: u32, payload_len: usize) -> Result<(), FrameError> {
+        let Some(state) = self.channels.get_mut(&channel) else {
+            return Err(FrameError::CreditExceeded { channel });
+        };
+        if state.frames == 0 || state.bytes < payload_len as u64 {
+            return Err(FrameError::CreditExceeded { channel });
+        }
+        state.frames -= 1;
+        state.bytes -= payload_len as u64;
+        Ok(())
+    }
+
+    /// The application is done with a message of `payload_len` bytes. Returns the credit frame
+    /// to send once half of the window was consumed.
+    pub fn on_consumed(&mut self, channel: u32, payload_len: usize) -> Option<ControlFrame> {
+        let state = self.channels.get_mut(&channel)?;
+        state.consumed_frames = state.consumed_frames.saturating_add(1);
+        state.consumed_bytes = state
+            .consumed_bytes
+            .saturating_add(u32::try_from(payload_len).unwrap_or(u32::MAX));
+
+        if state.consumed_frames < self.window_frames.div_ceil(2)
+            && state.consumed_bytes < self.window_bytes.div_ceil(2)
+        {
+            return None;
+        }
+        let (frames, bytes) = (state.consumed_frames, state.consumed_bytes);
+        state.consumed_frames = 0;
+        state.consumed_bytes = 0;
+        state.frames += u64::from(frames);
+        state.bytes += u64::from(bytes);
+
+        Some(ControlFrame::Credit {
+            channel,
+            frames,
+            bytes,
+        })
+    }
+}
@@ -0,0 +1,448 @@
+//! Wireshark dissector generation.
+//!
+//! The Lua script is written from the constants of `stream_frame` and the tables of `spec`, the
+//! same definitions the encoder and the parser use.
+
+use alloc::{format, string::String};
+use core::fmt::{Result, Write};
+
+use crate::{
+    extended::{EXT_HDR_SIZE, FLAG_MASKED, FLAG_PADDED, KIND_DATA, KIND_ESCAPED},
+    spec::{FLAGS, Field, FieldType, KINDS, known_flags},
+    stream_frame::{EXTENDED_FLAG, HDR_SIZE, MAGIC_PREFIX, MAX_BODY_LEN},
+};
+
+const PROTOCOL: &str = "stream_framer";
+
+/// A Wireshark Lua dissector for the frames of this crate.
+///
+/// Copy it to the Wireshark personal Lua plugins folder (Help > About Wireshark > Folders) and
+/// the TCP and UDP payloads starting with `MAGIC_PREFIX` are shown decoded: header, extension
+/// header, optional fields and control payloads. Other streams can be decoded with "Decode As".
+///
+/// ```rust
+/// let lua = stream_framer::wireshark_dissector();
+/// assert!(lua.contains(r#"Proto("stream_framer", "Stream Framer")"#));
+/// ```
+#[must_use]
+pub fn wireshark_dissector() -> String {
+    let mut lua = String::new();
+    // writing to a String never fails
+    let _ = write_dissector(&mut lua);
+    lua
+}
+
+fn write_dissector(lua: &mut String) -> Result {
+    let magic: String = MAGIC_PREFIX
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect();
+
+    writeln!(lua, "-- Wireshark dissector for stream_framer frames.")?;
+    writeln!(lua, "--")?;
+    writeln!(
+        lua,
+        "-- Generated from the crate's format definition (`stream-framer dissector`), regenerate"
+    )?;
+    writeln!(lua, "-- it rather than editing it.")?;
+    writeln!(lua)?;
+    writeln!(lua, r#"local proto = Proto("{PROTOCOL}", "Stream Framer")"#)?;
+    writeln!(lua)?;
+    writeln!(lua, r#"local MAGIC = ByteArray.new("{magic}")"#)?;
+    writeln!(lua, "local HDR_SIZE = {HDR_SIZE}")?;
+    writeln!(lua, "local EXTENDED_FLAG = {EXTENDED_FLAG:#010x}")?;
+    writeln!(lua, "local MAX_BODY_LEN = {MAX_BODY_LEN:#010x}")?;
+    writeln!(lua, "local EXT_HDR_SIZE = {EXT_HDR_SIZE}")?;
+    writeln!(lua, "local KNOWN_FLAGS = {:#04x}", known_flags())?;
+    writeln!(lua)?;
+
+    writeln!(lua, "local kinds = {{")?;
+    for kind in KINDS {
+        writeln!(lua, r#"    [{}] = "{}","#, kind.kind, kind.name)?;
+    }
+    writeln!(lua, "}}")?;
+    for kind in KINDS {
+        for field in kind.payload {
+            if let FieldType::Code(values) = field.ty {
+                writeln!(lua)?;
+                writeln!(lua, "local {}_{}_names = {{", kind.name, field.name)?;
+                for (value, name) in values {
+                    writeln!(lua, r#"    [{value}] = "{name}","#)?;
+                }
+                writeln!(lua, "}}")?;
+            }
+        }
+    }
+    writeln!(lua)?;
+
+    writeln!(lua, "local f = proto.fields")?;
+    writeln!(
+        lua,
+        r#"f.magic = ProtoField.bytes("{PROTOCOL}.magic", "Magic prefix")"#
+    )?;
+    writeln!(
+        lua,
+        r#"f.extended = ProtoField.bool("{PROTOCOL}.extended", "Extended", 32, nil, EXTENDED_FLAG)"#
+    )?;
+    writeln!(
+        lua,
+        r#"f.body_len = ProtoField.uint32("{PROTOCOL}.body_len", "Body length", base.DEC, nil, MAX_BODY_LEN)"#
+    )?;
+    writeln!(
+        lua,
+        r#"f.kind = ProtoField.uint8("{PROTOCOL}.kind", "Kind", base.DEC, kinds)"#
+    )?;
+    writeln!(
+        lua,
+        r#"f.flags = ProtoField.uint8("{PROTOCOL}.flags", "Flags", base.HEX)"#
+    )?;
+    for flag in FLAGS {
+        writeln!(
+            lua,
+            r#"f.flag_{} = ProtoField.bool("{PROTOCOL}.flags.{}", "{}", 8, nil, {:#04x})"#,
+            flag.name, flag.name, flag.label, flag.bit
+        )?;
+    }
+    for field in FLAGS.iter().filter_map(|flag| flag.field.as_ref()) {
+        declare_field(lua, field.name, field.name, field)?;
+    }
+    writeln!(
+        lua,
+        r#"f.masked_body = ProtoField.bytes("{PROTOCOL}.masked_body", "Masked fields and payload")"#
+    )?;
+    writeln!(
+        lua,
+        r#"f.padding = ProtoField.bytes("{PROTOCOL}.padding", "Padding")"#
+    )?;
+    for kind in KINDS {
+        for field in kind.payload {
+            declare_field(
+                lua,
+                &format!("{}_{}", kind.name, field.name),
+                &format!("{}.{}", kind.name, field.name),
+                field,
+            )?;
+        }
+    }
+    writeln!(lua)?;
+    writeln!(
+        lua,
+        r#"local malformed = ProtoExpert.new("{PROTOCOL}.malformed", "Malformed frame", expert.group.MALFORMED, expert.severity.ERROR)"#
+    )?;
+    writeln!(
+        lua,
+        r#"local truncated = ProtoExpert.new("{PROTOCOL}.truncated", "Truncated frame", expert.group.MALFORMED, expert.severity.WARN)"#
+    )?;
+    writeln!(lua, "proto.experts = {{ malformed, truncated }}")?;
+    writeln!(lua)?;
+
+    write_dissect_frame(lua)?;
+    write_entry_points(lua)
+}
+
+fn declare_field(lua: &mut String, var: &str, abbrev: &str, field: &Field) -> Result {
+    let label = field.label;
+    let abbrev = format!("{PROTOCOL}.{abbrev}");
+    match field.ty {
+        FieldType::U32 => writeln!(
+            lua,
+            r#"f.{var} = ProtoField.uint32("{abbrev}", "{label}", base.DEC)"#
+        ),
+        FieldType::U32Hex => writeln!(
+            lua,
+            r#"f.{var} = ProtoField.uint32("{abbrev}", "{label}", base.HEX)"#
+        ),
+        FieldType::U64 => writeln!(
+            lua,
+            r#"f.{var} = ProtoField.uint64("{abbrev}", "{label}", base.DEC)"#
+        ),
+        FieldType::Code(_) => writeln!(
+            lua,
+            r#"f.{var} = ProtoField.uint16("{abbrev}", "{label}", base.DEC, {var}_names)"#
+        ),
+        FieldType::ShortString => {
+            writeln!(
+                lua,
+                r#"f.{var}_len = ProtoField.uint8("{abbrev}_len", "{label} length", base.DEC)"#
+            )?;
+            writeln!(lua, r#"f.{var} = ProtoField.string("{abbrev}", "{label}")"#)
+        }
+        FieldType::Text => writeln!(lua, r#"f.{var} = ProtoField.string("{abbrev}", "{label}")"#),
+        FieldType::Bytes => writeln!(lua, r#"f.{var} = ProtoField.bytes("{abbrev}", "{label}")"#),
+    }
+}
+
+/// Lua adding `field` at `offset` to the `frame` tree and moving `offset` past it.
+fn dissect_field(lua: &mut String, indent: &str, var: &str, field: &Field) -> Result {
+    match field.ty.size() {
+        Some(size) => {
+            writeln!(lua, "{indent}frame:add(f.{var}, tvb(offset, {size}))")?;
+            writeln!(lua, "{indent}offset = offset + {size}")
+        }
+        None if matches!(field.ty, FieldType::ShortString) => {
+            writeln!(lua, "{indent}local len = tvb(offset, 1):uint()")?;
+            writeln!(lua, "{indent}frame:add(f.{var}_len, tvb(offset, 1))")?;
+            writeln!(lua, "{indent}frame:add(f.{var}, tvb(offset + 1, len))")?;
+            writeln!(lua, "{indent}offset = offset + 1 + len")
+        }
+        None => {
+            writeln!(lua, "{indent}if offset < payload_end then")?;
+            writeln!(
+                lua,
+                "{indent}    frame:add(f.{var}, tvb(offset, payload_end - offset))"
+            )?;
+            writeln!(lua, "{indent}end")?;
+            writeln!(lua, "{indent}offset = payload_end")
+        }
+    }
+}
+
+fn write_dissect_frame(lua: &mut String) -> Result {
+    writeln!(
+        lua,
+        "-- Dissect the frame filling `tvb` (header included), returns its length."
+    )?;
+    writeln!(lua, "local function dissect_frame(tvb, pinfo, tree)")?;
+    writeln!(lua, "    local len_field = tvb(8, 4):uint()")?;
+    writeln!(
+        lua,
+        "    local frame_len = HDR_SIZE + bit.band(len_field, MAX_BODY_LEN)"
+    )?;
+    writeln!(lua, "    local frame = tree:add(proto, tvb(0, frame_len))")?;
+    writeln!(lua, "    frame:add(f.magic, tvb(0, 8))")?;
+    writeln!(lua, "    if tvb(0, 8):bytes() ~= MAGIC then")?;
+    writeln!(
+        lua,
+        r#"        frame:add_proto_expert_info(malformed, "No magic prefix")"#
+    )?;
+    writeln!(lua, "        return frame_len")?;
+    writeln!(lua, "    end")?;
+    writeln!(lua, "    frame:add(f.extended, tvb(8, 4))")?;
+    writeln!(lua, "    frame:add(f.body_len, tvb(8, 4))")?;
+    writeln!(lua)?;
+    writeln!(lua, "    local kind = {KIND_DATA}")?;
+    writeln!(lua, "    local masked = false")?;
+    writeln!(lua, "    local payload_end = frame_len")?;
+    writeln!(lua, "    local offset = HDR_SIZE")?;
+    writeln!(lua, "    if bit.band(len_field, EXTENDED_FLAG) ~= 0 then")?;
+    writeln!(lua, "        if frame_len < HDR_SIZE + EXT_HDR_SIZE then")?;
+    writeln!(
+        lua,
+        r#"            frame:add_proto_expert_info(malformed, "Shorter than its extension header")"#
+    )?;
+    writeln!(lua, "            return frame_len")?;
+    writeln!(lua, "        end")?;
+    writeln!(lua, "        kind = tvb(offset, 1):uint()")?;
+    writeln!(lua, "        frame:add(f.kind, tvb(offset, 1))")?;
+    writeln!(lua, "    end")?;
+    writeln!(
+        lua,
+        "    -- an escaped frame has no flags, its body is another extended body, COBS encoded"
+    )?;
+    writeln!(lua, "    if kind == {KIND_ESCAPED} then")?;
+    writeln!(lua, "        offset = offset + 1")?;
+    writeln!(
+        lua,
+        "    elseif bit.band(len_field, EXTENDED_FLAG) ~= 0 then"
+    )?;
+    writeln!(lua, "        local flags = tvb(offset + 1, 1):uint()")?;
+    writeln!(
+        lua,
+        "        local flags_item = frame:add(f.flags, tvb(offset + 1, 1))"
+    )?;
+    for flag in FLAGS {
+        writeln!(
+            lua,
+            "        flags_item:add(f.flag_{}, tvb(offset + 1, 1))",
+            flag.name
+        )?;
+    }
+    writeln!(
+        lua,
+        "        if bit.band(flags, bit.bnot(KNOWN_FLAGS)) ~= 0 then"
+    )?;
+    writeln!(
+        lua,
+        r#"            flags_item:add_proto_expert_info(malformed, "Unknown flags")"#
+    )?;
+    writeln!(lua, "        end")?;
+    writeln!(lua, "        offset = offset + EXT_HDR_SIZE")?;
+    writeln!(
+        lua,
+        "        masked = bit.band(flags, {FLAG_MASKED:#04x}) ~= 0"
+    )?;
+    writeln!(
+        lua,
+        "        -- the masking key comes first, the other fields are masked with the payload"
+    )?;
+    writeln!(lua, "        if masked then")?;
+    for flag in FLAGS.iter().filter(|flag| flag.bit == FLAG_MASKED) {
+        if let Some(field) = &flag.field {
+            dissect_field(lua, "            ", field.name, field)?;
+        }
+    }
+    writeln!(lua, "        else")?;
+    writeln!(
+        lua,
+        "            -- the fields come in the order of the flag bits"
+    )?;
+    for flag in FLAGS.iter().filter(|flag| flag.bit != FLAG_MASKED) {
+        let Some(field) = &flag.field else {
+            continue;
+        };
+        writeln!(
+            lua,
+            "            if bit.band(flags, {:#04x}) ~= 0 then",
+            flag.bit
+        )?;
+        dissect_field(lua, "                ", field.name, field)?;
+        writeln!(lua, "            end")?;
+    }
+    writeln!(
+        lua,
+        "            -- the payload length is the last field, the padding follows the payload"
+    )?;
+    writeln!(
+        lua,
+        "            if bit.band(flags, {FLAG_PADDED:#04x}) ~= 0 then"
+    )?;
+    writeln!(
+        lua,
+        "                payload_end = math.min(offset + tvb(offset - 4, 4):uint(), frame_len)"
+    )?;
+    writeln!(lua, "                if payload_end < frame_len then")?;
+    writeln!(
+        lua,
+        "                    frame:add(f.padding, tvb(payload_end, frame_len - payload_end))"
+    )?;
+    writeln!(lua, "                end")?;
+    writeln!(lua, "            end")?;
+    writeln!(lua, "        end")?;
+    writeln!(lua, "    end")?;
+    writeln!(lua)?;
+    writeln!(lua, r#"    local name = kinds[kind] or "unknown kind""#)?;
+    writeln!(lua, r#"    frame:append_text(", " .. name)"#)?;
+    writeln!(lua, r#"    pinfo.cols.info:append(name .. " ")"#)?;
+    // the fields and the payload of a masked frame are XORed with the masking key, shown as is
+    writeln!(lua, "    if masked then")?;
+    writeln!(lua, "        if offset < payload_end then")?;
+    writeln!(
+        lua,
+        "            frame:add(f.masked_body, tvb(offset, payload_end - offset))"
+    )?;
+    writeln!(lua, "        end")?;
+    writeln!(lua, "        offset = payload_end")?;
+    for kind in KINDS {
+        writeln!(lua, "    elseif kind == {} then", kind.kind)?;
+        for field in kind.payload {
+            dissect_field(
+                lua,
+                "        ",
+                &format!("{}_{}", kind.name, field.name),
+                field,
+            )?;
+        }
+    }
+    writeln!(lua, "    else")?;
+    writeln!(
+        lua,
+        r#"        frame:add_proto_expert_info(malformed, "Unknown kind")"#
+    )?;
+    writeln!(lua, "        offset = payload_end")?;
+    writeln!(lua, "    end")?;
+    writeln!(lua, "    if offset ~= payload_end then")?;
+    writeln!(
+        lua,
+        r#"        frame:add_proto_expert_info(malformed, "Payload length doesn't match its kind")"#
+    )?;
+    writeln!(lua, "    end")?;
+    writeln!(lua, "    return frame_len")?;
+    writeln!(lua, "end")?;
+    writeln!(lua)
+}
+
+fn write_entry_points(lua: &mut String) -> Result {
+    writeln!(lua, "local function frame_len(tvb, pinfo, offset)")?;
+    writeln!(
+        lua,
+        "    return HDR_SIZE + bit.band(tvb(offset + 8, 4):uint(), MAX_BODY_LEN)"
+    )?;
+    writeln!(lua, "end")?;
+    writeln!(lua)?;
+    writeln!(lua, "local function set_columns(pinfo)")?;
+    writeln!(lua, r#"    pinfo.cols.protocol = "STREAM_FRAMER""#)?;
+    writeln!(lua, "    pinfo.cols.info:clear()")?;
+    writeln!(lua, "end")?;
+    writeln!(lua)?;
+    writeln!(lua, "-- TCP, frames are reassembled across segments")?;
+    writeln!(lua, "function proto.dissector(tvb, pinfo, tree)")?;
+    writeln!(lua, "    set_columns(pinfo)")?;
+    writeln!(
+        lua,
+        "    dissect_tcp_pdus(tvb, tree, HDR_SIZE, frame_len, dissect_frame)"
+    )?;
+    writeln!(lua, "end")?;
+    writeln!(lua)?;
+    writeln!(
+        lua,
+        "-- UDP, a frame continued in the next datagram is only flagged"
+    )?;
+    writeln!(lua, "local function dissect_datagram(tvb, pinfo, tree)")?;
+    writeln!(lua, "    set_columns(pinfo)")?;
+    writeln!(lua, "    local offset = 0")?;
+    writeln!(lua, "    while offset < tvb:len() do")?;
+    writeln!(lua, "        local rest = tvb:len() - offset")?;
+    writeln!(
+        lua,
+        "        if rest < HDR_SIZE or frame_len(tvb, pinfo, offset) > rest then"
+    )?;
+    writeln!(lua, "            local item = tree:add(proto, tvb(offset))")?;
+    writeln!(
+        lua,
+        r#"            item:add_proto_expert_info(truncated, "Frame continued in the next datagram")"#
+    )?;
+    writeln!(lua, "            break")?;
+    writeln!(lua, "        end")?;
+    writeln!(
+        lua,
+        "        offset = offset + dissect_frame(tvb(offset):tvb(), pinfo, tree)"
+    )?;
+    writeln!(lua, "    end")?;
+    writeln!(lua, "    return tvb:len()")?;
+    writeln!(lua, "end")?;
+    writeln!(lua)?;
+    writeln!(lua, "local function starts_with_magic(tvb)")?;
+    writeln!(
+        lua,
+        "    return tvb:len() >= HDR_SIZE and tvb(0, 8):bytes() == MAGIC"
+    )?;
+    writeln!(lua, "end")?;
+    writeln!(lua)?;
+    writeln!(lua, "local function heuristic_tcp(tvb, pinfo, tree)")?;
+    writeln!(lua, "    if not starts_with_magic(tvb) then")?;
+    writeln!(lua, "        return false")?;
+    writeln!(lua, "    end")?;
+    writeln!(
+        lua,
+        "    -- later segments of the connection don't necessarily start with a frame"
+    )?;
+    writeln!(lua, "    pinfo.conversation = proto")?;
+    writeln!(lua, "    proto.dissector(tvb, pinfo, tree)")?;
+    writeln!(lua, "    return true")?;
+    writeln!(lua, "end")?;
+    writeln!(lua)?;
+    writeln!(lua, "local function heuristic_udp(tvb, pinfo, tree)")?;
+    writeln!(lua, "    if not starts_with_magic(tvb) then")?;
+    writeln!(lua, "        return false")?;
+    writeln!(lua, "    end")?;
+    writeln!(lua, "    dissect_datagram(tvb, pinfo, tree)")?;
+    writeln!(lua, "    return true")?;
+    writeln!(lua, "end")?;
+    writeln!(lua)?;
+    writeln!(lua, r#"proto:register_heuristic("tcp", heuristic_tcp)"#)?;
+    writeln!(lua, r#"proto:register_heuristic("udp", heuristic_udp)"#)?;
+    writeln!(
+        lua,
+        r#"DissectorTable.get("tcp.port"):add_for_decode_as(proto)"#
+    )
+}
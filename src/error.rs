@@ -1,15 +1,32 @@
-use std::{
-    error::Error,
-    fmt::{self, Display},
-};
+use alloc::string::String;
+use core::fmt::{self, Display};
 
 #[derive(Debug)]
 pub enum FrameError {
+    #[cfg(feature = "std")]
     Io(std::io::Error),
     ParsingError(String),
+    /// The partial state handed to the parser is not one it could have handed out.
+    InvalidState(String),
     TypeCapacity(String),
     TypeConversionFailure(String),
     MessageEmpty,
+    FrameTooLarge {
+        len: usize,
+        capacity: usize,
+    },
+    BufferTooSmall {
+        needed: usize,
+    },
+    StreamClosed,
+    NoCredit {
+        channel: u32,
+    },
+    CreditExceeded {
+        channel: u32,
+    },
+    Timeout,
+    Cancelled,
 }
 
 impl Display for FrameError {
@@ -18,6 +35,9 @@ impl Display for FrameError {
             FrameError::ParsingError(err) => {
                 write!(f, "Parsing error : [{err:?}]")
             }
+            FrameError::InvalidState(err) => {
+                write!(f, "InvalidState error : [{err:?}]")
+            }
             FrameError::TypeCapacity(err) => {
                 write!(f, "TypeCapacity error : [{err:?}]")
             }
@@ -27,6 +47,31 @@ impl Display for FrameError {
             FrameError::MessageEmpty => {
                 write!(f, " Error ! Message empty ! ")
             }
+            FrameError::FrameTooLarge { len, capacity } => {
+                write!(
+                    f,
+                    "Frame too large : [{len}] bytes for a [{capacity}] bytes buffer"
+                )
+            }
+            FrameError::BufferTooSmall { needed } => {
+                write!(f, "Buffer too small : [{needed}] bytes needed")
+            }
+            FrameError::StreamClosed => {
+                write!(f, " Error ! Data frame received after a close frame ! ")
+            }
+            FrameError::NoCredit { channel } => {
+                write!(f, "No credit left on channel [{channel}]")
+            }
+            FrameError::CreditExceeded { channel } => {
+                write!(f, "Peer sent more than its credit on channel [{channel}]")
+            }
+            FrameError::Timeout => {
+                write!(f, " Error ! No reply before the timeout ! ")
+            }
+            FrameError::Cancelled => {
+                write!(f, " Error ! Request cancelled ! ")
+            }
+            #[cfg(feature = "std")]
             FrameError::Io(e) => {
                 write!(f, "Io error : [{e:?}]")
             }
@@ -34,8 +79,9 @@ impl Display for FrameError {
     }
 }
 
-impl Error for FrameError {}
+impl core::error::Error for FrameError {}
 
+#[cfg(feature = "std")]
 impl From<std::io::Error> for FrameError {
     fn from(value: std::io::Error) -> Self {
         FrameError::Io(value)
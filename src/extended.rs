@@ -0,0 +1,739 @@
+//! Extended frames.
+//!
+//! A header whose length field has `EXTENDED_FLAG` set announces a body starting with a 2 bytes
+//! extension header, then the optional fields announced by the flags (in the order of the flag
+//! bits, but for the masking key which comes first), followed by the payload :
+//!
+//! ```text
+//! MAGIC_PREFIX (8) | EXTENDED_FLAG | body len (4) | kind (1) | flags (1) | [fields] | payload
+//! ```
+//!
+//! | flag            | field                                                   |
+//! |-----------------|---------------------------------------------------------|
+//! | `0x01` id       | correlation id, `u64` big endian                        |
+//! | `0x02` reply    | no field, the frame answers the request with the same id |
+//! | `0x04` topic    | topic name, `u8` len followed by the UTF-8 name          |
+//! | `0x08` topic id | hashed topic name, `u32` big endian                      |
+//! | `0x10` masked   | masking key (4 bytes), what follows it is XORed with it  |
+//! | `0x20` padded   | payload len, `u32` big endian, zeros follow the payload  |
+//! | `0x40` continued | no field, more chunks of the frame follow (chunks only) |
+//!
+//! Plain frames (written by `FrameWriter`) never set the flag, so their wire format is
+//! unchanged.
+//!
+//! ## Escaped frames
+//! [`escape_frame`] wraps a frame in an escaped one, whose body is the COBS encoding (see the
+//! `cobs` module) of the wrapped extended body, a plain frame counting as a data frame without
+//! flags :
+//!
+//! ```text
+//! MAGIC_PREFIX (8) | EXTENDED_FLAG | body len (4) | kind = 5 (1) | COBS(kind | flags | fields | payload)
+//! ```
+//!
+//! The escaped bytes hold no `0x00`, the first byte of `MAGIC_PREFIX`, and the kind byte can't
+//! complete a magic prefix starting in the length field : in a stream of escaped frames, any
+//! magic prefix found is the start of a frame, so resyncing after corruption or loss is always
+//! right. The parser unwraps them, they come out as the frame they wrap.
+//!
+//! ## Masked frames
+//! [`mask_frame`] XORs everything after the extension header with a 4 bytes key, WebSocket
+//! style, so that repeated fields and payloads don't show on the wire :
+//!
+//! ```text
+//! MAGIC_PREFIX (8) | EXTENDED_FLAG | body len (4) | kind (1) | flags (1) | key (4) | XOR(fields | payload)
+//! ```
+//!
+//! The key comes before the other fields, whatever the flag order. The magic prefix, the length
+//! field, the kind and the flags stay in clear : the parser needs them to find and unmask the
+//! frame. The parser unmasks transparently : the key is not one of the `FrameTags`, a masked
+//! frame comes out as the frame it masks.
+//!
+//! ## Padded frames
+//! [`pad_frame`] rounds the body of a frame up to a size bucket (see [`PaddingPolicy`]) with
+//! zeros after the payload, its real len carried as the last field. The parser strips the
+//! padding, a padded frame comes out as the frame it pads.
+//!
+//! ## Chunked frames
+//! [`chunk_frame`] cuts a whole frame (header included) into chunk frames, so that other frames
+//! can be sent between them :
+//!
+//! ```text
+//! MAGIC_PREFIX (8) | EXTENDED_FLAG | body len (4) | kind = 6 (1) | flags (1) | stream (4) | piece
+//! ```
+//!
+//! The chunks of a frame share a `stream` id, all but the last one set the continued flag. The
+//! parser hands them out as `ParsedStreamData::Chunk`, `ParserState` reassembles them and
+//! hands out the frame they carry.
+
+use alloc::{format, string::ToString, vec::Vec};
+
+use crate::{
+    cobs,
+    control::ControlFrame,
+    error::FrameError,
+    spec::{self, FieldType},
+    stream_frame::{
+        EXTENDED_FLAG, HDR_SIZE, MAX_BODY_LEN, ParsedStreamData, body_len, decode_header,
+        encode_header, encode_header_field, encoded_len, is_extended,
+    },
+    topic::Topic,
+};
+
+/// kind + flags
+pub(crate) const EXT_HDR_SIZE: usize = 2;
+
+pub(crate) const KIND_DATA: u8 = 0;
+pub(crate) const KIND_PING: u8 = 1;
+pub(crate) const KIND_PONG: u8 = 2;
+pub(crate) const KIND_CLOSE: u8 = 3;
+pub(crate) const KIND_CREDIT: u8 = 4;
+/// Not followed by flags but by the COBS encoding of another extended body.
+pub(crate) const KIND_ESCAPED: u8 = 5;
+/// A piece of another frame, see [`chunk_frame`].
+pub(crate) const KIND_CHUNK: u8 = 6;
+
+pub(crate) const FLAG_CORRELATION_ID: u8 = 0x01;
+pub(crate) const FLAG_REPLY: u8 = 0x02;
+pub(crate) const FLAG_TOPIC_NAME: u8 = 0x04;
+pub(crate) const FLAG_TOPIC_ID: u8 = 0x08;
+pub(crate) const FLAG_MASKED: u8 = 0x10;
+pub(crate) const FLAG_PADDED: u8 = 0x20;
+pub(crate) const FLAG_CONTINUED: u8 = 0x40;
+const KNOWN_FLAGS: u8 = spec::known_flags();
+
+/// Optional header fields of a data frame, surfaced with its payload as
+/// `ParsedStreamData::Tagged`.
+///
+/// ```rust
+/// use stream_framer::{FrameParser, FrameTags, ParsedStreamData};
+///
+/// let tags = FrameTags { correlation_id: Some(7), ..Default::default() };
+/// let frame = tags.encode(b"request").unwrap();
+///
+/// match frame.parse_frame_header(None, None).unwrap().pop() {
+///     Some(ParsedStreamData::Tagged(parsed_tags, payload)) => {
+///         assert_eq!(parsed_tags, tags);
+///         assert_eq!(payload, b"request");
+///     }
+///     _ => panic!("expected a tagged frame"),
+/// }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FrameTags {
+    /// Matches a reply with its request.
+    pub correlation_id: Option<u64>,
+    /// The frame answers the request carrying the same `correlation_id`.
+    pub reply: bool,
+    /// Routes the frame, see `TopicRouter`.
+    pub topic: Option<Topic>,
+}
+
+pub(crate) const NO_TAGS: FrameTags = FrameTags {
+    correlation_id: None,
+    reply: false,
+    topic: None,
+};
+
+impl FrameTags {
+    /// True if no field is set, such a frame is sent as a plain frame.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        *self == NO_TAGS
+    }
+
+    /// Build the data frame (header included) carrying these tags and `payload`.
+    /// # Errors
+    /// `TypeCapacity` if the frame is > to `MAX_BODY_LEN`, `ParsingError` for a reply without
+    /// correlation id or an invalid topic name (empty, longer than `MAX_TOPIC_LEN` or holding a
+    /// wildcard).
+    pub fn encode(&self, payload: &[u8]) -> Result<Vec<u8>, FrameError> {
+        encode_extended(KIND_DATA, self, payload)
+    }
+
+    fn flags(&self) -> u8 {
+        let mut flags = 0;
+        if self.correlation_id.is_some() {
+            flags |= FLAG_CORRELATION_ID;
+        }
+        if self.reply {
+            flags |= FLAG_REPLY;
+        }
+        match self.topic {
+            Some(Topic::Name(_)) => flags |= FLAG_TOPIC_NAME,
+            Some(Topic::Id(_)) => flags |= FLAG_TOPIC_ID,
+            None => {}
+        }
+        flags
+    }
+}
+
+/// Build a whole extended frame (header included).
+pub(crate) fn encode_extended(
+    kind: u8,
+    tags: &FrameTags,
+    payload: &[u8],
+) -> Result<Vec<u8>, FrameError> {
+    if tags.reply && tags.correlation_id.is_none() {
+        return Err(FrameError::ParsingError(
+            "reply frame without correlation id".to_string(),
+        ));
+    }
+    let mut fields = Vec::new();
+    if let Some(id) = tags.correlation_id {
+        fields.extend_from_slice(&id.to_be_bytes());
+    }
+    if let Some(topic) = &tags.topic {
+        topic.validate()?;
+        match topic {
+            Topic::Name(name) => {
+                fields.push(name.len() as u8);
+                fields.extend_from_slice(name.as_bytes());
+            }
+            Topic::Id(id) => fields.extend_from_slice(&id.to_be_bytes()),
+        }
+    }
+
+    let body_len = EXT_HDR_SIZE + fields.len() + payload.len();
+    if body_len > MAX_BODY_LEN {
+        return Err(FrameError::TypeCapacity(
+            "Failed to get packet len (is > to MAX_BODY_LEN)".to_string(),
+        ));
+    }
+
+    let mut frame = Vec::with_capacity(HDR_SIZE + body_len);
+    frame.extend_from_slice(&encode_header_field(body_len as u32 | EXTENDED_FLAG));
+    frame.push(kind);
+    frame.push(tags.flags());
+    frame.extend_from_slice(&fields);
+    frame.extend_from_slice(payload);
+    Ok(frame)
+}
+
+/// Escape `frame`, a whole plain or extended frame (header included) : the returned frame holds
+/// no `MAGIC_PREFIX` but its own, and parses as `frame` does.
+///
+/// ```rust
+/// use stream_framer::{FrameParser, FrameWriter, MAGIC_PREFIX, ParsedStreamData, escape_frame};
+///
+/// let mut message = b"payload ".to_vec();
+/// message.extend_from_slice(&MAGIC_PREFIX);
+/// let escaped = escape_frame(&message.clone().prepend_frame().unwrap()).unwrap();
+/// assert!(!escaped[1..].windows(MAGIC_PREFIX.len()).any(|window| window == MAGIC_PREFIX));
+///
+/// match escaped.parse_frame_header(None, None).unwrap().pop() {
+///     Some(ParsedStreamData::Completed(payload)) => assert_eq!(payload, message),
+///     _ => panic!("expected a data frame"),
+/// }
+/// ```
+/// # Errors
+/// `ParsingError` if `frame` isn't exactly one frame or is already escaped, `TypeCapacity` if
+/// the escaped body is > to `MAX_BODY_LEN`.
+pub fn escape_frame(frame: &[u8]) -> Result<Vec<u8>, FrameError> {
+    let (len_field, body) = split_frame(frame)?;
+    let mut escaped = Vec::with_capacity(HDR_SIZE + 1 + cobs::max_encoded_len(2 + body.len()));
+    escaped.extend_from_slice(&[0; HDR_SIZE]);
+    escaped.push(KIND_ESCAPED);
+    if is_extended(len_field) {
+        if body.first() == Some(&KIND_ESCAPED) {
+            return Err(FrameError::ParsingError(
+                "frame already escaped".to_string(),
+            ));
+        }
+        cobs::encode_to(body, &mut escaped);
+    } else {
+        let mut data_body = Vec::with_capacity(EXT_HDR_SIZE + body.len());
+        data_body.extend_from_slice(&[KIND_DATA, 0]);
+        data_body.extend_from_slice(body);
+        cobs::encode_to(&data_body, &mut escaped);
+    }
+    finish_extended(escaped)
+}
+
+/// Mask the fields and the payload of `frame`, a whole plain or extended frame (header
+/// included), with `key`. It parses as `frame` does.
+///
+/// ```rust
+/// use stream_framer::{FrameParser, FrameTags, ParsedStreamData, Topic, mask_frame};
+///
+/// let tags = FrameTags {
+///     topic: Some(Topic::Name("sensors/room".to_string())),
+///     ..Default::default()
+/// };
+/// let frame = tags.encode(b"same old payload").unwrap();
+/// let masked = mask_frame(&frame, [0x37, 0xfa, 0x21, 0x3d]).unwrap();
+/// assert!(!masked.windows(8).any(|window| window == b"same old"));
+/// assert!(!masked.windows(7).any(|window| window == b"sensors"));
+///
+/// match masked.parse_frame_header(None, None).unwrap().pop() {
+///     Some(ParsedStreamData::Tagged(parsed, payload)) => {
+///         assert_eq!(parsed, tags);
+///         assert_eq!(payload, b"same old payload");
+///     }
+///     _ => panic!("expected a tagged frame"),
+/// }
+/// ```
+/// Use a new key for every frame, see `random_masking_key`. Mask before escaping, an escaped
+/// frame can't be masked. The padding of a padded frame is masked with its payload.
+///
+/// The fields (correlation id, topic, payload len...) are masked with the payload, the key
+/// itself comes first. Every frame still starts with the fixed `MAGIC_PREFIX` in clear,
+/// followed by the length field, the kind and the flags in clear too : masking doesn't keep a
+/// middlebox from matching on the prefix. The parser syncs on it and needs the flags to find
+/// the key, hiding them needs another framing (see `FrameFormat`).
+/// # Errors
+/// `ParsingError` if `frame` isn't exactly one frame, is malformed, already masked or escaped,
+/// `TypeCapacity` if the masked body is > to `MAX_BODY_LEN`.
+pub fn mask_frame(frame: &[u8], key: [u8; 4]) -> Result<Vec<u8>, FrameError> {
+    // the key first, so that the fields after it are masked too
+    let (mut masked, key_at, _) = with_field(frame, FLAG_MASKED, 0, key.len(), "masked")?;
+    masked[key_at..key_at + key.len()].copy_from_slice(&key);
+    apply_mask(&mut masked[key_at + key.len()..], key);
+    finish_extended(masked)
+}
+
+/// A masking key for [`mask_frame`], different at each call. Not cryptographically random,
+/// masking is not a security measure.
+#[cfg(feature = "std")]
+#[must_use]
+pub fn random_masking_key() -> [u8; 4] {
+    use std::hash::{BuildHasher, Hasher};
+
+    // each RandomState is seeded differently, from the OS randomness once per thread
+    let mut hasher = std::collections::hash_map::RandomState::new().build_hasher();
+    hasher.write_u8(0);
+    (hasher.finish() as u32).to_be_bytes()
+}
+
+/// XOR `data` with `key`, repeated. Masks and unmasks.
+fn apply_mask(data: &mut [u8], key: [u8; 4]) {
+    for (byte, key) in data.iter_mut().zip(key.iter().cycle()) {
+        *byte ^= key;
+    }
+}
+
+/// The body sizes [`pad_frame`] rounds up to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PaddingPolicy {
+    /// Frames are sent as they are.
+    #[default]
+    None,
+    /// Bodies are padded to the next power of two.
+    PowerOfTwo,
+    /// Bodies are padded to the next multiple of this many bytes, 0 and 1 don't pad.
+    Block(usize),
+}
+
+impl PaddingPolicy {
+    /// The padded len of a body of `len` bytes, `None` if it overflows `usize`.
+    #[must_use]
+    pub fn padded_len(&self, len: usize) -> Option<usize> {
+        match *self {
+            Self::None | Self::Block(0 | 1) => Some(len),
+            Self::PowerOfTwo => len.checked_next_power_of_two(),
+            Self::Block(block) => len.div_ceil(block).checked_mul(block),
+        }
+    }
+}
+
+/// Pad the body of `frame`, a whole plain or extended frame (header included), to the size
+/// `policy` rounds it up to. It parses as `frame` does, the real payload len is carried as its
+/// last field and the padding (zeros) is stripped by the parser.
+///
+/// ```rust
+/// use stream_framer::{FrameParser, FrameWriter, PaddingPolicy, ParsedStreamData, pad_frame};
+///
+/// let frame = b"short reply".to_vec().prepend_frame().unwrap();
+/// let padded = pad_frame(&frame, PaddingPolicy::Block(64)).unwrap();
+/// assert_eq!(padded.len(), stream_framer::HDR_SIZE + 64);
+///
+/// match padded.parse_frame_header(None, None).unwrap().pop() {
+///     Some(ParsedStreamData::Completed(payload)) => assert_eq!(payload, b"short reply"),
+///     _ => panic!("expected a data frame"),
+/// }
+/// ```
+/// The whole body is rounded up, extension header and fields included. `PaddingPolicy::None`
+/// returns `frame` as is. Pad before masking (the padding is then masked too) and escaping.
+/// # Errors
+/// `ParsingError` if `frame` isn't exactly one frame, is malformed, already padded, masked or
+/// escaped, `TypeCapacity` if the padded body is > to `MAX_BODY_LEN`.
+pub fn pad_frame(frame: &[u8], policy: PaddingPolicy) -> Result<Vec<u8>, FrameError> {
+    if policy == PaddingPolicy::None {
+        split_frame(frame)?;
+        return Ok(frame.to_vec());
+    }
+    let (mut padded, len_at, payload_at) =
+        with_field(frame, FLAG_PADDED, FLAG_PADDED - 1, 4, "padded")?;
+    let payload_len = padded.len() - payload_at;
+    let Some(target) = padded_body_len(policy, padded.len() - HDR_SIZE) else {
+        return Err(padding_overflow());
+    };
+    // `payload_len` is at most `MAX_BODY_LEN`
+    padded[len_at..len_at + 4].copy_from_slice(&(payload_len as u32).to_be_bytes());
+    padded.resize(HDR_SIZE + target, 0);
+    finish_extended(padded)
+}
+
+/// Size of the chunk stream id.
+const CHUNK_STREAM_SIZE: usize = 4;
+/// Largest piece of a frame a chunk carries.
+pub(crate) const MAX_CHUNK_LEN: usize = MAX_BODY_LEN - EXT_HDR_SIZE - CHUNK_STREAM_SIZE;
+
+/// Cut `frame`, a whole plain or extended frame (header included), into chunk frames carrying
+/// at most `chunk_size` bytes of it each (at least 1), tagged with `stream`.
+///
+/// ```rust
+/// use stream_framer::{FrameWriter, ParsedStreamData, ParserState, chunk_frame};
+///
+/// let frame = vec![7; 100].prepend_frame().unwrap();
+/// let chunks = chunk_frame(&frame, 1, 40).unwrap();
+/// assert_eq!(chunks.len(), 3);
+///
+/// // an urgent frame goes between two chunks
+/// let mut stream = chunks[0].clone();
+/// stream.extend(b"urgent".to_vec().prepend_frame().unwrap());
+/// stream.extend(chunks[1..].concat());
+///
+/// let mut state = ParserState::new();
+/// match &state.parse(stream).unwrap()[..] {
+///     [ParsedStreamData::Completed(urgent), ParsedStreamData::Completed(bulk)] => {
+///         assert_eq!(urgent, b"urgent");
+///         assert_eq!(bulk, &vec![7; 100]);
+///     }
+///     _ => panic!("expected two data frames"),
+/// }
+/// ```
+/// The chunks of the frames being sent at the same time must use different streams. Chunk
+/// before masking, padding or escaping the chunks, a chunk can't be chunked again.
+/// # Errors
+/// `ParsingError` if `frame` isn't exactly one frame or is a chunk.
+pub fn chunk_frame(
+    frame: &[u8],
+    stream: u32,
+    chunk_size: usize,
+) -> Result<Vec<Vec<u8>>, FrameError> {
+    let (len_field, body) = split_frame(frame)?;
+    if is_extended(len_field) && body.first() == Some(&KIND_CHUNK) {
+        return Err(FrameError::ParsingError(
+            "a chunk can't be chunked".to_string(),
+        ));
+    }
+    let chunk_size = chunk_size.clamp(1, MAX_CHUNK_LEN);
+    let count = frame.len().div_ceil(chunk_size);
+    Ok(frame
+        .chunks(chunk_size)
+        .enumerate()
+        .map(|(i, piece)| encode_chunk(stream, i + 1 < count, piece))
+        .collect())
+}
+
+/// The chunk frame carrying `piece`, at most `MAX_CHUNK_LEN` bytes.
+pub(crate) fn encode_chunk(stream: u32, continued: bool, piece: &[u8]) -> Vec<u8> {
+    let body_len = EXT_HDR_SIZE + CHUNK_STREAM_SIZE + piece.len();
+    let flags = if continued { FLAG_CONTINUED } else { 0 };
+
+    let mut chunk = Vec::with_capacity(HDR_SIZE + body_len);
+    // `piece` is at most `MAX_CHUNK_LEN` long, `body_len` at most `MAX_BODY_LEN`
+    chunk.extend_from_slice(&encode_header_field(body_len as u32 | EXTENDED_FLAG));
+    chunk.extend_from_slice(&[KIND_CHUNK, flags]);
+    chunk.extend_from_slice(&stream.to_be_bytes());
+    chunk.extend_from_slice(piece);
+    chunk
+}
+
+/// Append the data frame carrying the payload stored in two parts (e.g. the two halves of a
+/// ring buffer) to `out`, padded by `policy` : what `pad_frame` makes of the plain frame, in
+/// one pass.
+pub(crate) fn encode_padded(
+    first: &[u8],
+    second: &[u8],
+    policy: PaddingPolicy,
+    out: &mut Vec<u8>,
+) -> Result<(), FrameError> {
+    let payload_len = first.len() + second.len();
+    if policy == PaddingPolicy::None {
+        out.reserve(encoded_len(payload_len));
+        out.extend_from_slice(&encode_header(payload_len)?);
+        out.extend_from_slice(first);
+        out.extend_from_slice(second);
+        return Ok(());
+    }
+
+    let Some(target) = (EXT_HDR_SIZE + 4)
+        .checked_add(payload_len)
+        .and_then(|body_len| padded_body_len(policy, body_len))
+    else {
+        return Err(padding_overflow());
+    };
+    let frame_end = out.len() + HDR_SIZE + target;
+    out.reserve(HDR_SIZE + target);
+    // both fit, `payload_len` < `target` <= `MAX_BODY_LEN`
+    out.extend_from_slice(&encode_header_field(target as u32 | EXTENDED_FLAG));
+    out.extend_from_slice(&[KIND_DATA, FLAG_PADDED]);
+    out.extend_from_slice(&(payload_len as u32).to_be_bytes());
+    out.extend_from_slice(first);
+    out.extend_from_slice(second);
+    out.resize(frame_end, 0);
+    Ok(())
+}
+
+/// The len `policy` pads a body of `len` bytes to, `None` over `MAX_BODY_LEN`.
+fn padded_body_len(policy: PaddingPolicy, len: usize) -> Option<usize> {
+    policy
+        .padded_len(len)
+        .filter(|&target| target <= MAX_BODY_LEN)
+}
+
+fn padding_overflow() -> FrameError {
+    FrameError::TypeCapacity(
+        "Failed to pad the frame (the padded body is > to MAX_BODY_LEN)".to_string(),
+    )
+}
+
+/// `frame` as an extended frame with `flag` set and its field, `field_len` zeros, inserted
+/// after the fields of the `after` flags. Returns it with its header left blank, the offsets of
+/// the field and of the payload.
+fn with_field(
+    frame: &[u8],
+    flag: u8,
+    after: u8,
+    field_len: usize,
+    what: &str,
+) -> Result<(Vec<u8>, usize, usize), FrameError> {
+    let (len_field, body) = split_frame(frame)?;
+    let (kind, flags, fields, payload) = if is_extended(len_field) {
+        let Some((&[kind, flags], rest)) = body.split_first_chunk::<EXT_HDR_SIZE>() else {
+            return Err(FrameError::ParsingError(
+                "extended frame shorter than its extension header".to_string(),
+            ));
+        };
+        if kind == KIND_ESCAPED {
+            return Err(FrameError::ParsingError(format!(
+                "an escaped frame can't be {what}"
+            )));
+        }
+        if flags & flag != 0 {
+            return Err(FrameError::ParsingError(format!("frame already {what}")));
+        }
+        // its fields can't be read without unmasking them
+        if flags & FLAG_MASKED != 0 {
+            return Err(FrameError::ParsingError(format!(
+                "a masked frame can't be {what}"
+            )));
+        }
+        let Some((fields, payload)) = fields_len(flags, rest).map(|len| rest.split_at(len)) else {
+            return Err(FrameError::ParsingError(
+                "extended frame too short for its fields".to_string(),
+            ));
+        };
+        (kind, flags, fields, payload)
+    } else {
+        (KIND_DATA, 0, &[][..], body)
+    };
+    // the fields of the `after` flags come first
+    let Some(before) = fields_len(flags & after, fields) else {
+        return Err(FrameError::ParsingError(
+            "extended frame too short for its fields".to_string(),
+        ));
+    };
+
+    let mut extended =
+        Vec::with_capacity(HDR_SIZE + EXT_HDR_SIZE + fields.len() + field_len + payload.len());
+    extended.extend_from_slice(&[0; HDR_SIZE]);
+    extended.extend_from_slice(&[kind, flags | flag]);
+    extended.extend_from_slice(&fields[..before]);
+    let field_at = extended.len();
+    extended.resize(field_at + field_len, 0);
+    extended.extend_from_slice(&fields[before..]);
+    let payload_at = extended.len();
+    extended.extend_from_slice(payload);
+    Ok((extended, field_at, payload_at))
+}
+
+/// Write the header of `frame`, an extended frame whose header is left blank.
+fn finish_extended(mut frame: Vec<u8>) -> Result<Vec<u8>, FrameError> {
+    let body_len = frame.len() - HDR_SIZE;
+    if body_len > MAX_BODY_LEN {
+        return Err(FrameError::TypeCapacity(
+            "Failed to get packet len (is > to MAX_BODY_LEN)".to_string(),
+        ));
+    }
+    frame[..HDR_SIZE].copy_from_slice(&encode_header_field(body_len as u32 | EXTENDED_FLAG));
+    Ok(frame)
+}
+
+/// The header length field and the body of `frame`, checked to be exactly one frame.
+fn split_frame(frame: &[u8]) -> Result<(usize, &[u8]), FrameError> {
+    let Some((len_field, body)) = frame
+        .split_first_chunk::<HDR_SIZE>()
+        .and_then(|(hdr, body)| Some((decode_header(hdr)?, body)))
+    else {
+        return Err(FrameError::ParsingError("no frame header".to_string()));
+    };
+    if body.len() != body_len(len_field) {
+        return Err(FrameError::ParsingError(format!(
+            "frame body of {} bytes announced as {}",
+            body.len(),
+            body_len(len_field)
+        )));
+    }
+    Ok((len_field, body))
+}
+
+/// Len of the fields `flags` announce at the start of `fields`, `None` if they don't fit.
+fn fields_len(flags: u8, fields: &[u8]) -> Option<usize> {
+    let mut len = 0;
+    for flag in spec::FLAGS.iter().filter(|flag| flags & flag.bit != 0) {
+        len += match &flag.field {
+            None => 0,
+            Some(field) => match field.ty {
+                FieldType::ShortString => 1 + usize::from(*fields.get(len)?),
+                ref ty => ty.size()?,
+            },
+        };
+    }
+    (len <= fields.len()).then_some(len)
+}
+
+/// Decode the body of an extended frame.
+pub(crate) fn decode_extended(body: Vec<u8>) -> Result<ParsedStreamData, FrameError> {
+    match body.split_first() {
+        Some((&KIND_ESCAPED, escaped)) => {
+            let body = cobs::decode(escaped)?;
+            if body.first() == Some(&KIND_ESCAPED) {
+                return Err(FrameError::ParsingError(
+                    "escaped frame inside an escaped frame".to_string(),
+                ));
+            }
+            decode_unescaped(body)
+        }
+        _ => decode_unescaped(body),
+    }
+}
+
+fn decode_unescaped(mut body: Vec<u8>) -> Result<ParsedStreamData, FrameError> {
+    let [kind, flags, ..] = body[..] else {
+        return Err(FrameError::ParsingError(
+            "extended frame shorter than its extension header".to_string(),
+        ));
+    };
+    if flags & !KNOWN_FLAGS != 0 {
+        return Err(FrameError::ParsingError(format!(
+            "unknown extended frame flags [{flags:#04x}]"
+        )));
+    }
+    if flags & FLAG_CONTINUED != 0 && kind != KIND_CHUNK {
+        return Err(FrameError::ParsingError(format!(
+            "continued flag on a frame kind [{kind}] that isn't a chunk"
+        )));
+    }
+
+    // the masking key comes first, the rest of the body is masked
+    let mut fields_at = EXT_HDR_SIZE;
+    if flags & FLAG_MASKED != 0 {
+        let Some(&key) = body[EXT_HDR_SIZE..].first_chunk::<4>() else {
+            return Err(FrameError::ParsingError(
+                "extended frame too short for its masking key".to_string(),
+            ));
+        };
+        fields_at += key.len();
+        apply_mask(&mut body[fields_at..], key);
+    }
+
+    let mut fields = &body[fields_at..];
+    let mut tags = FrameTags::default();
+    if flags & FLAG_CORRELATION_ID != 0 {
+        let Some((id, rest)) = fields.split_first_chunk::<8>() else {
+            return Err(FrameError::ParsingError(
+                "extended frame too short for its correlation id".to_string(),
+            ));
+        };
+        tags.correlation_id = Some(u64::from_be_bytes(*id));
+        fields = rest;
+    }
+    if flags & FLAG_REPLY != 0 {
+        if tags.correlation_id.is_none() {
+            return Err(FrameError::ParsingError(
+                "reply frame without correlation id".to_string(),
+            ));
+        }
+        tags.reply = true;
+    }
+    if flags & (FLAG_TOPIC_NAME | FLAG_TOPIC_ID) == FLAG_TOPIC_NAME | FLAG_TOPIC_ID {
+        return Err(FrameError::ParsingError(
+            "extended frame with two topics".to_string(),
+        ));
+    }
+    if flags & FLAG_TOPIC_NAME != 0 {
+        let Some((name, rest)) = fields
+            .split_first()
+            .and_then(|(len, rest)| rest.split_at_checked(*len as usize))
+        else {
+            return Err(FrameError::ParsingError(
+                "extended frame too short for its topic".to_string(),
+            ));
+        };
+        let name = core::str::from_utf8(name)
+            .map_err(|e| FrameError::ParsingError(format!("topic name : {e}")))?;
+        let topic = Topic::Name(name.to_string());
+        topic.validate()?;
+        tags.topic = Some(topic);
+        fields = rest;
+    }
+    if flags & FLAG_TOPIC_ID != 0 {
+        let Some((id, rest)) = fields.split_first_chunk::<4>() else {
+            return Err(FrameError::ParsingError(
+                "extended frame too short for its topic id".to_string(),
+            ));
+        };
+        tags.topic = Some(Topic::Id(u32::from_be_bytes(*id)));
+        fields = rest;
+    }
+    let mut payload_len = None;
+    if flags & FLAG_PADDED != 0 {
+        let Some((len, rest)) = fields.split_first_chunk::<4>() else {
+            return Err(FrameError::ParsingError(
+                "extended frame too short for its payload len".to_string(),
+            ));
+        };
+        payload_len = Some(u32::from_be_bytes(*len) as usize);
+        fields = rest;
+    }
+    // the payload stays in the body allocation
+    let fields_end = body.len() - fields.len();
+    body.drain(..fields_end);
+    let mut payload = body;
+    if let Some(len) = payload_len {
+        if len > payload.len() {
+            return Err(FrameError::ParsingError(format!(
+                "payload len [{len}] over the {} bytes left",
+                payload.len()
+            )));
+        }
+        payload.truncate(len);
+    }
+
+    match kind {
+        KIND_DATA if tags.is_empty() => Ok(ParsedStreamData::Completed(payload)),
+        KIND_DATA => Ok(ParsedStreamData::Tagged(tags, payload)),
+        _ if !tags.is_empty() => Err(FrameError::ParsingError(format!(
+            "control frame kind [{kind}] with header fields"
+        ))),
+        KIND_CHUNK => {
+            let Some(stream) = payload.first_chunk::<CHUNK_STREAM_SIZE>() else {
+                return Err(FrameError::ParsingError(
+                    "chunk frame too short for its stream".to_string(),
+                ));
+            };
+            let stream = u32::from_be_bytes(*stream);
+            payload.drain(..CHUNK_STREAM_SIZE);
+            Ok(ParsedStreamData::Chunk {
+                stream,
+                continued: flags & FLAG_CONTINUED != 0,
+                piece: payload,
+            })
+        }
+        _ => Ok(ParsedStreamData::Control(ControlFrame::decode(
+            kind, &payload,
+        )?)),
+    }
+}
@@ -0,0 +1,407 @@
+//! Framing schemes behind one encoder/decoder trait.
+//!
+//! [`MagicLength`] is the crate's own magic prefix + length scheme, [`Cobs`], [`Slip`] and
+//! [`NewlineDelimited`] delimit frames with a byte that never appears inside them.
+
+use alloc::{format, string::ToString, vec::Vec};
+
+use crate::{
+    cobs,
+    error::FrameError,
+    extended::{PaddingPolicy, encode_padded},
+    parser_state::ParserState,
+    stream_frame::{MAX_BODY_LEN, ParsedStreamData},
+};
+
+/// A way to cut a byte stream in messages.
+///
+/// Decoding is incremental : the bytes of the stream are fed as they come, cut anywhere. A
+/// gateway bridging two formats only needs this trait :
+///
+/// ```rust
+/// use stream_framer::{Cobs, FrameFormat, Slip};
+///
+/// fn bridge(
+///     from: &mut dyn FrameFormat,
+///     to: &dyn FrameFormat,
+///     bytes: &[u8],
+///     out: &mut Vec<u8>,
+/// ) -> Result<(), stream_framer::FrameError> {
+///     let mut messages = Vec::new();
+///     let decoded = from.decode(bytes, &mut |message| messages.push(message));
+///     for message in messages {
+///         to.encode(&message, out)?;
+///     }
+///     decoded
+/// }
+///
+/// let mut slip_stream = Vec::new();
+/// Slip::new().encode(b"\xc0 from a SLIP peer", &mut slip_stream).unwrap();
+///
+/// let mut cobs_stream = Vec::new();
+/// bridge(&mut Slip::new(), &Cobs::new(), &slip_stream, &mut cobs_stream).unwrap();
+///
+/// let mut received = Vec::new();
+/// Cobs::new().decode(&cobs_stream, &mut |message| received.push(message)).unwrap();
+/// assert_eq!(received, [b"\xc0 from a SLIP peer"]);
+/// ```
+pub trait FrameFormat {
+    /// Append `message`, framed, to `out`.
+    /// # Errors
+    /// When the format can't carry `message`, see each implementation.
+    fn encode(&self, message: &[u8], out: &mut Vec<u8>) -> Result<(), FrameError>;
+
+    /// Feed the next bytes of the stream, calling `on_message` with each message they complete.
+    /// The rest is kept for the next call. Never panics, whatever the bytes.
+    /// # Errors
+    /// When the stream breaks the format, see each implementation.
+    fn decode(
+        &mut self,
+        bytes: &[u8],
+        on_message: &mut dyn FnMut(Vec<u8>),
+    ) -> Result<(), FrameError>;
+}
+
+/// The crate's own format : `MAGIC_PREFIX`, length, body, decoded with a [`ParserState`].
+///
+/// The messages are the payloads of the data frames, tagged ones included (their tags are
+/// dropped). Control frames are not messages and are skipped, use [`ParserState`] directly to
+/// see them.
+///
+/// Messages are sent as plain frames, or as padded frames (see `pad_frame`) with a padding
+/// policy. Decoding strips the padding whatever the policy.
+#[derive(Debug, Clone, Default)]
+pub struct MagicLength {
+    state: ParserState,
+    padding: PaddingPolicy,
+}
+
+impl MagicLength {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Pad the frames written with `policy`.
+    ///
+    /// ```rust
+    /// use stream_framer::{FrameFormat, MagicLength, PaddingPolicy};
+    ///
+    /// let writer = MagicLength::with_padding(PaddingPolicy::PowerOfTwo);
+    /// let mut short = Vec::new();
+    /// let mut long = Vec::new();
+    /// writer.encode(b"yes", &mut short).unwrap();
+    /// writer.encode(b"no thanks", &mut long).unwrap();
+    /// assert_eq!(short.len(), long.len());
+    /// ```
+    #[must_use]
+    pub fn with_padding(policy: PaddingPolicy) -> Self {
+        Self {
+            padding: policy,
+            ..Self::default()
+        }
+    }
+}
+
+impl FrameFormat for MagicLength {
+    /// # Errors
+    /// `TypeCapacity` if `message`, padded, is longer than `MAX_BODY_LEN`.
+    fn encode(&self, message: &[u8], out: &mut Vec<u8>) -> Result<(), FrameError> {
+        encode_padded(message, &[], self.padding, out)
+    }
+
+    /// # Errors
+    /// Forwards the [`ParserState::parse`] errors, the pending frame is dropped.
+    fn decode(
+        &mut self,
+        bytes: &[u8],
+        on_message: &mut dyn FnMut(Vec<u8>),
+    ) -> Result<(), FrameError> {
+        for parsed in self.state.parse(bytes)? {
+            match parsed {
+                ParsedStreamData::Completed(message) | ParsedStreamData::Tagged(_, message) => {
+                    on_message(message);
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Reassembly shared by the delimited formats.
+#[derive(Debug, Clone)]
+struct Delimited {
+    delimiter: u8,
+    max_len: usize,
+    frame: Vec<u8>,
+    // dropping the bytes of an oversized frame, up to the next delimiter
+    skipping: bool,
+}
+
+impl Delimited {
+    fn new(delimiter: u8, max_len: usize) -> Self {
+        Self {
+            delimiter,
+            max_len,
+            frame: Vec::new(),
+            skipping: false,
+        }
+    }
+
+    /// Call `on_frame` with each non-empty frame (delimiter excluded) completed by `bytes`.
+    ///
+    /// A frame `on_frame` refuses or longer than `max_len` is dropped and decoding goes on after
+    /// the next delimiter : the first such error is returned once `bytes` are all consumed.
+    fn feed(
+        &mut self,
+        mut bytes: &[u8],
+        mut on_frame: impl FnMut(&[u8]) -> Result<(), FrameError>,
+    ) -> Result<(), FrameError> {
+        let mut first_error = None;
+        while !bytes.is_empty() {
+            let (chunk, complete) = match bytes.iter().position(|&byte| byte == self.delimiter) {
+                Some(end) => {
+                    let (chunk, rest) = bytes.split_at(end);
+                    bytes = &rest[1..];
+                    (chunk, true)
+                }
+                None => (core::mem::take(&mut bytes), false),
+            };
+
+            if !self.skipping {
+                let len = self.frame.len() + chunk.len();
+                if len > self.max_len {
+                    self.frame.clear();
+                    self.skipping = true;
+                    first_error.get_or_insert(FrameError::FrameTooLarge {
+                        len,
+                        capacity: self.max_len,
+                    });
+                } else {
+                    self.frame.extend_from_slice(chunk);
+                }
+            }
+
+            if complete {
+                if !self.skipping
+                    && !self.frame.is_empty()
+                    && let Err(e) = on_frame(&self.frame)
+                {
+                    first_error.get_or_insert(e);
+                }
+                self.frame.clear();
+                self.skipping = false;
+            }
+        }
+        first_error.map_or(Ok(()), Err)
+    }
+}
+
+/// Consistent Overhead Byte Stuffing : each message is COBS encoded, then followed by a `0x00`
+/// delimiter which can't appear inside it.
+///
+/// Empty frames (consecutive delimiters, often sent to flush a link) are skipped.
+#[derive(Debug, Clone)]
+pub struct Cobs {
+    delimited: Delimited,
+}
+
+impl Default for Cobs {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Cobs {
+    /// Decodes frames of up to `MAX_BODY_LEN` encoded bytes.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::with_max_len(MAX_BODY_LEN)
+    }
+
+    /// Decodes frames of up to `max_len` encoded bytes (delimiter excluded).
+    #[must_use]
+    pub fn with_max_len(max_len: usize) -> Self {
+        Self {
+            delimited: Delimited::new(0, max_len),
+        }
+    }
+}
+
+impl FrameFormat for Cobs {
+    /// Never fails.
+    fn encode(&self, message: &[u8], out: &mut Vec<u8>) -> Result<(), FrameError> {
+        cobs::encode_to(message, out);
+        out.push(0);
+        Ok(())
+    }
+
+    /// A malformed or oversized frame is dropped, the next ones are still decoded.
+    /// # Errors
+    /// The first `ParsingError` (invalid COBS) or `FrameTooLarge` met in `bytes`.
+    fn decode(
+        &mut self,
+        bytes: &[u8],
+        on_message: &mut dyn FnMut(Vec<u8>),
+    ) -> Result<(), FrameError> {
+        self.delimited.feed(bytes, |frame| {
+            on_message(cobs::decode(frame)?);
+            Ok(())
+        })
+    }
+}
+
+/// Serial Line IP (RFC 1055) : each message is followed by `END` (`0xC0`), the `END` and
+/// `ESC` (`0xDB`) bytes inside it are sent as `ESC ESC_END` and `ESC ESC_ESC`.
+///
+/// Empty frames are skipped, so an empty message can't be sent and a peer may send `END`
+/// before its frames too.
+#[derive(Debug, Clone)]
+pub struct Slip {
+    delimited: Delimited,
+}
+
+impl Default for Slip {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Slip {
+    pub const END: u8 = 0xC0;
+    pub const ESC: u8 = 0xDB;
+    pub const ESC_END: u8 = 0xDC;
+    pub const ESC_ESC: u8 = 0xDD;
+
+    /// Decodes frames of up to `MAX_BODY_LEN` encoded bytes.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::with_max_len(MAX_BODY_LEN)
+    }
+
+    /// Decodes frames of up to `max_len` encoded bytes (`END` excluded).
+    #[must_use]
+    pub fn with_max_len(max_len: usize) -> Self {
+        Self {
+            delimited: Delimited::new(Self::END, max_len),
+        }
+    }
+}
+
+impl FrameFormat for Slip {
+    /// # Errors
+    /// `MessageEmpty` for an empty message.
+    fn encode(&self, message: &[u8], out: &mut Vec<u8>) -> Result<(), FrameError> {
+        if message.is_empty() {
+            return Err(FrameError::MessageEmpty);
+        }
+        out.reserve(message.len() + 1);
+        for &byte in message {
+            match byte {
+                Self::END => out.extend_from_slice(&[Self::ESC, Self::ESC_END]),
+                Self::ESC => out.extend_from_slice(&[Self::ESC, Self::ESC_ESC]),
+                _ => out.push(byte),
+            }
+        }
+        out.push(Self::END);
+        Ok(())
+    }
+
+    /// A malformed or oversized frame is dropped, the next ones are still decoded.
+    /// # Errors
+    /// The first `ParsingError` (`ESC` not followed by `ESC_END` or `ESC_ESC`) or
+    /// `FrameTooLarge` met in `bytes`.
+    fn decode(
+        &mut self,
+        bytes: &[u8],
+        on_message: &mut dyn FnMut(Vec<u8>),
+    ) -> Result<(), FrameError> {
+        self.delimited.feed(bytes, |frame| {
+            let mut message = Vec::with_capacity(frame.len());
+            let mut escaped = frame.iter();
+            while let Some(&byte) = escaped.next() {
+                if byte != Self::ESC {
+                    message.push(byte);
+                    continue;
+                }
+                match escaped.next() {
+                    Some(&Self::ESC_END) => message.push(Self::END),
+                    Some(&Self::ESC_ESC) => message.push(Self::ESC),
+                    other => {
+                        return Err(FrameError::ParsingError(format!(
+                            "invalid SLIP escape [{other:02x?}]"
+                        )));
+                    }
+                }
+            }
+            on_message(message);
+            Ok(())
+        })
+    }
+}
+
+/// One message per line (newline-delimited JSON and the like), a `\r` before the `\n` is
+/// dropped. Empty lines are skipped.
+#[derive(Debug, Clone)]
+pub struct NewlineDelimited {
+    delimited: Delimited,
+}
+
+impl Default for NewlineDelimited {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NewlineDelimited {
+    /// Decodes lines of up to `MAX_BODY_LEN` bytes.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::with_max_len(MAX_BODY_LEN)
+    }
+
+    /// Decodes lines of up to `max_len` bytes (`\n` excluded).
+    #[must_use]
+    pub fn with_max_len(max_len: usize) -> Self {
+        Self {
+            delimited: Delimited::new(b'\n', max_len),
+        }
+    }
+}
+
+impl FrameFormat for NewlineDelimited {
+    /// # Errors
+    /// `MessageEmpty` for an empty message, `ParsingError` if it holds a `\n` or ends with a
+    /// `\r` (it would not come back the same).
+    fn encode(&self, message: &[u8], out: &mut Vec<u8>) -> Result<(), FrameError> {
+        if message.is_empty() {
+            return Err(FrameError::MessageEmpty);
+        }
+        if message.contains(&b'\n') || message.ends_with(b"\r") {
+            return Err(FrameError::ParsingError(
+                "message holding a line break".to_string(),
+            ));
+        }
+        out.extend_from_slice(message);
+        out.push(b'\n');
+        Ok(())
+    }
+
+    /// # Errors
+    /// `FrameTooLarge` for a line longer than the max len, it is dropped and the next ones are
+    /// still decoded.
+    fn decode(
+        &mut self,
+        bytes: &[u8],
+        on_message: &mut dyn FnMut(Vec<u8>),
+    ) -> Result<(), FrameError> {
+        self.delimited.feed(bytes, |line| {
+            let line = line.strip_suffix(b"\r").unwrap_or(line);
+            if !line.is_empty() {
+                on_message(line.to_vec());
+            }
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,169 @@
+use core::time::Duration;
+
+use crate::control::ControlFrame;
+
+/// Monotonic time source of a [`Heartbeat`], so that it can be driven by a mocked clock.
+pub trait Clock {
+    /// Time elapsed since an arbitrary, fixed origin.
+    fn now(&self) -> Duration;
+}
+
+/// [`Clock`] backed by `std::time::Instant`.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy)]
+pub struct SystemClock {
+    origin: std::time::Instant,
+}
+
+#[cfg(feature = "std")]
+impl SystemClock {
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            origin: std::time::Instant::now(),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Default for SystemClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "std")]
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        self.origin.elapsed()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HeartbeatAction {
+    /// Nothing to send until the next poll.
+    Idle,
+    /// Send this ping to the peer.
+    SendPing(ControlFrame),
+    /// `max_missed` pings in a row went unanswered.
+    PeerDead,
+}
+
+/// Schedules pings on an idle stream and flags the peer as dead after `max_missed` pings in a
+/// row without pong. A pong answers every ping sent since the last one answered, whichever of
+/// them it echoes : a peer slower to answer than `interval` is late, not dead.
+///
+/// It sends and receives nothing itself : the caller polls it, sends the pings it asks for and
+/// hands it the control frames coming from the parser. The stream is idle once `interval` went
+/// by without [`Heartbeat::on_activity`] : the caller calls it for every frame sent or received,
+/// otherwise a busy stream is pinged every `interval` too.
+///
+/// ```rust
+/// # #[cfg(feature = "std")] {
+/// use core::time::Duration;
+/// use stream_framer::{Heartbeat, HeartbeatAction, SystemClock};
+///
+/// let mut heartbeat = Heartbeat::new(SystemClock::new(), Duration::from_secs(15), 3);
+///
+/// match heartbeat.poll() {
+///     HeartbeatAction::SendPing(ping) => { /* send ping.encode() */ }
+///     HeartbeatAction::PeerDead => { /* drop the connection */ }
+///     HeartbeatAction::Idle => {}
+/// }
+///
+/// // a frame was sent or received : no ping needed for another 15 s
+/// heartbeat.on_activity();
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct Heartbeat<C: Clock> {
+    clock: C,
+    interval: Duration,
+    max_missed: u32,
+    // last ping sent, or last activity if later
+    idle_since: Duration,
+    // nonce of the oldest ping sent since the last pong, the pings from it to `next_nonce` are
+    // all waiting for their pong
+    outstanding: Option<u64>,
+    missed: u32,
+    next_nonce: u64,
+}
+
+impl<C: Clock> Heartbeat<C> {
+    /// The first ping is due `interval` after the creation. `max_missed` is at least 1.
+    pub fn new(clock: C, interval: Duration, max_missed: u32) -> Self {
+        let idle_since = clock.now();
+        Self {
+            clock,
+            interval,
+            max_missed: max_missed.max(1),
+            idle_since,
+            outstanding: None,
+            missed: 0,
+            next_nonce: 0,
+        }
+    }
+
+    /// To be called periodically (at least once per `interval`).
+    pub fn poll(&mut self) -> HeartbeatAction {
+        if self.is_peer_dead() {
+            return HeartbeatAction::PeerDead;
+        }
+        let now = self.clock.now();
+        if now.saturating_sub(self.idle_since) < self.interval {
+            return HeartbeatAction::Idle;
+        }
+
+        if self.outstanding.is_some() {
+            self.missed += 1;
+            if self.is_peer_dead() {
+                return HeartbeatAction::PeerDead;
+            }
+        }
+
+        let nonce = self.next_nonce;
+        self.next_nonce = self.next_nonce.wrapping_add(1);
+        self.outstanding.get_or_insert(nonce);
+        self.idle_since = now;
+        HeartbeatAction::SendPing(ControlFrame::Ping(nonce))
+    }
+
+    /// A frame was sent or received : push the next ping back to `interval` from now. The
+    /// deadline of a ping waiting for its pong isn't pushed back, a peer which stopped answering
+    /// is still detected while the caller keeps sending.
+    pub fn on_activity(&mut self) {
+        if self.outstanding.is_none() {
+            self.idle_since = self.clock.now();
+        }
+    }
+
+    /// Handle a control frame received from the peer, returns the frame to send back if any
+    /// (the pong answering a ping).
+    pub fn on_control(&mut self, frame: &ControlFrame) -> Option<ControlFrame> {
+        match frame {
+            ControlFrame::Ping(nonce) => Some(ControlFrame::Pong(*nonce)),
+            ControlFrame::Pong(nonce) => {
+                // the nonces wrap around, compare their distances to the oldest outstanding one
+                if let Some(oldest) = self.outstanding
+                    && nonce.wrapping_sub(oldest) < self.next_nonce.wrapping_sub(oldest)
+                {
+                    self.outstanding = None;
+                    self.missed = 0;
+                }
+                None
+            }
+            ControlFrame::Close { .. } | ControlFrame::Credit { .. } => None,
+        }
+    }
+
+    /// Pings in a row that went unanswered.
+    #[must_use]
+    pub fn missed(&self) -> u32 {
+        self.missed
+    }
+
+    #[must_use]
+    pub fn is_peer_dead(&self) -> bool {
+        self.missed >= self.max_missed
+    }
+}
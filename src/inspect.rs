@@ -0,0 +1,196 @@
+//! Offline inspection of a captured stream.
+//!
+//! The capture is walked header by header. Each frame found is handed to the crate's own parser,
+//! so a frame reported as valid here is parsed the same way in production. Bytes that don't
+//! start with the magic prefix are skipped up to the next one (resync).
+
+use alloc::vec::Vec;
+
+use crate::{
+    error::FrameError,
+    stream_frame::{
+        FrameParser, HDR_SIZE, MAGIC_PREFIX, ParsedStreamData, body_len, decode_header,
+    },
+};
+
+/// What [`inspect`] found at some offset of the capture.
+pub enum InspectEvent {
+    /// A whole frame, and what the parser makes of it.
+    Frame {
+        offset: usize,
+        header: [u8; HDR_SIZE],
+        /// Announced body len (`EXTENDED_FLAG` masked).
+        len: usize,
+        parsed: Result<ParsedStreamData, FrameError>,
+    },
+    /// A header was expected at `offset` but doesn't start with the magic prefix.
+    BadMagic { offset: usize },
+    /// The next magic prefix, found after skipping `skipped` bytes.
+    Resync { offset: usize, skipped: usize },
+    /// No magic prefix between `offset` and the end of the capture.
+    Unsynced { offset: usize, len: usize },
+    /// The capture ends inside a header.
+    TruncatedHeader { offset: usize, available: usize },
+    /// The capture ends inside the body of the frame starting at `offset`.
+    TruncatedBody {
+        offset: usize,
+        len: usize,
+        available: usize,
+    },
+}
+
+impl InspectEvent {
+    /// Offset in the capture of the bytes the event is about.
+    #[must_use]
+    pub fn offset(&self) -> usize {
+        match self {
+            InspectEvent::Frame { offset, .. }
+            | InspectEvent::BadMagic { offset }
+            | InspectEvent::Resync { offset, .. }
+            | InspectEvent::Unsynced { offset, .. }
+            | InspectEvent::TruncatedHeader { offset, .. }
+            | InspectEvent::TruncatedBody { offset, .. } => *offset,
+        }
+    }
+
+    /// True for anything but a frame the parser accepted.
+    #[must_use]
+    pub fn is_anomaly(&self) -> bool {
+        !matches!(self, InspectEvent::Frame { parsed: Ok(_), .. })
+    }
+}
+
+/// Position of the first magic prefix in `data`, with `memchr`'s vectorized substring search.
+#[cfg(feature = "memchr")]
+pub(crate) fn find_magic_prefix(data: &[u8]) -> Option<usize> {
+    memchr::memmem::find(data, &MAGIC_PREFIX)
+}
+
+/// Position of the first magic prefix in `data`.
+///
+/// Filters `LANES` windows at a time on two of their bytes, without branching so that the
+/// compiler vectorizes the loop, and only compares whole windows in the chunks passing it. On
+/// real payloads, unlike the leading `0x00`, `0xF1 .. 0xDD` almost never matches by chance.
+#[cfg(not(feature = "memchr"))]
+pub(crate) fn find_magic_prefix(data: &[u8]) -> Option<usize> {
+    const LANES: usize = 32;
+    const LAST: usize = MAGIC_PREFIX.len() - 1;
+
+    let mut start = 0;
+    while let (Some(seconds), Some(lasts)) = (
+        data.get(start + 1..).and_then(<[u8]>::first_chunk::<LANES>),
+        data.get(start + LAST..)
+            .and_then(<[u8]>::first_chunk::<LANES>),
+    ) {
+        let candidate = seconds
+            .iter()
+            .zip(lasts)
+            .fold(false, |candidate, (&second, &last)| {
+                candidate | ((second == MAGIC_PREFIX[1]) & (last == MAGIC_PREFIX[LAST]))
+            });
+        if candidate
+            && let Some(position) = windows_position(data.get(start..start + LANES + LAST)?)
+        {
+            return Some(start + position);
+        }
+        start += LANES;
+    }
+    windows_position(data.get(start..)?).map(|position| start + position)
+}
+
+#[cfg(not(feature = "memchr"))]
+fn windows_position(data: &[u8]) -> Option<usize> {
+    data.windows(MAGIC_PREFIX.len())
+        .position(|window| window == MAGIC_PREFIX)
+}
+
+/// Walk a captured stream (starting on a frame boundary) and report every frame and anomaly in
+/// order. Never panics, whatever the capture.
+///
+/// ```rust
+/// use stream_framer::{FrameWriter, InspectEvent, inspect};
+///
+/// let mut capture = b"first".to_vec().prepend_frame().unwrap();
+/// capture.extend_from_slice(b"garbage");
+/// capture.extend(b"second".to_vec().prepend_frame().unwrap());
+///
+/// let events = inspect(&capture);
+/// assert!(matches!(events[1], InspectEvent::BadMagic { offset: 17 }));
+/// assert!(matches!(events[2], InspectEvent::Resync { offset: 24, skipped: 7 }));
+/// assert!(matches!(events[3], InspectEvent::Frame { offset: 24, len: 6, .. }));
+/// ```
+#[must_use]
+pub fn inspect(capture: &[u8]) -> Vec<InspectEvent> {
+    let mut events = Vec::new();
+    let mut offset = 0;
+
+    while offset < capture.len() {
+        let rest = &capture[offset..];
+        let Some(header) = rest.first_chunk::<HDR_SIZE>() else {
+            // the start of a header, or garbage too short to tell
+            if MAGIC_PREFIX.starts_with(&rest[..rest.len().min(MAGIC_PREFIX.len())]) {
+                events.push(InspectEvent::TruncatedHeader {
+                    offset,
+                    available: rest.len(),
+                });
+            } else {
+                events.push(InspectEvent::BadMagic { offset });
+                events.push(InspectEvent::Unsynced {
+                    offset,
+                    len: rest.len(),
+                });
+            }
+            break;
+        };
+
+        let Some(len_field) = decode_header(header) else {
+            events.push(InspectEvent::BadMagic { offset });
+            match find_magic_prefix(&rest[1..]) {
+                Some(position) => {
+                    offset += 1 + position;
+                    events.push(InspectEvent::Resync {
+                        offset,
+                        skipped: 1 + position,
+                    });
+                }
+                None => {
+                    events.push(InspectEvent::Unsynced {
+                        offset,
+                        len: rest.len(),
+                    });
+                    break;
+                }
+            }
+            continue;
+        };
+
+        let len = body_len(len_field);
+        let available = rest.len() - HDR_SIZE;
+        if available < len {
+            events.push(InspectEvent::TruncatedBody {
+                offset,
+                len,
+                available,
+            });
+            break;
+        }
+
+        let frame = rest[..HDR_SIZE + len].to_vec();
+        let parsed = frame.parse_frame_header(None, None).and_then(|mut parsed| {
+            match (parsed.pop(), parsed.is_empty()) {
+                (Some(parsed), true) => Ok(parsed),
+                _ => Err(FrameError::ParsingError(
+                    "the parser didn't return exactly one output".into(),
+                )),
+            }
+        });
+        events.push(InspectEvent::Frame {
+            offset,
+            header: *header,
+            len,
+            parsed,
+        });
+        offset += HDR_SIZE + len;
+    }
+    events
+}
@@ -0,0 +1,332 @@
+//! Common length-prefixed framings, as [`FrameFormat`]s.
+//!
+//! [`LengthDelimited`] is the default `tokio_util::codec::LengthDelimitedCodec` layout,
+//! [`VarintDelimited`] the protobuf `writeDelimitedTo` / `parseDelimitedFrom` one and
+//! [`Netstring`] D. J. Bernstein's `len:data,`.
+
+use alloc::{format, string::ToString, vec::Vec};
+
+use crate::{error::FrameError, format::FrameFormat, stream_frame::MAX_BODY_LEN};
+
+/// Reassembly shared by the length-prefixed formats.
+#[derive(Debug, Clone)]
+struct LengthPrefixed {
+    max_len: usize,
+    // bytes of the frames not complete yet
+    pending: Vec<u8>,
+}
+
+/// What a length prefix parser makes of the start of the pending bytes.
+enum Prefix {
+    /// More bytes are needed to read it.
+    Incomplete,
+    /// Prefix len, then body len.
+    Complete(usize, usize),
+}
+
+impl LengthPrefixed {
+    fn new(max_len: usize) -> Self {
+        Self {
+            max_len,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Call `on_message` with each body completed by `bytes`, read with `prefix` and followed by
+    /// `trailer`. On error the pending bytes are dropped : the stream is out of sync.
+    fn feed(
+        &mut self,
+        bytes: &[u8],
+        prefix: impl Fn(&[u8]) -> Result<Prefix, FrameError>,
+        trailer: &[u8],
+        on_message: &mut dyn FnMut(Vec<u8>),
+    ) -> Result<(), FrameError> {
+        self.pending.extend_from_slice(bytes);
+        let mut start = 0;
+        let result = loop {
+            let rest = self.pending.get(start..).unwrap_or_default();
+            let (prefix_len, body_len) = match prefix(rest) {
+                Ok(Prefix::Complete(prefix_len, body_len)) => (prefix_len, body_len),
+                Ok(Prefix::Incomplete) => break Ok(()),
+                Err(e) => break Err(e),
+            };
+            if body_len > self.max_len {
+                break Err(FrameError::FrameTooLarge {
+                    len: body_len,
+                    capacity: self.max_len,
+                });
+            }
+            let Some((body, after)) = rest
+                .get(prefix_len..)
+                .and_then(|rest| rest.split_at_checked(body_len))
+            else {
+                break Ok(());
+            };
+            let Some(after_trailer) = after.get(trailer.len()..) else {
+                break Ok(());
+            };
+            if !after.starts_with(trailer) {
+                break Err(FrameError::ParsingError(format!(
+                    "frame not followed by {trailer:02x?}"
+                )));
+            }
+            on_message(body.to_vec());
+            start = self.pending.len() - after_trailer.len();
+        };
+        match result {
+            Ok(()) => {
+                self.pending.drain(..start);
+            }
+            Err(_) => self.pending.clear(),
+        }
+        result
+    }
+
+    fn check_len(&self, len: usize) -> Result<(), FrameError> {
+        if len > self.max_len {
+            return Err(FrameError::FrameTooLarge {
+                len,
+                capacity: self.max_len,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Each message is preceded by its length as a big endian `u32`, the layout of
+/// `tokio_util::codec::LengthDelimitedCodec::new()`.
+///
+/// The codec refuses frames over 8 MiB by default, use [`LengthDelimited::with_max_len`] for
+/// the same limit.
+#[derive(Debug, Clone)]
+pub struct LengthDelimited {
+    frames: LengthPrefixed,
+}
+
+impl Default for LengthDelimited {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LengthDelimited {
+    /// Messages of up to `MAX_BODY_LEN` bytes.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::with_max_len(MAX_BODY_LEN)
+    }
+
+    /// Messages of up to `max_len` bytes, both ways.
+    #[must_use]
+    pub fn with_max_len(max_len: usize) -> Self {
+        Self {
+            frames: LengthPrefixed::new(max_len),
+        }
+    }
+}
+
+impl FrameFormat for LengthDelimited {
+    /// # Errors
+    /// `FrameTooLarge` if `message` is longer than the max len or than `u32::MAX`.
+    fn encode(&self, message: &[u8], out: &mut Vec<u8>) -> Result<(), FrameError> {
+        self.frames.check_len(message.len())?;
+        let Ok(len) = u32::try_from(message.len()) else {
+            return Err(FrameError::FrameTooLarge {
+                len: message.len(),
+                capacity: u32::MAX as usize,
+            });
+        };
+        out.extend_from_slice(&len.to_be_bytes());
+        out.extend_from_slice(message);
+        Ok(())
+    }
+
+    /// # Errors
+    /// `FrameTooLarge` if a length is over the max len, the pending bytes are dropped.
+    fn decode(
+        &mut self,
+        bytes: &[u8],
+        on_message: &mut dyn FnMut(Vec<u8>),
+    ) -> Result<(), FrameError> {
+        self.frames.feed(
+            bytes,
+            |pending| {
+                Ok(match pending.first_chunk::<4>() {
+                    Some(len) => Prefix::Complete(4, u32::from_be_bytes(*len) as usize),
+                    None => Prefix::Incomplete,
+                })
+            },
+            &[],
+            on_message,
+        )
+    }
+}
+
+/// Each message is preceded by its length as a protobuf varint (LEB128, 7 bits per byte,
+/// least significant group first), as written by `writeDelimitedTo` and read by
+/// `parseDelimitedFrom`.
+#[derive(Debug, Clone)]
+pub struct VarintDelimited {
+    frames: LengthPrefixed,
+}
+
+impl Default for VarintDelimited {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl VarintDelimited {
+    /// Longest varint, a `u64`.
+    const MAX_VARINT_LEN: usize = 10;
+
+    /// Messages of up to `MAX_BODY_LEN` bytes.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::with_max_len(MAX_BODY_LEN)
+    }
+
+    /// Messages of up to `max_len` bytes, both ways.
+    #[must_use]
+    pub fn with_max_len(max_len: usize) -> Self {
+        Self {
+            frames: LengthPrefixed::new(max_len),
+        }
+    }
+
+    fn read_prefix(pending: &[u8]) -> Result<Prefix, FrameError> {
+        let mut len: u64 = 0;
+        for (i, &byte) in pending.iter().take(Self::MAX_VARINT_LEN).enumerate() {
+            let group = u64::from(byte & 0x7f);
+            if i == Self::MAX_VARINT_LEN - 1 && group > 1 {
+                return Err(FrameError::ParsingError(
+                    "varint length over 64 bits".to_string(),
+                ));
+            }
+            len |= group << (7 * i);
+            if byte & 0x80 == 0 {
+                let Ok(len) = usize::try_from(len) else {
+                    return Err(FrameError::TypeConversionFailure(format!(
+                        "varint length [{len}] over usize"
+                    )));
+                };
+                return Ok(Prefix::Complete(i + 1, len));
+            }
+        }
+        if pending.len() >= Self::MAX_VARINT_LEN {
+            return Err(FrameError::ParsingError(
+                "varint length longer than 10 bytes".to_string(),
+            ));
+        }
+        Ok(Prefix::Incomplete)
+    }
+}
+
+impl FrameFormat for VarintDelimited {
+    /// # Errors
+    /// `FrameTooLarge` if `message` is longer than the max len.
+    fn encode(&self, message: &[u8], out: &mut Vec<u8>) -> Result<(), FrameError> {
+        self.frames.check_len(message.len())?;
+        let mut len = message.len() as u64;
+        while len >= 0x80 {
+            out.push(len as u8 | 0x80);
+            len >>= 7;
+        }
+        out.push(len as u8);
+        out.extend_from_slice(message);
+        Ok(())
+    }
+
+    /// # Errors
+    /// `ParsingError` for a varint over 64 bits, `TypeConversionFailure` for a length over
+    /// `usize` and `FrameTooLarge` for one over the max len. The pending bytes are dropped.
+    fn decode(
+        &mut self,
+        bytes: &[u8],
+        on_message: &mut dyn FnMut(Vec<u8>),
+    ) -> Result<(), FrameError> {
+        self.frames.feed(bytes, Self::read_prefix, &[], on_message)
+    }
+}
+
+/// Netstrings : the length in decimal ASCII, `:`, the message and `,` (`5:hello,`).
+///
+/// As the format requires, a length with leading zeros is refused.
+#[derive(Debug, Clone)]
+pub struct Netstring {
+    frames: LengthPrefixed,
+}
+
+impl Default for Netstring {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Netstring {
+    /// Messages of up to `MAX_BODY_LEN` bytes.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::with_max_len(MAX_BODY_LEN)
+    }
+
+    /// Messages of up to `max_len` bytes, both ways.
+    #[must_use]
+    pub fn with_max_len(max_len: usize) -> Self {
+        Self {
+            frames: LengthPrefixed::new(max_len),
+        }
+    }
+
+    fn read_prefix(pending: &[u8]) -> Result<Prefix, FrameError> {
+        let mut len: usize = 0;
+        for (i, &byte) in pending.iter().enumerate() {
+            match byte {
+                b':' if i > 0 => return Ok(Prefix::Complete(i + 1, len)),
+                b'0'..=b'9' if i == 0 || len != 0 => {
+                    // bounds the digits waiting for their `:`, whatever the max len
+                    len = len
+                        .checked_mul(10)
+                        .and_then(|len| len.checked_add(usize::from(byte - b'0')))
+                        .filter(|&len| len <= MAX_BODY_LEN)
+                        .ok_or_else(|| {
+                            FrameError::ParsingError(
+                                "netstring length over MAX_BODY_LEN".to_string(),
+                            )
+                        })?;
+                }
+                _ => {
+                    return Err(FrameError::ParsingError(format!(
+                        "invalid netstring length [{:?}]",
+                        pending.get(..=i).unwrap_or_default()
+                    )));
+                }
+            }
+        }
+        Ok(Prefix::Incomplete)
+    }
+}
+
+impl FrameFormat for Netstring {
+    /// # Errors
+    /// `FrameTooLarge` if `message` is longer than the max len.
+    fn encode(&self, message: &[u8], out: &mut Vec<u8>) -> Result<(), FrameError> {
+        self.frames.check_len(message.len())?;
+        out.extend_from_slice(message.len().to_string().as_bytes());
+        out.push(b':');
+        out.extend_from_slice(message);
+        out.push(b',');
+        Ok(())
+    }
+
+    /// # Errors
+    /// `ParsingError` for an invalid length or a missing `,`, `FrameTooLarge` for a length over
+    /// the max len. The pending bytes are dropped.
+    fn decode(
+        &mut self,
+        bytes: &[u8],
+        on_message: &mut dyn FnMut(Vec<u8>),
+    ) -> Result<(), FrameError> {
+        self.frames.feed(bytes, Self::read_prefix, b",", on_message)
+    }
+}
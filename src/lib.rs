@@ -16,7 +16,8 @@
 //!     I use it in the context of the QUIC protocol (with a HTTP/3 framework based on ```Quiche``` crate), which garantees data order accuracy.
 //!
 //! ## Example
-//! Add the header (magic number: 8 bytes + frame len big endian u32: 4 bytes).
+//! Add the header (magic number: 8 bytes + frame len big endian u32: 4 bytes, whose top bit
+//! flags an extended frame, so a body is at most [`MAX_BODY_LEN`] bytes).
 //! ```rust
 //! use stream_framer::{FrameParser, ParsedStreamData};
 //! use stream_framer::FrameWriter;
@@ -29,11 +30,11 @@
 //!
 //! Then you can parse and handle truncations:
 //! ```rust
-//! use stream_framer::{FrameParser, ParsedStreamData};
+//! use stream_framer::{AnnouncedLen, FrameParser, ParsedStreamData};
 //! use stream_framer::FrameWriter;
 //! // states that keep track of truncated datas (for header and the frame)
 //!
-//! let mut incompleted_stream_data_buffer: Option<(usize, Vec<u8>)> = None; // (frame_size, partial data already received);
+//! let mut incompleted_stream_data_buffer: Option<(AnnouncedLen, Vec<u8>)> = None; // (frame_size, partial data already received);
 //! let mut truncated_header_buffer: Option<Vec<u8>> = None; // The partial header truncated in the previous packet parsing.
 //!
 //! let datagram: Vec<u8> = vec![1; 512];
@@ -66,6 +67,21 @@
 //!                                   truncated_header_buffer = Some(truncated_hdr);
 //!
 //!                               }
+//!                               ParsedStreamData::Control(control_frame) => {
+//!
+//!                                   // ping, pong or close sent by the peer.
+//!
+//!                               }
+//!                               ParsedStreamData::Tagged(tags, data) => {
+//!
+//!                                   // a frame carrying a correlation id (see `RpcEndpoint`).
+//!
+//!                               }
+//!                               _ => {
+//!
+//!                                   // kinds of frames added by later versions.
+//!
+//!                               }
 //!                      }
 //!                    }
 //!                }
@@ -76,17 +92,212 @@
 //!
 //!
 //! ```
+//!
+//! ## Keeping the state for you
+//! [`ParserState`] carries the truncated header and the incomplete frame between packets, and
+//! can be exported as a versioned byte blob to resume a half received message after a
+//! reconnection.
+//! ```rust
+//! use stream_framer::{FrameWriter, ParserState, ParsedStreamData};
+//!
+//! let mut state = ParserState::new();
+//! let stream = b"some message".to_vec().prepend_frame().unwrap();
+//!
+//! for packet in stream.chunks(5) {
+//!     for parsed in state.parse(packet.to_vec()).unwrap() {
+//!         if let ParsedStreamData::Completed(msg) = parsed {
+//!             assert_eq!(msg, b"some message");
+//!         }
+//!     }
+//! }
+//!
+//! let checkpoint: Vec<u8> = state.to_bytes();
+//! let state = ParserState::from_bytes(&checkpoint).unwrap();
+//! ```
+//!
+//! ## Control frames
+//! Frames whose header length field has its top bit ([`EXTENDED_FLAG`]) set are extended
+//! frames, their body starts with a kind and flags byte. [`ControlFrame`]s (ping, pong, close)
+//! use them and come out of the parser as `ParsedStreamData::Control`. [`Heartbeat`] schedules
+//! pings on idle streams and flags a peer as dead after some missed pongs. A close frame carries
+//! a reason code and an optional message, [`ParserState`] refuses any data frame after it.
+//! ```rust
+//! use stream_framer::{ControlFrame, FrameParser, ParsedStreamData};
+//!
+//! let ping = ControlFrame::Ping(42).encode().unwrap();
+//!
+//! match ping.parse_frame_header(None, None).unwrap().pop() {
+//!     Some(ParsedStreamData::Control(ControlFrame::Ping(nonce))) => assert_eq!(nonce, 42),
+//!     _ => panic!("expected a ping"),
+//! }
+//! ```
+//!
+//! ## Escaped frames
+//! A payload can hold the 8 bytes of `MAGIC_PREFIX`, and a resync on it (after corruption or
+//! loss on a serial-like link) then starts in the middle of a frame. [`escape_frame`] rewrites
+//! any frame into one whose body is COBS encoded, holding no magic prefix. The parser unescapes
+//! it transparently, so a stream sending only escaped frames is self-synchronizing : with
+//! `ParserState::with_resync`, bytes lost or damaged in transit cost the frames they hit, the
+//! parser picks up again at the next one. `SliceFrameDecoder` and `RingFrameDecoder`, which
+//! only take plain frames, skip garbage the same way with their own `with_resync`.
+//!
+//! ## Masked frames
+//! Middleboxes doing deep packet inspection can choke on repeated payloads. [`mask_frame`] XORs
+//! the fields and the payload of a frame with a 4 bytes key carried in its extension header
+//! (WebSocket style, `random_masking_key` draws one with the `std` feature), the parser unmasks
+//! it transparently. It is obfuscation, not security : the magic prefix starting every frame,
+//! the length field, the kind and the flags stay in clear.
+//!
+//! ## Padded frames
+//! Message sizes leak what is sent, even through encryption. [`pad_frame`] pads a frame body up
+//! to a size bucket chosen by a [`PaddingPolicy`] (the next power of two or block multiple) and
+//! records the real payload len in its extension header, the parser strips the padding before
+//! returning the frame. `FrameWriter::prepend_frame_padded` frames a payload padded in one
+//! go, and a writer going through [`MagicLength`] sets its policy with
+//! `MagicLength::with_padding`.
+//!
+//! ## Chunked frames
+//! A large frame delays everything sent after it. [`chunk_frame`] cuts a frame into chunk
+//! frames, other frames can go between them, and [`ParserState`] reassembles them (the raw
+//! parser hands them out as `ParsedStreamData::Chunk`).
+//!
+//! ## Other framing schemes
+//! [`FrameFormat`] encodes and incrementally decodes messages, whatever the framing :
+//! [`MagicLength`] (this crate's), [`Cobs`], [`Slip`] (RFC 1055), [`NewlineDelimited`]
+//! (newline-delimited JSON and the like), [`LengthDelimited`] (`tokio_util`'s
+//! `LengthDelimitedCodec`), [`VarintDelimited`] (protobuf delimited streams) or [`Netstring`].
+//! A gateway decodes with one and encodes with another to bridge two peers.
+//!
+//! ## Request/response
+//! [`FrameTags`] adds an optional correlation id to a data frame, which is surfaced as
+//! `ParsedStreamData::Tagged`. With the `std` feature, [`RpcEndpoint`] sends requests and hands
+//! back an [`RpcHandle`], resolved (blocking or as a future) by the reply carrying the same id,
+//! with timeouts and cancellation.
+//!
+//! ## Topics
+//! A data frame can carry a topic, as a name or as its hash ([`Topic`]). [`TopicRouter`] hands
+//! such frames to the callbacks (or channels) subscribed with a matching filter, `+` and `#`
+//! wildcards included.
+//!
+//! ## Flow control
+//! [`CreditReceiver`] grants frames and bytes per channel with `ControlFrame::Credit`, and
+//! [`CreditSender`] refuses or queues the messages of a channel out of credit. Both are sans-io
+//! and work with any transport.
+//!
+//! ## Priorities
+//! [`PriorityFrameQueue`] holds outgoing frames in priority classes and drains them as packets
+//! of the usual wire format, urgent frames first, with starvation protection. Opting in with
+//! `PriorityFrameQueue::with_chunk_size`, it sends large frames as chunks so that urgent frames
+//! don't wait for them : the receivers then need a `ParserState` to reassemble them.
+//!
+//! ## Buffer types
+//! Besides `Vec<u8>`, both traits are implemented for `Box<[u8]>`, `Cow<[u8]>` and
+//! `VecDeque<u8>`, for `bytes::Bytes`/`bytes::BytesMut` with the `bytes` feature and for
+//! `smallvec::SmallVec` with the `smallvec` feature. `&[u8]` implements `FrameParser`. The
+//! parser only reads the packet, whatever its type : it is never copied, the completed bodies
+//! are.
+//!
+//! ## Writing into a caller buffer
+//! [`encode_into`] frames a payload without allocating, in a buffer sized with [`encoded_len`]:
+//! ```rust
+//! use stream_framer::{encode_into, encoded_len};
+//!
+//! let payload = b"hot path";
+//! let mut out = [0u8; 64];
+//!
+//! let written = encode_into(payload, &mut out).unwrap();
+//! assert_eq!(written, encoded_len(payload.len()));
+//! ```
+//!
+//! ## `no_std`
+//! The crate is `no_std` + `alloc` when the default `std` feature is disabled (only
+//! `FrameError::Io` goes away). [`SliceFrameDecoder`] reassembles frames inside a caller-provided
+//! `&mut [u8]` for peers that can't grow buffers.
+//!
+//! ## Inspecting a capture
+//! [`inspect`] walks a raw capture of a stream and reports every frame (as parsed by the crate's
+//! own parser), bad magic prefix, resync and truncation. The `stream-framer` binary (`cli`
+//! feature) prints that report for a file or stdin : `stream-framer inspect [--json] [FILE]`.
+//! Its `encode`, `split --mtu N` and `join` commands frame messages, cut a stream into packet
+//! files and reassemble them, to reproduce a reception offline. The search for the next magic
+//! prefix is vectorized, with `memchr::memmem` when the `memchr` feature is enabled.
+//!
+//! ## Captures
+//! With the `pcap` feature, [`Capture`] reads pcap / pcapng files of plain TCP or UDP traffic,
+//! reassembles each flow (5-tuple, sequence numbers) and feeds it to the frame decoder.
+//! [`wireshark_dissector`] writes a Lua plugin showing the frames decoded in Wireshark
+//! (`stream-framer dissector` from the command line).
+//!
+//! ## Bounded memory
+//! [`RingFrameDecoder`] reassembles frames in a fixed-capacity circular buffer, applies
+//! backpressure when full and lends frames as one or two slices.
+//!
+//! ## Untrusted input
+//! The parsing APIs never panic, whatever the bytes and the caller held state: malformed input
+//! comes back as a [`FrameError`]. This covers [`FrameParser::parse_frame_header`],
+//! [`ParserState`] (`parse` and `from_bytes`), [`SliceFrameDecoder`], [`RingFrameDecoder`],
+//! [`inspect`] and `Capture::parse`, and is checked by the adversarial property tests.
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
 
+mod buffers;
+mod cobs;
+mod control;
+mod credit;
+mod dissector;
 mod error;
+mod extended;
+mod format;
+mod heartbeat;
+mod inspect;
+mod length_prefixed;
+mod parser_state;
+#[cfg(feature = "pcap")]
+mod pcap;
+mod priority;
+mod ring_decoder;
+#[cfg(feature = "std")]
+mod rpc;
+mod slice_decoder;
+mod spec;
 mod stream_frame;
 mod test;
+mod topic;
 
+pub use control::ControlFrame;
+pub use credit::{CreditReceiver, CreditSender, OnNoCredit};
+pub use dissector::wireshark_dissector;
+pub use error::FrameError;
+#[cfg(feature = "std")]
+pub use extended::random_masking_key;
+pub use extended::{FrameTags, PaddingPolicy, chunk_frame, escape_frame, mask_frame, pad_frame};
+pub use format::{Cobs, FrameFormat, MagicLength, NewlineDelimited, Slip};
+#[cfg(feature = "std")]
+pub use heartbeat::SystemClock;
+pub use heartbeat::{Clock, Heartbeat, HeartbeatAction};
+pub use inspect::{InspectEvent, inspect};
+pub use length_prefixed::{LengthDelimited, Netstring, VarintDelimited};
+pub use parser_state::PARSER_STATE_VERSION;
+pub use parser_state::ParserState;
+#[cfg(feature = "pcap")]
+pub use pcap::{Capture, Flow, FlowKey, FlowStream, Gap, Transport};
+pub use priority::PriorityFrameQueue;
+pub use ring_decoder::{RingFrame, RingFrameDecoder};
+#[cfg(feature = "std")]
+pub use rpc::{RpcEndpoint, RpcEvent, RpcHandle};
+pub use slice_decoder::SliceFrameDecoder;
 pub use stream_frame::FrameParser;
 pub use stream_frame::FrameWriter;
 pub use stream_frame::ParsedStreamData;
+pub use stream_frame::{AnnouncedLen, EXTENDED_FLAG, HDR_SIZE, MAGIC_PREFIX, MAX_BODY_LEN};
+pub use stream_frame::{encode_into, encoded_len};
+pub use topic::{MAX_TOPIC_LEN, Topic, TopicRouter};
 
 pub mod prelude {
+    pub use super::ControlFrame;
     pub use super::FrameParser;
     pub use super::FrameWriter;
     pub use super::ParsedStreamData;
+    pub use super::ParserState;
 }
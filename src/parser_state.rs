@@ -0,0 +1,460 @@
+use alloc::{
+    collections::{BTreeMap, btree_map::Entry},
+    format,
+    string::ToString,
+    vec,
+    vec::Vec,
+};
+
+use crate::{
+    control::ControlFrame,
+    error::FrameError,
+    stream_frame::{AnnouncedLen, FrameParser, HDR_SIZE, MAX_BODY_LEN, ParsedStreamData, body_len},
+};
+
+/// Version tag written in front of every checkpoint produced by [`ParserState::to_bytes`].
+pub const PARSER_STATE_VERSION: u8 = 1;
+
+const HAS_PENDING_BODY: u8 = 0b0000_0001;
+const HAS_PENDING_HEADER: u8 = 0b0000_0010;
+const CLOSED: u8 = 0b0000_0100;
+const HAS_CHUNKS: u8 = 0b0000_1000;
+
+/// Largest frame a chunk stream reassembles.
+const MAX_CHUNKED_LEN: usize = HDR_SIZE + MAX_BODY_LEN;
+/// Chunk streams reassembled at the same time, unless set with `with_chunk_limits`.
+const DEFAULT_MAX_CHUNK_STREAMS: usize = 64;
+/// Bytes of chunked frames buffered at the same time, unless set with `with_chunk_limits`.
+const DEFAULT_MAX_CHUNKED_BYTES: usize = 16 * 1024 * 1024;
+
+/// What the parser is waiting for between two packets.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+enum Pending {
+    #[default]
+    Nothing,
+    // (announced len, body bytes already received)
+    Body(AnnouncedLen, Vec<u8>),
+    // header bytes received so far, always < HDR_SIZE
+    Header(Vec<u8>),
+}
+
+/// Owns the partial state that [`FrameParser::parse_frame_header`] expects the caller to carry
+/// between packets (the pending header bytes, the announced body length and the body bytes
+/// received so far).
+///
+/// The state can be checkpointed with [`ParserState::to_bytes`] and restored with
+/// [`ParserState::from_bytes`], so that a half-received message survives a process restart or
+/// a connection migration.
+///
+/// Once a `ControlFrame::Close` went through, the state refuses any further data frame.
+///
+/// The chunks of a frame cut by `chunk_frame` are reassembled per chunk stream, the frame they
+/// carry comes out once its last chunk arrived. The streams open at the same time and the bytes
+/// they buffer are capped (64 streams and 16 MiB unless set with
+/// [`ParserState::with_chunk_limits`]), a peer can't make the state grow without bound.
+///
+/// The state is opaque : only the parser or [`ParserState::from_bytes`] (which checks the blob)
+/// build it, so it can't be inconsistent the way the raw `Option`s passed to
+/// `parse_frame_header` can.
+///
+/// ```rust
+/// use stream_framer::{FrameWriter, ParserState, ParsedStreamData};
+///
+/// let frame = b"half received".to_vec().prepend_frame().unwrap();
+/// let (first, second) = frame.split_at(16);
+///
+/// let mut state = ParserState::new();
+/// assert!(state.parse(first.to_vec()).unwrap().is_empty());
+///
+/// // checkpoint, then resume from the blob
+/// let blob = state.to_bytes();
+/// let mut restored = ParserState::from_bytes(&blob).unwrap();
+///
+/// match restored.parse(second.to_vec()).unwrap().pop() {
+///     Some(ParsedStreamData::Completed(msg)) => assert_eq!(msg, b"half received"),
+///     _ => panic!("expected a completed message"),
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParserState {
+    pending: Pending,
+    // a close frame was received
+    closed: bool,
+    // frame bytes received so far of the chunked frames being received, by stream
+    chunks: BTreeMap<u32, Vec<u8>>,
+    // bytes buffered in `chunks`
+    chunked_bytes: usize,
+    max_chunk_streams: usize,
+    max_chunked_bytes: usize,
+    // skip the frames refused instead of failing the packet
+    resync: bool,
+}
+
+impl Default for ParserState {
+    fn default() -> Self {
+        Self {
+            pending: Pending::Nothing,
+            closed: false,
+            chunks: BTreeMap::new(),
+            chunked_bytes: 0,
+            max_chunk_streams: DEFAULT_MAX_CHUNK_STREAMS,
+            max_chunked_bytes: DEFAULT_MAX_CHUNKED_BYTES,
+            resync: false,
+        }
+    }
+}
+
+impl ParserState {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parse the packets with [`FrameParser::parse_frame_header_resyncing`] : a header without
+    /// the magic prefix or an extended frame that doesn't decode is skipped up to the next magic
+    /// prefix instead of failing the packet, so that a stream of escaped frames recovers from
+    /// lost or damaged bytes. Like the chunk limits, the mode isn't part of the checkpoint.
+    #[must_use]
+    pub fn with_resync(mut self) -> Self {
+        self.resync = true;
+        self
+    }
+
+    /// Cap the chunk streams reassembled at the same time to `max_streams`, and the bytes they
+    /// buffer to `max_bytes`. The limits aren't part of the [`ParserState::to_bytes`]
+    /// checkpoint, a restored state starts again from the defaults.
+    #[must_use]
+    pub fn with_chunk_limits(mut self, max_streams: usize, max_bytes: usize) -> Self {
+        self.max_chunk_streams = max_streams;
+        self.max_chunked_bytes = max_bytes;
+        self
+    }
+
+    /// Parse a packet, keeping the truncated header or the incomplete body for the next call.
+    ///
+    /// The returned vec only contains finished frames, `Incompleted` and `TruncatedHeader`
+    /// outputs are absorbed into the state, as well as the chunks of a chunked frame (it comes
+    /// out whole with its last chunk). Never panics, whatever the packet.
+    ///
+    /// The packet carrying the close frame still hands out everything up to and including the
+    /// close, as well as the control frames after it. Data frames following the close in that
+    /// same packet are dropped.
+    /// # Errors
+    /// Forwards the parser errors, `ParsingError` if a chunked frame is over `MAX_BODY_LEN` or
+    /// doesn't reassemble into exactly one frame or if a chunk goes over the chunk limits, and
+    /// `StreamClosed` if a later packet carries a data frame. The pending state, chunked frames included, is dropped in all cases.
+    pub fn parse<P: FrameParser>(
+        &mut self,
+        packet: P,
+    ) -> Result<Vec<ParsedStreamData>, FrameError> {
+        let parsed = self.parse_packet(packet);
+        if parsed.is_err() {
+            self.pending = Pending::Nothing;
+            self.chunks.clear();
+            self.chunked_bytes = 0;
+        }
+        parsed
+    }
+
+    fn parse_packet<P: FrameParser>(
+        &mut self,
+        packet: P,
+    ) -> Result<Vec<ParsedStreamData>, FrameError> {
+        let (last_incomplete_reception, is_last_header_truncated) =
+            match core::mem::take(&mut self.pending) {
+                Pending::Nothing => (None, None),
+                Pending::Body(size, data) => (Some((size, data)), None),
+                Pending::Header(hdr) => (None, Some(hdr)),
+            };
+
+        let parsed = if self.resync {
+            packet
+                .parse_frame_header_resyncing(last_incomplete_reception, is_last_header_truncated)?
+        } else {
+            packet.parse_frame_header(last_incomplete_reception, is_last_header_truncated)?
+        };
+
+        let closed_before = self.closed;
+        let mut output = Vec::with_capacity(parsed.len());
+        for p in parsed {
+            self.accept(p, closed_before, &mut output)?;
+        }
+        Ok(output)
+    }
+
+    fn accept(
+        &mut self,
+        parsed: ParsedStreamData,
+        closed_before: bool,
+        output: &mut Vec<ParsedStreamData>,
+    ) -> Result<(), FrameError> {
+        match parsed {
+            ParsedStreamData::Incompleted(size, data) => {
+                self.pending = Pending::Body(size, data);
+            }
+            ParsedStreamData::TruncatedHeader(hdr) => {
+                self.pending = Pending::Header(hdr);
+            }
+            ParsedStreamData::Chunk {
+                stream,
+                continued,
+                piece,
+            } => {
+                if let Some(frame) = self.add_chunk(stream, continued, piece)? {
+                    self.accept(reassemble(&frame)?, closed_before, output)?;
+                }
+            }
+            ParsedStreamData::Completed(_) | ParsedStreamData::Tagged(..) if closed_before => {
+                return Err(FrameError::StreamClosed);
+            }
+            // sent along with the close, keep what came before it
+            ParsedStreamData::Completed(_) | ParsedStreamData::Tagged(..) if self.closed => {}
+            ParsedStreamData::Control(ControlFrame::Close { code, reason }) => {
+                self.closed = true;
+                output.push(ParsedStreamData::Control(ControlFrame::Close {
+                    code,
+                    reason,
+                }));
+            }
+            completed => output.push(completed),
+        }
+        Ok(())
+    }
+
+    // the reassembled frame once `piece` is the last chunk of `stream`
+    fn add_chunk(
+        &mut self,
+        stream: u32,
+        continued: bool,
+        piece: Vec<u8>,
+    ) -> Result<Option<Vec<u8>>, FrameError> {
+        let buffered = self.chunks.get(&stream).map_or(0, Vec::len);
+        if buffered + piece.len() > MAX_CHUNKED_LEN {
+            return Err(FrameError::ParsingError(format!(
+                "chunked frame of stream [{stream}] over MAX_BODY_LEN"
+            )));
+        }
+        if !continued {
+            let Some(mut frame) = self.chunks.remove(&stream) else {
+                return Ok(Some(piece));
+            };
+            self.chunked_bytes -= frame.len();
+            frame.extend_from_slice(&piece);
+            return Ok(Some(frame));
+        }
+        if buffered == 0 && self.chunks.len() >= self.max_chunk_streams {
+            return Err(FrameError::ParsingError(format!(
+                "more than [{}] chunk streams open",
+                self.max_chunk_streams
+            )));
+        }
+        if self.chunked_bytes + piece.len() > self.max_chunked_bytes {
+            return Err(FrameError::ParsingError(format!(
+                "more than [{}] bytes of chunked frames buffered",
+                self.max_chunked_bytes
+            )));
+        }
+        self.chunked_bytes += piece.len();
+        match self.chunks.entry(stream) {
+            Entry::Vacant(entry) => {
+                entry.insert(piece);
+            }
+            Entry::Occupied(mut entry) => entry.get_mut().extend_from_slice(&piece),
+        }
+        Ok(None)
+    }
+
+    /// True once the peer sent a close frame.
+    #[must_use]
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    /// True if no partial header, partial body nor chunked frame is pending.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.pending == Pending::Nothing && self.chunks.is_empty()
+    }
+    /// Header bytes received so far, if the last packet ended inside a header.
+    #[must_use]
+    pub fn pending_header(&self) -> Option<&[u8]> {
+        match &self.pending {
+            Pending::Header(hdr) => Some(hdr),
+            _ => None,
+        }
+    }
+    /// Body length announced by the header of the frame being received.
+    #[must_use]
+    pub fn announced_len(&self) -> Option<usize> {
+        match &self.pending {
+            Pending::Body(announced, _) => Some(announced.body_len()),
+            _ => None,
+        }
+    }
+    /// Body bytes of the frame being received that already arrived.
+    #[must_use]
+    pub fn received(&self) -> &[u8] {
+        match &self.pending {
+            Pending::Body(_, data) => data,
+            _ => &[],
+        }
+    }
+
+    /// Export the state as a versioned byte blob.
+    ///
+    /// Layout (big endian) : `version: u8 | flags: u8 | [announced: u32 | received_len: u32 |
+    /// received] | [hdr_len: u8 | hdr] | [chunks: u32 | (stream: u32 | len: u32 | bytes)*]`.
+    #[must_use]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut flags = if self.closed { CLOSED } else { 0 };
+        if !self.chunks.is_empty() {
+            flags |= HAS_CHUNKS;
+        }
+        let mut blob = vec![PARSER_STATE_VERSION];
+        match &self.pending {
+            Pending::Nothing => blob.push(flags),
+            Pending::Body(size, data) => {
+                blob.push(HAS_PENDING_BODY | flags);
+                // both fit, a frame body len is a u32 by construction
+                blob.extend_from_slice(&(size.to_field() as u32).to_be_bytes());
+                blob.extend_from_slice(&(data.len() as u32).to_be_bytes());
+                blob.extend_from_slice(data);
+            }
+            Pending::Header(hdr) => {
+                blob.push(HAS_PENDING_HEADER | flags);
+                blob.push(hdr.len() as u8);
+                blob.extend_from_slice(hdr);
+            }
+        }
+        if !self.chunks.is_empty() {
+            // a chunked frame is at most `MAX_CHUNKED_LEN` long, there are fewer streams
+            // than chunks received
+            blob.extend_from_slice(&(self.chunks.len() as u32).to_be_bytes());
+            for (stream, frame) in &self.chunks {
+                blob.extend_from_slice(&stream.to_be_bytes());
+                blob.extend_from_slice(&(frame.len() as u32).to_be_bytes());
+                blob.extend_from_slice(frame);
+            }
+        }
+        blob
+    }
+
+    /// Restore a state exported with [`ParserState::to_bytes`]. Never panics, whatever the blob.
+    /// # Errors
+    /// Returns a `ParsingError` if the version is unknown or if the blob is truncated, and
+    /// `InvalidState` if it is inconsistent (more bytes received than announced, header longer
+    /// than `HDR_SIZE`...).
+    pub fn from_bytes(blob: &[u8]) -> Result<Self, FrameError> {
+        let invalid = |reason: &str| FrameError::ParsingError(format!("parser state : {reason}"));
+        let inconsistent =
+            |reason: &str| FrameError::InvalidState(format!("parser state : {reason}"));
+
+        let [version, flags, rest @ ..] = blob else {
+            return Err(invalid("blob too short"));
+        };
+        if *version != PARSER_STATE_VERSION {
+            return Err(invalid(&format!("unsupported version {version}")));
+        }
+
+        let (pending, mut rest) = match *flags & !(CLOSED | HAS_CHUNKS) {
+            0 => (Pending::Nothing, rest),
+            HAS_PENDING_BODY => {
+                let (Some(size), Some(received_len)) = (read_u32(rest, 0), read_u32(rest, 4))
+                else {
+                    return Err(invalid("blob too short"));
+                };
+                let Some((data, rest)) = rest
+                    .get(8..)
+                    .and_then(|rest| rest.split_at_checked(received_len as usize))
+                else {
+                    return Err(invalid("blob too short"));
+                };
+                if received_len as usize > body_len(size as usize) {
+                    return Err(inconsistent("received more bytes than announced"));
+                }
+                (
+                    Pending::Body(AnnouncedLen::from_field(size as usize), data.to_vec()),
+                    rest,
+                )
+            }
+            HAS_PENDING_HEADER => {
+                let Some((hdr_len, rest)) = rest.split_first() else {
+                    return Err(invalid("blob too short"));
+                };
+                let hdr_len = *hdr_len as usize;
+                if hdr_len == 0 || hdr_len >= HDR_SIZE {
+                    return Err(inconsistent("pending header len out of range"));
+                }
+                let Some((hdr, rest)) = rest.split_at_checked(hdr_len) else {
+                    return Err(invalid("blob too short"));
+                };
+                (Pending::Header(hdr.to_vec()), rest)
+            }
+            _ => return Err(invalid("unknown flags")),
+        };
+
+        let mut chunks = BTreeMap::new();
+        let mut chunked_bytes = 0;
+        if *flags & HAS_CHUNKS != 0 {
+            let Some(count) = read_u32(rest, 0) else {
+                return Err(invalid("blob too short"));
+            };
+            if count == 0 {
+                return Err(inconsistent("no chunked frame"));
+            }
+            rest = &rest[4..];
+            for _ in 0..count {
+                let (Some(stream), Some(len)) = (read_u32(rest, 0), read_u32(rest, 4)) else {
+                    return Err(invalid("blob too short"));
+                };
+                let Some((frame, tail)) = rest[8..].split_at_checked(len as usize) else {
+                    return Err(invalid("blob too short"));
+                };
+                if frame.is_empty() || frame.len() > MAX_CHUNKED_LEN {
+                    return Err(inconsistent("chunked frame len out of range"));
+                }
+                if chunks.insert(stream, frame.to_vec()).is_some() {
+                    return Err(inconsistent("chunk stream listed twice"));
+                }
+                chunked_bytes += frame.len();
+                rest = tail;
+            }
+        }
+
+        if !rest.is_empty() {
+            return Err(invalid("trailing bytes"));
+        }
+        Ok(Self {
+            pending,
+            closed: *flags & CLOSED != 0,
+            chunks,
+            chunked_bytes,
+            ..Self::default()
+        })
+    }
+}
+
+/// The single frame `frame`, the bytes of a chunk stream, is made of.
+fn reassemble(frame: &[u8]) -> Result<ParsedStreamData, FrameError> {
+    let mut parsed = frame.parse_frame_header(None, None)?;
+    match (parsed.pop(), parsed.is_empty()) {
+        (
+            Some(
+                ParsedStreamData::Incompleted(..)
+                | ParsedStreamData::TruncatedHeader(_)
+                | ParsedStreamData::Chunk { .. },
+            ),
+            _,
+        )
+        | (_, false)
+        | (None, _) => Err(FrameError::ParsingError(
+            "chunks don't reassemble into exactly one frame".to_string(),
+        )),
+        (Some(parsed), true) => Ok(parsed),
+    }
+}
+
+fn read_u32(data: &[u8], at: usize) -> Option<u32> {
+    let bytes: [u8; 4] = data.get(at..at + 4)?.try_into().ok()?;
+    Some(u32::from_be_bytes(bytes))
+}
@@ -0,0 +1,516 @@
+//! pcap / pcapng import.
+//!
+//! Reads the packets of a capture file, decodes the link (Ethernet, Linux cooked, raw IP,
+//! loopback), IPv4 / IPv6 and TCP / UDP layers and groups the payloads by flow (directional
+//! 5-tuple). A TCP flow is reassembled by sequence number, a UDP flow is the list of its
+//! datagrams. Either can then be fed to the frame decoder.
+//!
+//! Packets that can't carry a flow payload (ARP, ICMP, IP fragments, unknown link types...) are
+//! counted and skipped.
+
+use alloc::{format, vec, vec::Vec};
+use core::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use crate::{error::FrameError, parser_state::ParserState, stream_frame::ParsedStreamData};
+
+const PCAP_MAGIC_MICROS: u32 = 0xa1b2_c3d4;
+const PCAP_MAGIC_NANOS: u32 = 0xa1b2_3c4d;
+const PCAP_HDR_SIZE: usize = 24;
+const PCAP_RECORD_HDR_SIZE: usize = 16;
+
+const PCAPNG_SECTION_HEADER: u32 = 0x0a0d_0d0a;
+const PCAPNG_BYTE_ORDER_MAGIC: u32 = 0x1a2b_3c4d;
+const PCAPNG_INTERFACE_DESCRIPTION: u32 = 1;
+const PCAPNG_SIMPLE_PACKET: u32 = 3;
+const PCAPNG_ENHANCED_PACKET: u32 = 6;
+
+const LINKTYPE_NULL: u32 = 0;
+const LINKTYPE_ETHERNET: u32 = 1;
+const LINKTYPE_RAW: u32 = 101;
+const LINKTYPE_LINUX_SLL: u32 = 113;
+const LINKTYPE_IPV4: u32 = 228;
+const LINKTYPE_IPV6: u32 = 229;
+
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const ETHERTYPE_IPV6: u16 = 0x86dd;
+const ETHERTYPE_VLAN: u16 = 0x8100;
+
+const IP_PROTO_TCP: u8 = 6;
+const IP_PROTO_UDP: u8 = 17;
+
+const TCP_FLAG_SYN: u8 = 0x02;
+
+/// Transport protocol of a flow.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Transport {
+    Tcp,
+    Udp,
+}
+
+/// One direction of a conversation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FlowKey {
+    pub transport: Transport,
+    pub src: SocketAddr,
+    pub dst: SocketAddr,
+}
+
+/// Bytes missing from a reassembled TCP stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Gap {
+    /// Position in `FlowStream::data` where the missing bytes should be.
+    pub offset: usize,
+    pub missing: usize,
+}
+
+/// The bytes of a flow, in order.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FlowStream {
+    pub data: Vec<u8>,
+    /// Holes left by segments that were not captured, the bytes around them are kept.
+    pub gaps: Vec<Gap>,
+}
+
+#[derive(Debug, Clone)]
+struct Segment {
+    // sequence number of the first payload byte, TCP only
+    seq: u32,
+    payload: Vec<u8>,
+}
+
+/// The payloads of one flow, in capture order.
+#[derive(Debug, Clone)]
+pub struct Flow {
+    pub key: FlowKey,
+    // first byte of the stream, once a SYN was seen
+    isn: Option<u32>,
+    segments: Vec<Segment>,
+}
+
+impl Flow {
+    /// Captured payloads, empty ones (pure ACKs...) excluded.
+    #[must_use]
+    pub fn packets(&self) -> Vec<&[u8]> {
+        self.segments
+            .iter()
+            .map(|segment| segment.payload.as_slice())
+            .collect()
+    }
+
+    /// Payload bytes captured on the flow, retransmissions included.
+    #[must_use]
+    pub fn captured_bytes(&self) -> usize {
+        self.segments
+            .iter()
+            .map(|segment| segment.payload.len())
+            .sum()
+    }
+
+    /// The flow bytes in order : TCP segments sorted by sequence number without the
+    /// retransmitted bytes, UDP datagrams concatenated in capture order.
+    #[must_use]
+    pub fn stream(&self) -> FlowStream {
+        match self.key.transport {
+            Transport::Udp => FlowStream {
+                data: self
+                    .segments
+                    .iter()
+                    .flat_map(|segment| segment.payload.iter().copied())
+                    .collect(),
+                gaps: vec![],
+            },
+            Transport::Tcp => self.reassemble(),
+        }
+    }
+
+    /// Feed the flow through the frame decoder : every UDP datagram as a packet, or the
+    /// reassembled TCP stream. A partial frame at the end of the capture is dropped.
+    /// # Errors
+    /// `ParsingError` if a TCP segment is missing, and the parser errors.
+    pub fn decode(&self) -> Result<Vec<ParsedStreamData>, FrameError> {
+        let mut state = ParserState::new();
+        let mut output = vec![];
+        match self.key.transport {
+            Transport::Udp => {
+                for segment in &self.segments {
+                    output.extend(state.parse(segment.payload.clone())?);
+                }
+            }
+            Transport::Tcp => {
+                let stream = self.reassemble();
+                if let Some(gap) = stream.gaps.first() {
+                    return Err(FrameError::ParsingError(format!(
+                        "tcp flow : [{}] bytes missing at offset [{}]",
+                        gap.missing, gap.offset
+                    )));
+                }
+                output.extend(state.parse(stream.data)?);
+            }
+        }
+        Ok(output)
+    }
+
+    fn reassemble(&self) -> FlowStream {
+        let Some(first) = self.segments.first() else {
+            return FlowStream::default();
+        };
+        // without a SYN, the stream starts at the lowest sequence number captured
+        let base = self.isn.unwrap_or_else(|| {
+            self.segments
+                .iter()
+                .map(|segment| segment.seq)
+                .min_by_key(|seq| seq.wrapping_sub(first.seq) as i32)
+                .unwrap_or(first.seq)
+        });
+
+        let mut segments: Vec<(usize, &[u8])> = self
+            .segments
+            .iter()
+            .map(|segment| {
+                (
+                    segment.seq.wrapping_sub(base) as usize,
+                    segment.payload.as_slice(),
+                )
+            })
+            .collect();
+        segments.sort_by_key(|(offset, _)| *offset);
+
+        let mut stream = FlowStream::default();
+        // stream offset of the next byte expected
+        let mut next = 0;
+        for (offset, payload) in segments {
+            let end = offset + payload.len();
+            if end <= next {
+                // retransmission
+                continue;
+            }
+            if offset > next {
+                stream.gaps.push(Gap {
+                    offset: stream.data.len(),
+                    missing: offset - next,
+                });
+                next = offset;
+            }
+            stream.data.extend_from_slice(&payload[next - offset..]);
+            next = end;
+        }
+        stream
+    }
+}
+
+/// The flows of a capture file.
+///
+/// ```rust
+/// use stream_framer::{Capture, ParsedStreamData};
+///
+/// # fn run(bytes: &[u8]) -> Result<(), stream_framer::FrameError> {
+/// let capture = Capture::parse(bytes)?;
+/// for flow in capture.flows() {
+///     for parsed in flow.decode()? {
+///         if let ParsedStreamData::Completed(msg) = parsed {
+///             println!("{} -> {} : {msg:?}", flow.key.src, flow.key.dst);
+///         }
+///     }
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Capture {
+    flows: Vec<Flow>,
+    packets: usize,
+    skipped: usize,
+}
+
+impl Capture {
+    /// Parse a whole pcap or pcapng file. Never panics, whatever the file.
+    /// # Errors
+    /// `ParsingError` if the file is neither pcap nor pcapng, or if it is truncated.
+    pub fn parse(file: &[u8]) -> Result<Self, FrameError> {
+        let mut capture = Capture::default();
+        let magic = file.get(..4).ok_or_else(|| invalid("file too short"))?;
+        if u32::from_le_bytes(magic.try_into().unwrap_or_default()) == PCAPNG_SECTION_HEADER {
+            read_pcapng(file, &mut capture)?;
+        } else {
+            read_pcap(file, &mut capture)?;
+        }
+        Ok(capture)
+    }
+
+    /// Read and parse the capture file at `path`.
+    /// # Errors
+    /// `Io` errors, and the `Capture::parse` errors.
+    #[cfg(feature = "std")]
+    pub fn open(path: impl AsRef<std::path::Path>) -> Result<Self, FrameError> {
+        Self::parse(&std::fs::read(path)?)
+    }
+
+    /// Flows in the order of their first packet.
+    #[must_use]
+    pub fn flows(&self) -> &[Flow] {
+        &self.flows
+    }
+
+    #[must_use]
+    pub fn flow(&self, key: &FlowKey) -> Option<&Flow> {
+        self.flows.iter().find(|flow| flow.key == *key)
+    }
+
+    /// Packets read from the file.
+    #[must_use]
+    pub fn packets(&self) -> usize {
+        self.packets
+    }
+
+    /// Packets without a TCP or UDP payload to extract.
+    #[must_use]
+    pub fn skipped(&self) -> usize {
+        self.skipped
+    }
+
+    fn add_packet(&mut self, link_type: u32, data: &[u8]) {
+        self.packets += 1;
+        let Some((key, seq, syn, payload)) = decode_link(link_type, data) else {
+            self.skipped += 1;
+            return;
+        };
+
+        let index = match self.flows.iter().position(|flow| flow.key == key) {
+            Some(index) => index,
+            None => {
+                self.flows.push(Flow {
+                    key,
+                    isn: None,
+                    segments: vec![],
+                });
+                self.flows.len() - 1
+            }
+        };
+        let flow = &mut self.flows[index];
+        if syn {
+            flow.isn = Some(seq.wrapping_add(1));
+        }
+        if !payload.is_empty() {
+            flow.segments.push(Segment {
+                // a SYN consumes one sequence number
+                seq: seq.wrapping_add(u32::from(syn)),
+                payload: payload.to_vec(),
+            });
+        }
+    }
+}
+
+fn invalid(reason: &str) -> FrameError {
+    FrameError::ParsingError(format!("capture file : {reason}"))
+}
+
+#[derive(Clone, Copy)]
+enum Endian {
+    Little,
+    Big,
+}
+
+impl Endian {
+    fn u16(self, data: &[u8], at: usize) -> Option<u16> {
+        let bytes = *data.get(at..at + 2)?.first_chunk::<2>()?;
+        Some(match self {
+            Endian::Little => u16::from_le_bytes(bytes),
+            Endian::Big => u16::from_be_bytes(bytes),
+        })
+    }
+    fn u32(self, data: &[u8], at: usize) -> Option<u32> {
+        let bytes = *data.get(at..at + 4)?.first_chunk::<4>()?;
+        Some(match self {
+            Endian::Little => u32::from_le_bytes(bytes),
+            Endian::Big => u32::from_be_bytes(bytes),
+        })
+    }
+}
+
+fn read_pcap(file: &[u8], capture: &mut Capture) -> Result<(), FrameError> {
+    let endian = match Endian::Little.u32(file, 0) {
+        Some(PCAP_MAGIC_MICROS | PCAP_MAGIC_NANOS) => Endian::Little,
+        Some(magic) if [PCAP_MAGIC_MICROS, PCAP_MAGIC_NANOS].contains(&magic.swap_bytes()) => {
+            Endian::Big
+        }
+        _ => return Err(invalid("neither pcap nor pcapng")),
+    };
+    let link_type = endian
+        .u32(file, 20)
+        .ok_or_else(|| invalid("truncated pcap header"))?;
+
+    let mut at = PCAP_HDR_SIZE;
+    while at < file.len() {
+        let captured = endian
+            .u32(file, at + 8)
+            .ok_or_else(|| invalid("truncated pcap record header"))?
+            as usize;
+        let data = file
+            .get(at + PCAP_RECORD_HDR_SIZE..at + PCAP_RECORD_HDR_SIZE + captured)
+            .ok_or_else(|| invalid("truncated pcap record"))?;
+        capture.add_packet(link_type, data);
+        at += PCAP_RECORD_HDR_SIZE + captured;
+    }
+    Ok(())
+}
+
+fn read_pcapng(file: &[u8], capture: &mut Capture) -> Result<(), FrameError> {
+    let mut endian = Endian::Little;
+    // link type of each interface of the current section
+    let mut interfaces: Vec<u32> = vec![];
+
+    let mut at = 0;
+    while at < file.len() {
+        let block_type = endian
+            .u32(file, at)
+            .ok_or_else(|| invalid("truncated pcapng block"))?;
+        if block_type == PCAPNG_SECTION_HEADER {
+            // the byte order magic tells the endianness of the whole section
+            endian = match Endian::Little.u32(file, at + 8) {
+                Some(PCAPNG_BYTE_ORDER_MAGIC) => Endian::Little,
+                Some(magic) if magic.swap_bytes() == PCAPNG_BYTE_ORDER_MAGIC => Endian::Big,
+                _ => return Err(invalid("bad pcapng byte order magic")),
+            };
+            interfaces.clear();
+        }
+        let block_len = endian
+            .u32(file, at + 4)
+            .ok_or_else(|| invalid("truncated pcapng block"))? as usize;
+        if block_len < 12 || !block_len.is_multiple_of(4) {
+            return Err(invalid("bad pcapng block len"));
+        }
+        let block = file
+            .get(at..at + block_len)
+            .ok_or_else(|| invalid("truncated pcapng block"))?;
+        let body = &block[8..block_len - 4];
+
+        match block_type {
+            PCAPNG_INTERFACE_DESCRIPTION => {
+                let link_type = endian
+                    .u16(body, 0)
+                    .ok_or_else(|| invalid("truncated interface description"))?;
+                interfaces.push(u32::from(link_type));
+            }
+            PCAPNG_ENHANCED_PACKET => {
+                let (Some(interface), Some(captured)) = (endian.u32(body, 0), endian.u32(body, 12))
+                else {
+                    return Err(invalid("truncated enhanced packet"));
+                };
+                let link_type = *interfaces
+                    .get(interface as usize)
+                    .ok_or_else(|| invalid("packet on an undescribed interface"))?;
+                let data = body
+                    .get(20..20 + captured as usize)
+                    .ok_or_else(|| invalid("truncated enhanced packet"))?;
+                capture.add_packet(link_type, data);
+            }
+            PCAPNG_SIMPLE_PACKET => {
+                let link_type = *interfaces
+                    .first()
+                    .ok_or_else(|| invalid("packet on an undescribed interface"))?;
+                // the original len, the packet may be cut by the snap len
+                let original = endian
+                    .u32(body, 0)
+                    .ok_or_else(|| invalid("truncated simple packet"))?;
+                let data = &body[4..];
+                capture.add_packet(link_type, &data[..data.len().min(original as usize)]);
+            }
+            // statistics, name resolution, custom blocks...
+            _ => {}
+        }
+        at += block_len;
+    }
+    Ok(())
+}
+
+type Decoded<'a> = (FlowKey, u32, bool, &'a [u8]);
+
+fn decode_link(link_type: u32, data: &[u8]) -> Option<Decoded<'_>> {
+    match link_type {
+        LINKTYPE_ETHERNET => {
+            let mut ether_type = u16::from_be_bytes(*data.get(12..14)?.first_chunk()?);
+            let mut at = 14;
+            if ether_type == ETHERTYPE_VLAN {
+                ether_type = u16::from_be_bytes(*data.get(16..18)?.first_chunk()?);
+                at = 18;
+            }
+            match ether_type {
+                ETHERTYPE_IPV4 | ETHERTYPE_IPV6 => decode_ip(data.get(at..)?),
+                _ => None,
+            }
+        }
+        LINKTYPE_LINUX_SLL => match u16::from_be_bytes(*data.get(14..16)?.first_chunk()?) {
+            ETHERTYPE_IPV4 | ETHERTYPE_IPV6 => decode_ip(data.get(16..)?),
+            _ => None,
+        },
+        // the address family is in the capturing host byte order, the IP version says it all
+        LINKTYPE_NULL => decode_ip(data.get(4..)?),
+        LINKTYPE_RAW | LINKTYPE_IPV4 | LINKTYPE_IPV6 => decode_ip(data),
+        _ => None,
+    }
+}
+
+fn decode_ip(data: &[u8]) -> Option<Decoded<'_>> {
+    match data.first()? >> 4 {
+        4 => {
+            let hdr_len = usize::from(data.first()? & 0x0f) * 4;
+            let total_len = usize::from(u16::from_be_bytes(*data.get(2..4)?.first_chunk()?));
+            let fragment = u16::from_be_bytes(*data.get(6..8)?.first_chunk()?);
+            // more fragments flag or fragment offset : not reassembled
+            if fragment & 0x3fff != 0 {
+                return None;
+            }
+            let src: [u8; 4] = *data.get(12..16)?.first_chunk()?;
+            let dst: [u8; 4] = *data.get(16..20)?.first_chunk()?;
+            // the capture can be cut by the snap len, or padded by the link
+            let payload = data.get(hdr_len..total_len.min(data.len()))?;
+            decode_transport(
+                data[9],
+                IpAddr::V4(Ipv4Addr::from(src)),
+                IpAddr::V4(Ipv4Addr::from(dst)),
+                payload,
+            )
+        }
+        6 => {
+            let payload_len = usize::from(u16::from_be_bytes(*data.get(4..6)?.first_chunk()?));
+            let src: [u8; 16] = *data.get(8..24)?.first_chunk()?;
+            let dst: [u8; 16] = *data.get(24..40)?.first_chunk()?;
+            let mut next_header = data[6];
+            let mut payload = data.get(40..(40 + payload_len).min(data.len()))?;
+            // hop-by-hop, routing and destination options
+            while matches!(next_header, 0 | 43 | 60) {
+                let len = (usize::from(*payload.get(1)?) + 1) * 8;
+                next_header = *payload.first()?;
+                payload = payload.get(len..)?;
+            }
+            decode_transport(
+                next_header,
+                IpAddr::V6(Ipv6Addr::from(src)),
+                IpAddr::V6(Ipv6Addr::from(dst)),
+                payload,
+            )
+        }
+        _ => None,
+    }
+}
+
+fn decode_transport(protocol: u8, src: IpAddr, dst: IpAddr, data: &[u8]) -> Option<Decoded<'_>> {
+    let src_port = u16::from_be_bytes(*data.get(0..2)?.first_chunk()?);
+    let dst_port = u16::from_be_bytes(*data.get(2..4)?.first_chunk()?);
+    let (transport, seq, syn, payload) = match protocol {
+        IP_PROTO_TCP => {
+            let seq = u32::from_be_bytes(*data.get(4..8)?.first_chunk()?);
+            let hdr_len = usize::from(data.get(12)? >> 4) * 4;
+            let syn = data.get(13)? & TCP_FLAG_SYN != 0;
+            (Transport::Tcp, seq, syn, data.get(hdr_len..)?)
+        }
+        IP_PROTO_UDP => (Transport::Udp, 0, false, data.get(8..)?),
+        _ => return None,
+    };
+    let key = FlowKey {
+        transport,
+        src: SocketAddr::new(src, src_port),
+        dst: SocketAddr::new(dst, dst_port),
+    };
+    Some((key, seq, syn, payload))
+}
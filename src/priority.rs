@@ -0,0 +1,170 @@
+use alloc::{collections::VecDeque, format, vec, vec::Vec};
+
+use crate::{
+    error::FrameError,
+    extended::{MAX_CHUNK_LEN, encode_chunk},
+    stream_frame::FrameWriter,
+};
+
+/// Writer side queue holding framed messages in priority classes, drained as packets.
+///
+/// Class 0 is the most urgent. By default, packets are cut in the byte stream exactly like
+/// `prepend_frame` output would be, so the receivers parse them as usual : a frame spreads over
+/// as many packets as needed and is finished before the next one starts. Priority applies at
+/// frame boundaries, an urgent message waits at most for the end of the frame being sent.
+///
+/// [`PriorityFrameQueue::with_chunk_size`] opts into a different wire format : the frames
+/// longer than the chunk size are sent as chunk frames (see `chunk_frame`) and priority applies
+/// between chunks, an urgent message waits at most for the end of the chunk being sent. The
+/// receivers have to reassemble the chunks with a 0.3 `ParserState` : a 0.2 peer can't read
+/// them, and the raw parser hands them out as `ParsedStreamData::Chunk`.
+///
+/// Starvation protection : a waiting class passed over `max_skips` times in a row goes next.
+///
+/// ```rust
+/// use stream_framer::PriorityFrameQueue;
+///
+/// let mut queue = PriorityFrameQueue::new(2, 8).with_chunk_size(1024);
+/// queue.push(1, vec![0; 4000]).unwrap(); // bulk, sent in 4 chunks
+/// queue.push(0, b"urgent".to_vec()).unwrap();
+///
+/// while let Some(packet) = queue.next_packet(1200) {
+///     // send packet
+/// }
+/// ```
+#[derive(Debug)]
+pub struct PriorityFrameQueue {
+    classes: Vec<VecDeque<Vec<u8>>>,
+    // times in a row each class was passed over while not empty
+    skips: Vec<u32>,
+    max_skips: u32,
+    // frames longer than this are sent in chunks
+    chunk_size: Option<usize>,
+    // bytes of the first frame of each class already sent in chunks
+    chunked: Vec<usize>,
+    // (frame or chunk, bytes already drained, the frame left its class) of the frame being sent
+    current: Option<(Vec<u8>, usize, bool)>,
+}
+
+impl PriorityFrameQueue {
+    /// `classes` priority classes (at least 1).
+    #[must_use]
+    pub fn new(classes: usize, max_skips: u32) -> Self {
+        let classes = classes.max(1);
+        Self {
+            classes: vec![VecDeque::new(); classes],
+            skips: vec![0; classes],
+            max_skips,
+            chunk_size: None,
+            chunked: vec![0; classes],
+            current: None,
+        }
+    }
+
+    /// Send the frames longer than `chunk_size` bytes (at least 1) as chunks of that size. The
+    /// chunks of a class use its index as chunk stream.
+    ///
+    /// This changes the wire format : only receivers reassembling with a `ParserState` read
+    /// the chunked frames back.
+    #[must_use]
+    pub fn with_chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = Some(chunk_size.clamp(1, MAX_CHUNK_LEN));
+        self
+    }
+
+    /// Frame `payload` and queue it in `class`.
+    /// # Errors
+    /// `TypeCapacity` if `class` doesn't exist, or the `FrameWriter` errors.
+    pub fn push(&mut self, class: usize, payload: Vec<u8>) -> Result<(), FrameError> {
+        self.push_frame(class, payload.prepend_frame()?)
+    }
+
+    /// Queue an already framed message (e.g. `ControlFrame::encode` output) in `class`.
+    /// # Errors
+    /// `TypeCapacity` if `class` doesn't exist.
+    pub fn push_frame(&mut self, class: usize, frame: Vec<u8>) -> Result<(), FrameError> {
+        let classes = self.classes.len();
+        let Some(queue) = self.classes.get_mut(class) else {
+            return Err(FrameError::TypeCapacity(format!(
+                "priority class [{class}] out of [{classes}] classes"
+            )));
+        };
+        queue.push_back(frame);
+        Ok(())
+    }
+
+    /// Frames waiting, the one being sent included.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        let current = self.current.as_ref().is_some_and(|(_, _, left)| *left);
+        self.classes.iter().map(VecDeque::len).sum::<usize>() + usize::from(current)
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Next packet of at most `mtu` bytes (at least 1), `None` once the queue is drained.
+    pub fn next_packet(&mut self, mtu: usize) -> Option<Vec<u8>> {
+        let mtu = mtu.max(1);
+        let mut packet = Vec::with_capacity(mtu);
+
+        while packet.len() < mtu {
+            let (frame, drained, left) = match self.current.take() {
+                Some(current) => current,
+                None => match self.next_frame() {
+                    Some((frame, left)) => (frame, 0, left),
+                    None => break,
+                },
+            };
+            let n = (mtu - packet.len()).min(frame.len() - drained);
+            packet.extend_from_slice(&frame[drained..drained + n]);
+
+            if drained + n < frame.len() {
+                self.current = Some((frame, drained + n, left));
+            }
+        }
+
+        if packet.is_empty() {
+            None
+        } else {
+            Some(packet)
+        }
+    }
+
+    // frame or chunk of the highest class not empty, unless a class starves, and whether it
+    // ends the first frame of the class
+    fn next_frame(&mut self) -> Option<(Vec<u8>, bool)> {
+        let starving = (0..self.classes.len())
+            .find(|c| !self.classes[*c].is_empty() && self.skips[*c] >= self.max_skips);
+        let class =
+            starving.or_else(|| (0..self.classes.len()).find(|c| !self.classes[*c].is_empty()))?;
+
+        for (c, queue) in self.classes.iter().enumerate() {
+            if c == class {
+                self.skips[c] = 0;
+            } else if c > class && !queue.is_empty() {
+                self.skips[c] += 1;
+            }
+        }
+
+        let frame = self.classes[class].front()?;
+        let sent = self.chunked[class];
+        match self.chunk_size {
+            Some(chunk_size) if sent > 0 || frame.len() > chunk_size => {
+                let end = frame.len().min(sent + chunk_size);
+                // the chunk stream is the class index, far below u32::MAX in practice
+                let chunk = encode_chunk(class as u32, end < frame.len(), &frame[sent..end]);
+                if end < frame.len() {
+                    self.chunked[class] = end;
+                    return Some((chunk, false));
+                }
+                self.chunked[class] = 0;
+                self.classes[class].pop_front();
+                Some((chunk, true))
+            }
+            _ => self.classes[class].pop_front().map(|frame| (frame, true)),
+        }
+    }
+}
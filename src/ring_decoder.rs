@@ -0,0 +1,208 @@
+use alloc::{boxed::Box, vec, vec::Vec};
+
+use crate::{
+    error::FrameError,
+    stream_frame::{HDR_SIZE, MAGIC_PREFIX, decode_plain_header},
+};
+
+/// Frame decoder reassembling frames inside a fixed-capacity circular buffer.
+///
+/// Memory is allocated once, in [`RingFrameDecoder::with_capacity`]. [`RingFrameDecoder::push`]
+/// only accepts the bytes that fit, the caller keeps the rest until frames are consumed with
+/// [`RingFrameDecoder::next_frame`] (backpressure). Frames are borrowed from the ring, as one or
+/// two slices when the frame wraps around its end.
+///
+/// ```rust
+/// use stream_framer::{FrameWriter, RingFrameDecoder};
+///
+/// let mut decoder = RingFrameDecoder::with_capacity(64);
+/// let stream = b"ring".to_vec().prepend_frame().unwrap();
+///
+/// assert_eq!(decoder.push(&stream), stream.len());
+///
+/// let frame = decoder.next_frame().unwrap().expect("a complete frame");
+/// assert_eq!(frame.to_vec(), b"ring");
+/// ```
+#[derive(Debug)]
+pub struct RingFrameDecoder {
+    buf: Box<[u8]>,
+    // read position
+    head: usize,
+    // bytes stored from head
+    len: usize,
+    // skip the headers refused instead of failing
+    resync: bool,
+}
+
+/// A frame body borrowed from a [`RingFrameDecoder`], `second` is empty unless the body wraps
+/// around the end of the ring.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RingFrame<'a> {
+    pub first: &'a [u8],
+    pub second: &'a [u8],
+}
+
+impl RingFrame<'_> {
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.first.len() + self.second.len()
+    }
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    #[must_use]
+    pub fn to_vec(&self) -> Vec<u8> {
+        [self.first, self.second].concat()
+    }
+}
+
+impl RingFrameDecoder {
+    /// The largest frame body accepted is `capacity - HDR_SIZE`.
+    /// # Panics
+    /// If `capacity` is lower than `HDR_SIZE` : such a ring could never hold a header, so
+    /// [`RingFrameDecoder::next_frame`] would wait for it forever.
+    #[must_use]
+    pub fn with_capacity(capacity: usize) -> Self {
+        assert!(
+            capacity >= HDR_SIZE,
+            "a ring of [{capacity}] bytes can't hold a [{HDR_SIZE}] bytes header"
+        );
+        Self {
+            buf: vec![0; capacity].into_boxed_slice(),
+            head: 0,
+            len: 0,
+            resync: false,
+        }
+    }
+
+    /// Skip a header refused (no magic prefix, or an extended frame) up to the next magic
+    /// prefix after its first byte instead of returning an error, so that the decoder recovers
+    /// from lost or damaged bytes on its own.
+    #[must_use]
+    pub fn with_resync(mut self) -> Self {
+        self.resync = true;
+        self
+    }
+
+    #[must_use]
+    pub fn capacity(&self) -> usize {
+        self.buf.len()
+    }
+    /// Bytes buffered and not consumed yet.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+    /// Bytes that the next [`RingFrameDecoder::push`] can accept.
+    #[must_use]
+    pub fn free(&self) -> usize {
+        self.buf.len() - self.len
+    }
+    #[must_use]
+    pub fn is_full(&self) -> bool {
+        self.free() == 0
+    }
+
+    /// Drop every buffered byte.
+    pub fn clear(&mut self) {
+        self.head = 0;
+        self.len = 0;
+    }
+
+    /// Copy as much of `data` as fits and return the number of bytes accepted. The caller has to
+    /// consume frames before pushing the remaining bytes.
+    pub fn push(&mut self, data: &[u8]) -> usize {
+        let accepted = data.len().min(self.free());
+        let capacity = self.buf.len();
+        let tail = (self.head + self.len) % capacity;
+
+        let until_end = (capacity - tail).min(accepted);
+        self.buf[tail..tail + until_end].copy_from_slice(&data[..until_end]);
+        self.buf[..accepted - until_end].copy_from_slice(&data[until_end..accepted]);
+
+        self.len += accepted;
+        accepted
+    }
+
+    /// Return the next complete frame body, or `None` if more bytes are needed. Never panics,
+    /// whatever the bytes pushed.
+    /// # Errors
+    /// Returns a `ParsingError` if the buffered bytes don't start with the magic prefix or with
+    /// an extended frame (control frames are not handled by this decoder), or
+    /// `FrameTooLarge` if the announced body can never fit in the ring, checked as soon as the
+    /// header is buffered so that a full ring never waits for bytes it can't accept. The
+    /// buffered bytes are dropped in both cases. With [`RingFrameDecoder::with_resync`], the
+    /// headers refused are skipped instead of returning a `ParsingError`.
+    pub fn next_frame(&mut self) -> Result<Option<RingFrame<'_>>, FrameError> {
+        let body_len = loop {
+            if self.len < HDR_SIZE {
+                return Ok(None);
+            }
+            let mut hdr = [0u8; HDR_SIZE];
+            for (i, byte) in hdr.iter_mut().enumerate() {
+                *byte = self.byte(i);
+            }
+            match decode_plain_header(&hdr) {
+                Ok(body_len) => break body_len,
+                Err(_) if self.resync => self.skip_header(),
+                Err(e) => {
+                    self.clear();
+                    return Err(e);
+                }
+            }
+        };
+        if body_len > self.buf.len() - HDR_SIZE {
+            self.clear();
+            return Err(FrameError::FrameTooLarge {
+                len: body_len,
+                capacity: self.buf.len() - HDR_SIZE,
+            });
+        }
+        if self.len < HDR_SIZE + body_len {
+            return Ok(None);
+        }
+
+        let start = (self.head + HDR_SIZE) % self.buf.len();
+        let first_len = (self.buf.len() - start).min(body_len);
+        let second_len = body_len - first_len;
+
+        self.len -= HDR_SIZE + body_len;
+        self.head = if self.len == 0 {
+            // start again from the beginning, fewer frames will wrap
+            0
+        } else {
+            (start + body_len) % self.buf.len()
+        };
+
+        Ok(Some(RingFrame {
+            first: &self.buf[start..start + first_len],
+            second: &self.buf[..second_len],
+        }))
+    }
+
+    // the `i`th buffered byte
+    fn byte(&self, i: usize) -> u8 {
+        self.buf[(self.head + i) % self.buf.len()]
+    }
+
+    // drop the buffered bytes up to the next one, the first excluded, that may start a magic
+    // prefix
+    fn skip_header(&mut self) {
+        let next = (1..self.len).find(|at| {
+            let len = (self.len - at).min(MAGIC_PREFIX.len());
+            (0..len).all(|i| self.byte(at + i) == MAGIC_PREFIX[i])
+        });
+        match next {
+            Some(at) => {
+                self.head = (self.head + at) % self.buf.len();
+                self.len -= at;
+            }
+            None => self.clear(),
+        }
+    }
+}
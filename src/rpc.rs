@@ -0,0 +1,297 @@
+//! Request/response over tagged frames.
+//!
+//! A request is a data frame carrying a correlation id, the reply carries the same id and the
+//! reply flag. The endpoint is transport agnostic : frames go out through the `send` closure and
+//! the caller feeds what its parser returns to [`RpcEndpoint::handle_incoming`].
+
+use std::{
+    collections::HashMap,
+    future::Future,
+    pin::Pin,
+    sync::{
+        Arc, Condvar, Mutex, MutexGuard,
+        atomic::{AtomicU64, Ordering},
+    },
+    task::{Context, Poll, Waker},
+    time::{Duration, Instant},
+};
+
+use crate::{error::FrameError, extended::FrameTags, stream_frame::ParsedStreamData};
+
+#[derive(Debug, Default)]
+struct Slot {
+    reply: Option<Vec<u8>>,
+    cancelled: bool,
+    waker: Option<Waker>,
+}
+
+#[derive(Debug, Default)]
+struct Pending {
+    slots: Mutex<HashMap<u64, Slot>>,
+    resolved: Condvar,
+}
+
+impl Pending {
+    fn lock(&self) -> MutexGuard<'_, HashMap<u64, Slot>> {
+        // a panicking waiter can't leave the map inconsistent
+        self.slots
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+
+    /// Store the outcome of request `id` and wake whoever waits on it.
+    fn resolve(&self, id: u64, outcome: impl FnOnce(&mut Slot)) -> bool {
+        let mut slots = self.lock();
+        let Some(slot) = slots.get_mut(&id) else {
+            return false;
+        };
+        outcome(slot);
+        if let Some(waker) = slot.waker.take() {
+            waker.wake();
+        }
+        self.resolved.notify_all();
+        true
+    }
+}
+
+/// What [`RpcEndpoint::handle_incoming`] hands back to the caller.
+pub enum RpcEvent {
+    /// The peer expects an answer through [`RpcEndpoint::respond`] with this `id`.
+    Request { id: u64, payload: Vec<u8> },
+    /// Anything that is neither a request nor a reply (plain data, control frames...).
+    Other(ParsedStreamData),
+}
+
+/// Sends requests and matches the replies with the [`RpcHandle`] waiting for them.
+///
+/// ```rust
+/// use stream_framer::{FrameParser, RpcEndpoint, RpcEvent};
+/// use std::sync::mpsc;
+///
+/// let (to_server, from_client) = mpsc::channel();
+/// let (to_client, from_server) = mpsc::channel();
+///
+/// let client = RpcEndpoint::new(move |frame| Ok(to_server.send(frame).unwrap()));
+/// let server = RpcEndpoint::new(move |frame| Ok(to_client.send(frame).unwrap()));
+///
+/// let handle = client.request(b"ping?").unwrap();
+///
+/// for parsed in from_client.recv().unwrap().parse_frame_header(None, None).unwrap() {
+///     if let Some(RpcEvent::Request { id, payload }) = server.handle_incoming(parsed) {
+///         assert_eq!(payload, b"ping?");
+///         server.respond(id, b"pong!").unwrap();
+///     }
+/// }
+/// for parsed in from_server.recv().unwrap().parse_frame_header(None, None).unwrap() {
+///     assert!(client.handle_incoming(parsed).is_none());
+/// }
+/// assert_eq!(handle.wait().unwrap(), b"pong!");
+/// ```
+pub struct RpcEndpoint<S> {
+    send: S,
+    next_id: AtomicU64,
+    pending: Arc<Pending>,
+}
+
+impl<S: Fn(Vec<u8>) -> Result<(), FrameError>> RpcEndpoint<S> {
+    /// `send` writes a whole frame to the transport.
+    pub fn new(send: S) -> Self {
+        Self {
+            send,
+            next_id: AtomicU64::new(0),
+            pending: Arc::default(),
+        }
+    }
+
+    /// Send `payload` with a fresh correlation id, the handle resolves with the reply payload.
+    /// # Errors
+    /// Forwards the encoding and `send` errors, nothing is left pending in this case.
+    pub fn request(&self, payload: &[u8]) -> Result<RpcHandle, FrameError> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let frame = FrameTags {
+            correlation_id: Some(id),
+            ..FrameTags::default()
+        }
+        .encode(payload)?;
+
+        // registered first, the reply may come back before `send` returns
+        self.pending.lock().insert(id, Slot::default());
+        let handle = RpcHandle {
+            id,
+            pending: self.pending.clone(),
+        };
+        (self.send)(frame)?;
+        Ok(handle)
+    }
+
+    /// Answer the request `id` received from the peer.
+    /// # Errors
+    /// Forwards the encoding and `send` errors.
+    pub fn respond(&self, id: u64, payload: &[u8]) -> Result<(), FrameError> {
+        let frame = FrameTags {
+            correlation_id: Some(id),
+            reply: true,
+            topic: None,
+        }
+        .encode(payload)?;
+        (self.send)(frame)
+    }
+
+    /// Resolve the pending request a reply belongs to, or hand the frame back to the caller.
+    ///
+    /// Returns `None` for replies, including the late ones whose request timed out or was
+    /// cancelled (they are dropped). Requests carrying a topic are handed back as `Other`, for a
+    /// `TopicRouter`.
+    pub fn handle_incoming(&self, parsed: ParsedStreamData) -> Option<RpcEvent> {
+        match parsed {
+            ParsedStreamData::Tagged(
+                FrameTags {
+                    correlation_id: Some(id),
+                    reply: true,
+                    ..
+                },
+                payload,
+            ) => {
+                self.pending.resolve(id, |slot| slot.reply = Some(payload));
+                None
+            }
+            ParsedStreamData::Tagged(
+                FrameTags {
+                    correlation_id: Some(id),
+                    reply: false,
+                    topic: None,
+                },
+                payload,
+            ) => Some(RpcEvent::Request { id, payload }),
+            other => Some(RpcEvent::Other(other)),
+        }
+    }
+
+    /// Cancel the request `id`, its handle resolves with `FrameError::Cancelled`. Returns
+    /// false if the request is not pending anymore.
+    pub fn cancel(&self, id: u64) -> bool {
+        self.pending.resolve(id, |slot| slot.cancelled = true)
+    }
+
+    /// Requests still waiting for their reply.
+    #[must_use]
+    pub fn pending(&self) -> usize {
+        self.pending.lock().len()
+    }
+}
+
+impl<S> Drop for RpcEndpoint<S> {
+    fn drop(&mut self) {
+        // nobody is left to deliver the replies
+        let mut slots = self.pending.lock();
+        for slot in slots.values_mut() {
+            slot.cancelled = true;
+            if let Some(waker) = slot.waker.take() {
+                waker.wake();
+            }
+        }
+        self.pending.resolved.notify_all();
+    }
+}
+
+impl<S> core::fmt::Debug for RpcEndpoint<S> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("RpcEndpoint")
+            .field("next_id", &self.next_id)
+            .field("pending", &self.pending)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A request waiting for its reply, either blocking ([`RpcHandle::wait`],
+/// [`RpcHandle::wait_timeout`]) or awaited as a future.
+///
+/// Dropping the handle cancels the request : a late reply is dropped.
+#[derive(Debug)]
+pub struct RpcHandle {
+    id: u64,
+    pending: Arc<Pending>,
+}
+
+impl RpcHandle {
+    /// Correlation id of the request.
+    #[must_use]
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Block until the reply arrives.
+    /// # Errors
+    /// `Cancelled` if the request was cancelled or the endpoint dropped.
+    pub fn wait(self) -> Result<Vec<u8>, FrameError> {
+        let mut slots = self.pending.lock();
+        loop {
+            if let Some(outcome) = take_outcome(&mut slots, self.id) {
+                return outcome;
+            }
+            slots = self
+                .pending
+                .resolved
+                .wait(slots)
+                .unwrap_or_else(std::sync::PoisonError::into_inner);
+        }
+    }
+
+    /// Block until the reply arrives or `timeout` elapses.
+    /// # Errors
+    /// `Timeout` if no reply arrived in time, `Cancelled` if the request was cancelled or the
+    /// endpoint dropped.
+    pub fn wait_timeout(self, timeout: Duration) -> Result<Vec<u8>, FrameError> {
+        let deadline = Instant::now() + timeout;
+        let mut slots = self.pending.lock();
+        loop {
+            if let Some(outcome) = take_outcome(&mut slots, self.id) {
+                return outcome;
+            }
+            let Some(left) = deadline.checked_duration_since(Instant::now()) else {
+                return Err(FrameError::Timeout);
+            };
+            slots = self
+                .pending
+                .resolved
+                .wait_timeout(slots, left)
+                .unwrap_or_else(std::sync::PoisonError::into_inner)
+                .0;
+        }
+    }
+
+    /// Give up on the reply.
+    pub fn cancel(self) {}
+}
+
+fn take_outcome(slots: &mut HashMap<u64, Slot>, id: u64) -> Option<Result<Vec<u8>, FrameError>> {
+    let Some(slot) = slots.get_mut(&id) else {
+        return Some(Err(FrameError::Cancelled));
+    };
+    if let Some(reply) = slot.reply.take() {
+        return Some(Ok(reply));
+    }
+    slot.cancelled.then_some(Err(FrameError::Cancelled))
+}
+
+/// Resolves with the reply. There is no timeout here, use the one of the async runtime.
+impl Future for RpcHandle {
+    type Output = Result<Vec<u8>, FrameError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut slots = self.pending.lock();
+        if let Some(outcome) = take_outcome(&mut slots, self.id) {
+            return Poll::Ready(outcome);
+        }
+        if let Some(slot) = slots.get_mut(&self.id) {
+            slot.waker = Some(cx.waker().clone());
+        }
+        Poll::Pending
+    }
+}
+
+impl Drop for RpcHandle {
+    fn drop(&mut self) {
+        self.pending.lock().remove(&self.id);
+    }
+}
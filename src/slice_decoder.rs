@@ -0,0 +1,158 @@
+use crate::{
+    error::FrameError,
+    stream_frame::{HDR_SIZE, MAGIC_PREFIX, decode_plain_header, skip_to_magic},
+};
+
+/// Heapless frame decoder reassembling bodies inside a caller-provided buffer.
+///
+/// The buffer bounds the largest body that can be received, the decoder itself never
+/// allocates while decoding (only the error path formats a message).
+///
+/// ```rust
+/// use stream_framer::{FrameWriter, SliceFrameDecoder};
+///
+/// let mut buf = [0u8; 64];
+/// let mut decoder = SliceFrameDecoder::new(&mut buf);
+///
+/// let stream = b"tiny".to_vec().prepend_frame().unwrap();
+/// let mut frames = 0;
+///
+/// for packet in stream.chunks(3) {
+///     decoder
+///         .decode(packet, |body| {
+///             assert_eq!(body, b"tiny");
+///             frames += 1;
+///         })
+///         .unwrap();
+/// }
+/// assert_eq!(frames, 1);
+/// ```
+#[derive(Debug)]
+pub struct SliceFrameDecoder<'buf> {
+    buf: &'buf mut [u8],
+    hdr: [u8; HDR_SIZE],
+    hdr_len: usize,
+    // announced body len, once the header is complete
+    body_len: Option<usize>,
+    received: usize,
+    // skip the headers refused instead of failing the packet
+    resync: bool,
+}
+
+impl<'buf> SliceFrameDecoder<'buf> {
+    pub fn new(buf: &'buf mut [u8]) -> Self {
+        Self {
+            buf,
+            hdr: [0; HDR_SIZE],
+            hdr_len: 0,
+            body_len: None,
+            received: 0,
+            resync: false,
+        }
+    }
+
+    /// Skip a header refused (no magic prefix, or an extended frame) up to the next magic
+    /// prefix after its first byte instead of returning an error, so that the decoder recovers
+    /// from lost or damaged bytes on its own.
+    #[must_use]
+    pub fn with_resync(mut self) -> Self {
+        self.resync = true;
+        self
+    }
+
+    /// Largest frame body this decoder can reassemble.
+    #[must_use]
+    pub fn capacity(&self) -> usize {
+        self.buf.len()
+    }
+
+    /// True if no partial header nor partial body is pending.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.hdr_len == 0
+    }
+
+    /// Drop any partial frame.
+    pub fn reset(&mut self) {
+        self.hdr_len = 0;
+        self.body_len = None;
+        self.received = 0;
+    }
+
+    /// Feed a packet, calling `on_frame` with the body of each frame completed by it. Never
+    /// panics, whatever the packet.
+    /// # Errors
+    /// Returns a `ParsingError` if a header doesn't start with the magic prefix or announces an
+    /// extended frame (control frames are not handled by this decoder), or
+    /// `FrameTooLarge` if an announced body doesn't fit in the buffer. The partial frame and
+    /// the rest of the packet are dropped in both cases. With
+    /// [`SliceFrameDecoder::with_resync`], the headers refused are skipped instead of returning
+    /// a `ParsingError`.
+    pub fn decode<F: FnMut(&[u8])>(
+        &mut self,
+        mut data: &[u8],
+        mut on_frame: F,
+    ) -> Result<(), FrameError> {
+        while !data.is_empty() || self.body_len == Some(0) {
+            let Some(body_len) = self.body_len else {
+                let missing = (HDR_SIZE - self.hdr_len).min(data.len());
+                self.hdr[self.hdr_len..self.hdr_len + missing].copy_from_slice(&data[..missing]);
+                self.hdr_len += missing;
+                data = &data[missing..];
+
+                if self.hdr_len == HDR_SIZE {
+                    let body_len = match decode_plain_header(&self.hdr) {
+                        Ok(body_len) => body_len,
+                        Err(_) if self.resync => {
+                            self.skip_header();
+                            if self.hdr_len == 0 {
+                                data = skip_to_magic(data);
+                            }
+                            continue;
+                        }
+                        Err(e) => {
+                            self.reset();
+                            return Err(e);
+                        }
+                    };
+                    if body_len > self.buf.len() {
+                        self.reset();
+                        return Err(FrameError::FrameTooLarge {
+                            len: body_len,
+                            capacity: self.buf.len(),
+                        });
+                    }
+                    self.body_len = Some(body_len);
+                }
+                continue;
+            };
+
+            let missing = (body_len - self.received).min(data.len());
+            self.buf[self.received..self.received + missing].copy_from_slice(&data[..missing]);
+            self.received += missing;
+            data = &data[missing..];
+
+            if self.received == body_len {
+                on_frame(&self.buf[..body_len]);
+                self.reset();
+            }
+        }
+        Ok(())
+    }
+
+    // keep the header bytes from the next one, its first excluded, that may start a magic prefix
+    fn skip_header(&mut self) {
+        let next = (1..HDR_SIZE).find(|at| {
+            let candidate = &self.hdr[*at..];
+            let len = candidate.len().min(MAGIC_PREFIX.len());
+            candidate[..len] == MAGIC_PREFIX[..len]
+        });
+        match next {
+            Some(at) => {
+                self.hdr.copy_within(at.., 0);
+                self.hdr_len = HDR_SIZE - at;
+            }
+            None => self.hdr_len = 0,
+        }
+    }
+}
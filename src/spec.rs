@@ -0,0 +1,242 @@
+//! The wire format as data.
+//!
+//! Describes what follows the header of an extended frame: the optional fields announced by the
+//! flags, and the payload layout of each kind. `decode_extended` derives the known flags from
+//! these tables and the Wireshark dissector is generated from them, so a field added here shows
+//! up in both.
+
+use crate::{
+    control::ControlFrame,
+    extended::{
+        FLAG_CONTINUED, FLAG_CORRELATION_ID, FLAG_MASKED, FLAG_PADDED, FLAG_REPLY, FLAG_TOPIC_ID,
+        FLAG_TOPIC_NAME, KIND_CHUNK, KIND_CLOSE, KIND_CREDIT, KIND_DATA, KIND_ESCAPED, KIND_PING,
+        KIND_PONG,
+    },
+};
+
+/// How a field is laid out, integers are big endian.
+pub(crate) enum FieldType {
+    U32,
+    /// Shown in hexadecimal (hashes, ids).
+    U32Hex,
+    U64,
+    /// `u16` with named values.
+    Code(&'static [(u16, &'static str)]),
+    /// `u8` len followed by that many UTF-8 bytes.
+    ShortString,
+    /// UTF-8 up to the end of the frame.
+    Text,
+    /// Bytes up to the end of the frame.
+    Bytes,
+}
+
+impl FieldType {
+    /// Size on the wire, `None` when it depends on the content.
+    pub(crate) const fn size(&self) -> Option<usize> {
+        match self {
+            FieldType::Code(_) => Some(2),
+            FieldType::U32 | FieldType::U32Hex => Some(4),
+            FieldType::U64 => Some(8),
+            FieldType::ShortString | FieldType::Text | FieldType::Bytes => None,
+        }
+    }
+}
+
+pub(crate) struct Field {
+    /// Identifier, `snake_case`.
+    pub(crate) name: &'static str,
+    pub(crate) label: &'static str,
+    pub(crate) ty: FieldType,
+}
+
+/// A bit of the extension header flags, and the field it announces.
+pub(crate) struct FlagSpec {
+    pub(crate) bit: u8,
+    pub(crate) name: &'static str,
+    pub(crate) label: &'static str,
+    /// `None` for a flag carrying no field (reply, continued).
+    pub(crate) field: Option<Field>,
+}
+
+/// A frame kind and the layout of its payload.
+pub(crate) struct KindSpec {
+    pub(crate) kind: u8,
+    pub(crate) name: &'static str,
+    pub(crate) payload: &'static [Field],
+}
+
+/// Ordered by bit, the order of their fields on the wire but for the masking key, which comes
+/// first.
+pub(crate) const FLAGS: &[FlagSpec] = &[
+    FlagSpec {
+        bit: FLAG_CORRELATION_ID,
+        name: "correlation_id",
+        label: "Correlation id",
+        field: Some(Field {
+            name: "correlation_id",
+            label: "Correlation id",
+            ty: FieldType::U64,
+        }),
+    },
+    FlagSpec {
+        bit: FLAG_REPLY,
+        name: "reply",
+        label: "Reply",
+        field: None,
+    },
+    FlagSpec {
+        bit: FLAG_TOPIC_NAME,
+        name: "topic_name",
+        label: "Topic name",
+        field: Some(Field {
+            name: "topic_name",
+            label: "Topic name",
+            ty: FieldType::ShortString,
+        }),
+    },
+    FlagSpec {
+        bit: FLAG_TOPIC_ID,
+        name: "topic_id",
+        label: "Topic id",
+        field: Some(Field {
+            name: "topic_id",
+            label: "Topic id",
+            ty: FieldType::U32Hex,
+        }),
+    },
+    FlagSpec {
+        bit: FLAG_MASKED,
+        name: "masked",
+        label: "Masked",
+        field: Some(Field {
+            name: "masking_key",
+            label: "Masking key",
+            ty: FieldType::U32Hex,
+        }),
+    },
+    FlagSpec {
+        bit: FLAG_PADDED,
+        name: "padded",
+        label: "Padded",
+        field: Some(Field {
+            name: "payload_len",
+            label: "Payload length",
+            ty: FieldType::U32,
+        }),
+    },
+    FlagSpec {
+        bit: FLAG_CONTINUED,
+        name: "continued",
+        label: "Continued",
+        field: None,
+    },
+];
+
+const NONCE: &[Field] = &[Field {
+    name: "nonce",
+    label: "Nonce",
+    ty: FieldType::U64,
+}];
+
+pub(crate) const CLOSE_CODES: &[(u16, &str)] = &[
+    (ControlFrame::CLOSE_NORMAL, "normal"),
+    (ControlFrame::CLOSE_GOING_AWAY, "going away"),
+    (ControlFrame::CLOSE_PROTOCOL_ERROR, "protocol error"),
+    (ControlFrame::CLOSE_INTERNAL_ERROR, "internal error"),
+];
+
+pub(crate) const KINDS: &[KindSpec] = &[
+    KindSpec {
+        kind: KIND_DATA,
+        name: "data",
+        payload: &[Field {
+            name: "payload",
+            label: "Payload",
+            ty: FieldType::Bytes,
+        }],
+    },
+    KindSpec {
+        kind: KIND_PING,
+        name: "ping",
+        payload: NONCE,
+    },
+    KindSpec {
+        kind: KIND_PONG,
+        name: "pong",
+        payload: NONCE,
+    },
+    KindSpec {
+        kind: KIND_CLOSE,
+        name: "close",
+        payload: &[
+            Field {
+                name: "code",
+                label: "Code",
+                ty: FieldType::Code(CLOSE_CODES),
+            },
+            Field {
+                name: "reason",
+                label: "Reason",
+                ty: FieldType::Text,
+            },
+        ],
+    },
+    KindSpec {
+        kind: KIND_CREDIT,
+        name: "credit",
+        payload: &[
+            Field {
+                name: "channel",
+                label: "Channel",
+                ty: FieldType::U32,
+            },
+            Field {
+                name: "frames",
+                label: "Frames",
+                ty: FieldType::U32,
+            },
+            Field {
+                name: "bytes",
+                label: "Bytes",
+                ty: FieldType::U32,
+            },
+        ],
+    },
+    // no flags, the whole body after the kind
+    KindSpec {
+        kind: KIND_ESCAPED,
+        name: "escaped",
+        payload: &[Field {
+            name: "body",
+            label: "Escaped body (COBS)",
+            ty: FieldType::Bytes,
+        }],
+    },
+    KindSpec {
+        kind: KIND_CHUNK,
+        name: "chunk",
+        payload: &[
+            Field {
+                name: "stream",
+                label: "Chunk stream",
+                ty: FieldType::U32,
+            },
+            Field {
+                name: "piece",
+                label: "Frame piece",
+                ty: FieldType::Bytes,
+            },
+        ],
+    },
+];
+
+/// Union of the flags in `FLAGS`.
+pub(crate) const fn known_flags() -> u8 {
+    let mut flags = 0;
+    let mut i = 0;
+    while i < FLAGS.len() {
+        flags |= FLAGS[i].bit;
+        i += 1;
+    }
+    flags
+}
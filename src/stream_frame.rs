@@ -3,379 +3,524 @@
 #![allow(clippy::single_match_else)]
 #![allow(clippy::ref_option)]
 
+use alloc::{format, string::ToString};
+
+use crate::error::FrameError;
+
+pub(crate) use stream_frame_parse::skip_to_magic;
 pub use stream_frame_parse::{FrameParser, ParsedStreamData};
-pub use stream_frame_writer::FrameWriter;
+pub use stream_frame_writer::{FrameWriter, encode_into, encoded_len};
+pub(crate) use stream_frame_writer::{
+    encode_header, encode_header_field, frame_from_slices, padded_frame_from_slices,
+};
 pub const HDR_SIZE: usize = 12; // u32
 pub const MAGIC_PREFIX: [u8; 8] = [0x00, 0xF1, 0x01, 0xE4, 0x02, 0xFF, 0x03, 0xDD];
+/// Top bit of the header length field : the body starts with an extension header (control
+/// frames...), see the `extended` module.
+pub const EXTENDED_FLAG: u32 = 0x8000_0000;
+/// Largest body a frame can announce, the length field top bit being `EXTENDED_FLAG`.
+pub const MAX_BODY_LEN: usize = (EXTENDED_FLAG - 1) as usize;
+
+/// Returns the header length field if `hdr` starts with `MAGIC_PREFIX`.
+pub(crate) fn decode_header(hdr: &[u8; HDR_SIZE]) -> Option<usize> {
+    let (magic, encoded_len) = hdr.split_at(MAGIC_PREFIX.len());
+    if magic != MAGIC_PREFIX {
+        return None;
+    }
+    let encoded_len: [u8; 4] = encoded_len.try_into().ok()?;
+    Some(u32::from_be_bytes(encoded_len) as usize)
+}
+
+/// Body len of a plain frame header, for the decoders which don't handle extended frames.
+pub(crate) fn decode_plain_header(hdr: &[u8; HDR_SIZE]) -> Result<usize, FrameError> {
+    match decode_header(hdr) {
+        None => Err(FrameError::ParsingError(format!(
+            "no magic prefix in header [{hdr:?}]"
+        ))),
+        Some(len_field) if is_extended(len_field) => Err(FrameError::ParsingError(
+            "extended frames are not supported by this decoder".to_string(),
+        )),
+        Some(len_field) => Ok(len_field),
+    }
+}
+
+/// Body len announced by a header length field.
+pub(crate) fn body_len(len_field: usize) -> usize {
+    len_field & MAX_BODY_LEN
+}
+
+pub(crate) fn is_extended(len_field: usize) -> bool {
+    len_field & EXTENDED_FLAG as usize != 0
+}
+
+/// What the header of a frame still being received announced, handed out with
+/// `ParsedStreamData::Incompleted` and passed back to the parser with the next packet.
+///
+/// Built from a `usize` it is the body len of a plain frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct AnnouncedLen {
+    body_len: usize,
+    extended: bool,
+}
+
+impl AnnouncedLen {
+    /// Body len of the frame, `EXTENDED_FLAG` excluded.
+    #[must_use]
+    pub const fn body_len(self) -> usize {
+        self.body_len
+    }
+
+    /// True for an extended frame, see the `extended` module.
+    #[must_use]
+    pub const fn is_extended(self) -> bool {
+        self.extended
+    }
+
+    pub(crate) const fn from_field(len_field: usize) -> Self {
+        Self {
+            body_len: len_field & MAX_BODY_LEN,
+            extended: len_field & EXTENDED_FLAG as usize != 0,
+        }
+    }
+
+    // only called once checked to be at most MAX_BODY_LEN
+    pub(crate) const fn to_field(self) -> usize {
+        if self.extended {
+            self.body_len | EXTENDED_FLAG as usize
+        } else {
+            self.body_len
+        }
+    }
+}
+
+impl From<usize> for AnnouncedLen {
+    fn from(body_len: usize) -> Self {
+        Self {
+            body_len,
+            extended: false,
+        }
+    }
+}
 mod stream_frame_writer {
-    use crate::error::FrameError;
+    use alloc::{string::ToString, vec::Vec};
+
+    use crate::{
+        error::FrameError,
+        extended::{PaddingPolicy, encode_padded, pad_frame},
+    };
 
-    use super::MAGIC_PREFIX;
+    use super::{HDR_SIZE, MAGIC_PREFIX, MAX_BODY_LEN};
 
     pub trait FrameWriter {
         /// # Errors
-        /// This returns an errors if the packet length is > to u32 capacity.
+        /// This returns an errors if the packet length is > to `MAX_BODY_LEN`.
         fn prepend_frame_in_place(&mut self) -> Result<(), FrameError>;
         /// # Errors
-        /// This returns an errors if the packet length is > to u32 capacity.
+        /// This returns an errors if the packet length is > to `MAX_BODY_LEN`.
         fn prepend_frame(self) -> Result<Vec<u8>, FrameError>;
+        /// Frame as a padded frame (see `pad_frame`), `PaddingPolicy::None` frames as
+        /// `prepend_frame` does. The crate's buffer types write it in one allocation.
+        /// # Errors
+        /// This returns an errors if the padded body length is > to `MAX_BODY_LEN`.
+        fn prepend_frame_padded(self, policy: PaddingPolicy) -> Result<Vec<u8>, FrameError>
+        where
+            Self: Sized,
+        {
+            pad_frame(&self.prepend_frame()?, policy)
+        }
     }
 
-    impl FrameWriter for Vec<u8> {
-        fn prepend_frame_in_place(&mut self) -> Result<(), FrameError> {
-            // cast to u32 because usize is to large and to suitable (diffence of size between archs)
-            let Ok(p_len) = u32::try_from(self.len()) else {
-                return Err(FrameError::TypeCapacity(
-                    "Failed to get packet len (is > to u32 capacity)".to_string(),
-                ));
-            };
+    /// Size of the frame (header included) carrying a body of `payload_len` bytes.
+    #[must_use]
+    pub const fn encoded_len(payload_len: usize) -> usize {
+        payload_len.saturating_add(HDR_SIZE)
+    }
 
-            let mut frame: Vec<u8> = p_len.to_be_bytes().to_vec();
+    /// Write the header and `payload` at the start of `out`, without allocating.
+    /// Returns the number of bytes written.
+    /// # Errors
+    /// `TypeCapacity` if the payload length is > to `MAX_BODY_LEN`, `BufferTooSmall` if `out` is
+    /// shorter than `encoded_len(payload.len())`.
+    pub fn encode_into(payload: &[u8], out: &mut [u8]) -> Result<usize, FrameError> {
+        let hdr = encode_header(payload.len())?;
+        let needed = encoded_len(payload.len());
+
+        let Some(out) = out.get_mut(..needed) else {
+            return Err(FrameError::BufferTooSmall { needed });
+        };
+        out[..HDR_SIZE].copy_from_slice(&hdr);
+        out[HDR_SIZE..].copy_from_slice(payload);
+
+        Ok(needed)
+    }
 
-            let mut magic_prefix = MAGIC_PREFIX.to_vec();
-            magic_prefix.append(&mut frame);
-            magic_prefix.append(self);
+    // magic prefix followed by the body len as a big endian u32
+    pub(crate) fn encode_header(payload_len: usize) -> Result<[u8; HDR_SIZE], FrameError> {
+        // cast to u32 because usize is to large and to suitable (diffence of size between archs)
+        let Some(p_len) = u32::try_from(payload_len)
+            .ok()
+            .filter(|_| payload_len <= MAX_BODY_LEN)
+        else {
+            return Err(FrameError::TypeCapacity(
+                "Failed to get packet len (is > to MAX_BODY_LEN)".to_string(),
+            ));
+        };
+
+        Ok(encode_header_field(p_len))
+    }
+
+    // the length field can carry EXTENDED_FLAG
+    pub(crate) fn encode_header_field(len_field: u32) -> [u8; HDR_SIZE] {
+        let mut hdr = [0u8; HDR_SIZE];
+        hdr[..MAGIC_PREFIX.len()].copy_from_slice(&MAGIC_PREFIX);
+        hdr[MAGIC_PREFIX.len()..].copy_from_slice(&len_field.to_be_bytes());
+        hdr
+    }
+
+    // frame a body stored in two parts (e.g. the two halves of a ring buffer) in one allocation
+    pub(crate) fn frame_from_slices(first: &[u8], second: &[u8]) -> Result<Vec<u8>, FrameError> {
+        let body_len = first.len() + second.len();
+        let hdr = encode_header(body_len)?;
 
-            *self = magic_prefix;
+        let mut frame = Vec::with_capacity(encoded_len(body_len));
+        frame.extend_from_slice(&hdr);
+        frame.extend_from_slice(first);
+        frame.extend_from_slice(second);
+        Ok(frame)
+    }
+
+    // `frame_from_slices` for a padded frame
+    pub(crate) fn padded_frame_from_slices(
+        first: &[u8],
+        second: &[u8],
+        policy: PaddingPolicy,
+    ) -> Result<Vec<u8>, FrameError> {
+        let mut frame = Vec::new();
+        encode_padded(first, second, policy, &mut frame)?;
+        Ok(frame)
+    }
+
+    impl FrameWriter for Vec<u8> {
+        fn prepend_frame_in_place(&mut self) -> Result<(), FrameError> {
+            let hdr = encode_header(self.len())?;
+
+            self.splice(0..0, hdr);
             Ok(())
         }
         fn prepend_frame(self) -> Result<Vec<u8>, FrameError> {
-            // cast to u32 because usize is to large and to suitable (diffence of size between archs)
-            let Ok(p_len) = u32::try_from(self.len()) else {
-                return Err(FrameError::TypeCapacity(
-                    "Failed to get packet len (is > to u32 capacity)".to_string(),
-                ));
-            };
+            let hdr = encode_header(self.len())?;
 
-            let mut magic_prefix = MAGIC_PREFIX.to_vec();
-            let mut frame: Vec<u8> = p_len.to_be_bytes().to_vec();
-            magic_prefix.append(&mut frame);
-            magic_prefix.extend(self);
+            let mut frame = Vec::with_capacity(encoded_len(self.len()));
+            frame.extend_from_slice(&hdr);
+            frame.extend(self);
 
-            Ok(magic_prefix)
+            Ok(frame)
+        }
+        fn prepend_frame_padded(self, policy: PaddingPolicy) -> Result<Vec<u8>, FrameError> {
+            padded_frame_from_slices(&self, &[], policy)
         }
     }
 }
 
 mod stream_frame_parse {
 
-    use crate::error::FrameError;
+    use alloc::{
+        format,
+        string::{String, ToString},
+        vec,
+        vec::Vec,
+    };
 
-    use super::{HDR_SIZE, MAGIC_PREFIX};
+    use crate::{
+        control::ControlFrame,
+        error::FrameError,
+        extended::{FrameTags, decode_extended},
+        inspect::find_magic_prefix,
+    };
+
+    use super::{
+        AnnouncedLen, HDR_SIZE, MAGIC_PREFIX, MAX_BODY_LEN, body_len, decode_header, is_extended,
+    };
 
-    type BodyLen = usize;
     pub trait FrameParser {
         /// Parse a stream packet
+        ///
+        /// Never panics : malformed packets (bytes from an untrusted peer) as well as
+        /// inconsistent `last_incomplete_reception` / `is_last_header_truncated` state come
+        /// back as an error. `ParserState` carries that state without the caller being able to
+        /// get it wrong.
+        ///
+        /// The parser doesn't track the stream state : frames following a
+        /// `ControlFrame::Close` are returned like any other. `ParserState` refuses data after
+        /// a close.
         /// # Errors
         /// Return a String in case something wrong happened in
         /// slice conversions.
         ///
+        /// `InvalidState` if the state passed is not one a previous call could have handed
+        /// out : both an incomplete body and a truncated header, more body bytes received than
+        /// announced, an announced body len over `MAX_BODY_LEN`, or a truncated header of
+        /// `HDR_SIZE` bytes or more.
         fn parse_frame_header(
             self,
-            last_incomplete_reception: Option<(BodyLen, Vec<u8>)>,
+            last_incomplete_reception: Option<(AnnouncedLen, Vec<u8>)>,
+            is_last_header_truncated: Option<Vec<u8>>,
+        ) -> Result<Vec<ParsedStreamData>, FrameError>;
+
+        /// Same as [`FrameParser::parse_frame_header`], but a header without the magic prefix
+        /// or an extended frame that doesn't decode (an escaped frame damaged in transit...) is
+        /// skipped instead of failing the packet : parsing starts again at the next magic
+        /// prefix after its first byte, bytes from previous packets included.
+        ///
+        /// In a stream of escaped frames (see `escape_frame`), the frame found that way is
+        /// always a real one. In a stream of plain frames, a magic prefix inside a payload can
+        /// be taken for one. A damaged length field announcing more than was sent still holds
+        /// the frames after it until that many bytes arrived.
+        /// # Errors
+        /// `InvalidState` if the state passed is not one a previous call could have handed out.
+        fn parse_frame_header_resyncing(
+            self,
+            last_incomplete_reception: Option<(AnnouncedLen, Vec<u8>)>,
             is_last_header_truncated: Option<Vec<u8>>,
         ) -> Result<Vec<ParsedStreamData>, FrameError>;
     }
 
-    type MessageSize = usize;
+    /// New kinds of frames may be added : match them with a wildcard arm.
+    #[non_exhaustive]
     pub enum ParsedStreamData {
         Completed(Vec<u8>),
-        Incompleted(MessageSize, Vec<u8>),
+        Incompleted(AnnouncedLen, Vec<u8>),
         TruncatedHeader(Vec<u8>), // bool +> end of stream
-    }
-
-    enum HeaderParsing {
-        Completed(usize, Vec<u8>),
-        OneMessageAndRemains((usize, Vec<u8>), Vec<u8>), // (size_first_msg, data_first_message),
-        // remaining data
-        DataTooLittle(Vec<u8>),
-        StartWithNoHeaderAndIncompleted(MessageSize, Vec<u8>), // (no header data bytes
-        // size, data_without_header), Option<(hdr->data_size, next_data)>
-        None,
+        Control(ControlFrame),
+        Tagged(FrameTags, Vec<u8>),
+        /// A piece of a frame cut by `chunk_frame`, `ParserState` reassembles them.
+        Chunk {
+            stream: u32,
+            /// More chunks of the frame follow.
+            continued: bool,
+            piece: Vec<u8>,
+        },
     }
 
     impl FrameParser for Vec<u8> {
-        // decode header, builded with u32 as bytes collection, big endian
         fn parse_frame_header(
-            mut self,
-            mut last_incomplete_reception: Option<(BodyLen, Vec<u8>)>,
-            mut is_last_header_truncated: Option<Vec<u8>>,
+            self,
+            last_incomplete_reception: Option<(AnnouncedLen, Vec<u8>)>,
+            is_last_header_truncated: Option<Vec<u8>>,
         ) -> Result<Vec<ParsedStreamData>, FrameError> {
-            let mut output: Vec<ParsedStreamData> = vec![];
-            let mut data = std::mem::take(&mut self);
-            let data_len = data.len();
-
-            'parse: loop {
-                if data_len == 0 {
-                    break 'parse Ok(output);
-                }
-                let mut start_of_stream = false;
-                // when data is now < HDR_SIZE, implies truncating next header
-                // what if is_last_header_truncated is_some() ? (To resolve)
-                match is_data_size_truncating_next_header(
-                    data,
-                    &last_incomplete_reception,
-                    &mut is_last_header_truncated,
-                ) {
-                    Truncating::Yes(truncated_hdr) => {
-                        if !truncated_hdr.is_empty() {
-                            output.push(ParsedStreamData::TruncatedHeader(truncated_hdr));
-                        }
-                        return Ok(output);
-                    }
-
-                    Truncating::No((data_continue, s_o_s)) => {
-                        data = data_continue;
-                        start_of_stream = s_o_s;
-                    }
-                }
+            self.as_slice()
+                .parse_frame_header(last_incomplete_reception, is_last_header_truncated)
+        }
+        fn parse_frame_header_resyncing(
+            self,
+            last_incomplete_reception: Option<(AnnouncedLen, Vec<u8>)>,
+            is_last_header_truncated: Option<Vec<u8>>,
+        ) -> Result<Vec<ParsedStreamData>, FrameError> {
+            self.as_slice()
+                .parse_frame_header_resyncing(last_incomplete_reception, is_last_header_truncated)
+        }
+    }
 
-                // vec len is already verified to be  > HDR_SIZE
-
-                if start_of_stream {
-                    let (corriged_hdr_len, hdr) =
-                        compose_header_candidat(&data, is_last_header_truncated.take())?;
-
-                    if is_header(&hdr) {
-                        // It starts directly with a header :
-                        // case 0: total_len == body_len,
-                        // case 1: total_len > body_len,
-                        // case 2: total_len < body_len.
-
-                        let encoded_len: [u8; 4] =
-                            if let Ok(e_l) = hdr[MAGIC_PREFIX.len()..].try_into() {
-                                e_l
-                            } else {
-                                return Err(FrameError::ParsingError(
-                                    "error decoding header".to_string(),
-                                ));
-                            };
-                        let body_total_len = u32::from_be_bytes(encoded_len);
-
-                        let body = data[corriged_hdr_len..].to_vec();
-
-                        match has_hdr_first(body_total_len as usize, body) {
-                            // final case, return.
-                            HeaderParsing::Completed(_size, completed_data) => {
-                                output.push(ParsedStreamData::Completed(completed_data));
-                                return Ok(output);
-                            }
-                            // One first message completed, and remaining data, continue parsing
-                            HeaderParsing::OneMessageAndRemains(
-                                (_first_msg_size, first_msg_data),
-                                remaining_data,
-                            ) => {
-                                data = remaining_data; // check if remaining data is > hdr size
-                                output.push(ParsedStreamData::Completed(first_msg_data));
-
-                                continue 'parse;
-                            }
-                            // Final case
-                            HeaderParsing::DataTooLittle(data) => {
-                                // Is inferior to the announced body len : the remaining will be in the
-                                // next server push, which will have no hdr
-
-                                output.push(ParsedStreamData::Incompleted(
-                                    body_total_len as usize,
-                                    data,
-                                ));
-                                return Ok(output);
-                            }
-                            _ => return Err(FrameError::ParsingError("Other case".to_string())),
-                        }
-                    }
-                    // It starts with no hdr.
-                    // case 3: It has 1 header pattern after some bytes.
-                }
-                match has_no_hdr_start(data, last_incomplete_reception.take()) {
-                    Ok(data_parsed) => match data_parsed {
-                        HeaderParsing::OneMessageAndRemains((_size, data_wo_hdr), remaining) => {
-                            data = remaining; // TODO handle case 0.0 => See if hdr is
-                            // truncated
-                            output.push(ParsedStreamData::Completed(data_wo_hdr));
-                        }
-                        // Final case
-                        HeaderParsing::Completed(_size, completed_data) => {
-                            output.push(ParsedStreamData::Completed(completed_data));
-                            return Ok(output);
-                        }
-                        HeaderParsing::StartWithNoHeaderAndIncompleted(message_size, data) => {
-                            // case 2 => if the msg len > stream len
-                            output.push(ParsedStreamData::Incompleted(message_size, data));
-                            return Ok(output);
-                        }
-                        _ => {
-                            return Err(FrameError::ParsingError(
-                                "other case after no header".to_string(),
-                            ));
-                        }
-                    },
-                    Err(data_err) => data = data_err,
-                }
-            }
+    // the packet is only read : the bytes of each completed body are copied out once, the
+    // packet itself is never copied nor split
+    impl FrameParser for &[u8] {
+        fn parse_frame_header(
+            self,
+            last_incomplete_reception: Option<(AnnouncedLen, Vec<u8>)>,
+            is_last_header_truncated: Option<Vec<u8>>,
+        ) -> Result<Vec<ParsedStreamData>, FrameError> {
+            parse_packet(
+                self,
+                last_incomplete_reception,
+                is_last_header_truncated,
+                false,
+            )
+        }
+        fn parse_frame_header_resyncing(
+            self,
+            last_incomplete_reception: Option<(AnnouncedLen, Vec<u8>)>,
+            is_last_header_truncated: Option<Vec<u8>>,
+        ) -> Result<Vec<ParsedStreamData>, FrameError> {
+            parse_packet(
+                self,
+                last_incomplete_reception,
+                is_last_header_truncated,
+                true,
+            )
         }
     }
 
-    type SuffixLen = usize;
-    // verify if there is already a truncated header from the last packet. If yes compose a header
-    // with it, if no, output header size Vec<u8>
-    fn compose_header_candidat(
-        data: &[u8],
+    // with `resync`, a frame refused with a `ParsingError` is skipped : parsing starts again at
+    // the next magic prefix after its first byte
+    fn parse_packet(
+        packet: &[u8],
+        last_incomplete_reception: Option<(AnnouncedLen, Vec<u8>)>,
         is_last_header_truncated: Option<Vec<u8>>,
-    ) -> Result<(SuffixLen, [u8; HDR_SIZE]), FrameError> {
-        let output_res: Result<(SuffixLen, [u8; HDR_SIZE]), FrameError> =
-            match is_last_header_truncated {
-                Some(mut hdr_prefix) => {
-                    let mut hdr_suffix_len = 0;
-                    if hdr_prefix.len() >= HDR_SIZE {
-                        hdr_suffix_len = HDR_SIZE;
-                    } else {
-                        hdr_suffix_len = HDR_SIZE - hdr_prefix.len();
-
-                        if hdr_suffix_len != 0 {
-                            let suffix_slice = data[0..hdr_suffix_len].to_vec();
-                            hdr_prefix.extend(suffix_slice);
-                        } else {
-                            hdr_suffix_len = HDR_SIZE;
-                        }
-                    }
-
-                    let hdr: [u8; HDR_SIZE] = hdr_prefix[..HDR_SIZE].try_into().map_err(|_e| {
-                        FrameError::TypeConversionFailure(
-                            "Failed to build [u8] from vec<u8>".to_string(),
-                        )
-                    })?;
-
-                    Ok((hdr_suffix_len, hdr))
-                }
+        resync: bool,
+    ) -> Result<Vec<ParsedStreamData>, FrameError> {
+        validate_state(&last_incomplete_reception, &is_last_header_truncated)?;
 
-                None => {
-                    let hdr: [u8; HDR_SIZE] = data[..HDR_SIZE]
-                        .try_into()
-                        .map_err(|e| FrameError::TypeConversionFailure(format!("[{e:?}]")))?;
+        let mut output: Vec<ParsedStreamData> = vec![];
 
-                    Ok((HDR_SIZE, hdr))
-                }
-            };
-        output_res
-    }
-    fn is_header(hdr: &[u8]) -> bool {
-        if hdr.len() != HDR_SIZE {
-            return false;
+        // an empty packet leaves the caller's state untouched : hand it back as is.
+        if packet.is_empty() {
+            if let Some((size, data)) = last_incomplete_reception {
+                output.push(ParsedStreamData::Incompleted(size, data));
+            }
+            if let Some(truncated_hdr) = is_last_header_truncated {
+                output.push(ParsedStreamData::TruncatedHeader(truncated_hdr));
+            }
+            return Ok(output);
         }
 
-        let has_magic_prefix_slice = &hdr[..MAGIC_PREFIX.len()];
-
-        if has_magic_prefix_slice != MAGIC_PREFIX {
-            return false;
-        }
-        true
-    }
-    enum Truncating {
-        Yes(Vec<u8>),
-        No((Vec<u8>, bool)), // end of stream
-    }
-    fn is_data_size_truncating_next_header(
-        data: Vec<u8>,
-        last_incomplete_reception: &Option<(BodyLen, Vec<u8>)>,
-        is_last_header_truncated: &mut Option<Vec<u8>>,
-    ) -> Truncating {
-        match last_incomplete_reception {
-            Some((_size, data_received)) => {
-                //  if size - data_received.len() > data.len() {
-                let is_start_of_stream =
-                    data_received.is_empty() && is_last_header_truncated.is_some();
-                return Truncating::No((data, is_start_of_stream));
-                // }
+        let mut data = packet;
+        if let Some((announced, mut received)) = last_incomplete_reception {
+            // the end of the body announced by the previous packets comes first
+            let missing = announced.body_len() - received.len();
+            let Some((end, rest)) = data.split_at_checked(missing) else {
+                received.extend_from_slice(data);
+                output.push(ParsedStreamData::Incompleted(announced, received));
+                return Ok(output);
+            };
+            received.extend_from_slice(end);
+            // only an extended body can be refused, the next frame may start inside it
+            let body = (resync && announced.is_extended()).then(|| received.clone());
+            match (completed_frame(announced.to_field(), received), body) {
+                (Ok(parsed), _) => output.push(parsed),
+                (Err(FrameError::ParsingError(_)), Some(body)) => {
+                    let rescan = [&body, rest].concat();
+                    take_frames(&mut output, skip_to_magic(&rescan), resync)?;
+                    return Ok(output);
+                }
+                (Err(e), _) => return Err(e),
             }
-            None => {}
-        }
-        if data.len() < HDR_SIZE {
-            match is_last_header_truncated {
-                Some(previous_truncation) => {
-                    if previous_truncation.len() >= HDR_SIZE {
-                        return Truncating::No((data, true));
-                    }
-                    let mut previous_extended = previous_truncation.clone();
-                    let previous_len = previous_extended.len();
-                    let len_max = HDR_SIZE - previous_len;
-
-                    if data.len() < len_max {
-                        previous_extended.extend_from_slice(&data);
-                    } else {
-                        previous_extended.extend_from_slice(&data[..len_max]);
-                    }
-
-                    *previous_truncation = previous_extended;
-                    if previous_truncation.len() == HDR_SIZE {
-                        previous_truncation.extend_from_slice(&data[len_max..]);
-                        Truncating::No((previous_truncation.clone(), true))
-                    } else {
-                        Truncating::Yes(previous_truncation.clone())
-                    }
+            data = rest;
+        } else if let Some(mut hdr) = is_last_header_truncated {
+            // the end of the header started by the previous packets comes first
+            let (end, rest) = data.split_at((HDR_SIZE - hdr.len()).min(data.len()));
+            hdr.extend_from_slice(end);
+            let hdr: [u8; HDR_SIZE] = match hdr.try_into() {
+                Ok(hdr) => hdr,
+                Err(hdr) => {
+                    output.push(ParsedStreamData::TruncatedHeader(hdr));
+                    return Ok(output);
+                }
+            };
+            match take_frame(&mut output, &hdr, rest) {
+                Ok(Some(rest)) => data = rest,
+                Ok(None) => return Ok(output),
+                Err(FrameError::ParsingError(_)) if resync => {
+                    let rescan = [&hdr[1..], rest].concat();
+                    take_frames(&mut output, skip_to_magic(&rescan), resync)?;
+                    return Ok(output);
                 }
-                None => Truncating::Yes(data),
+                Err(e) => return Err(e),
             }
-        } else {
-            Truncating::No((data, true))
         }
+
+        take_frames(&mut output, data, resync)?;
+        Ok(output)
     }
-    fn has_no_hdr_start(
-        mut data: Vec<u8>,
-        last_incomplete_reception: Option<(BodyLen, Vec<u8>)>,
-    ) -> Result<HeaderParsing, Vec<u8>> {
-        match last_incomplete_reception {
-            Some((msg_len, mut already_received)) => {
-                let total_packet_len = data.len();
-
-                // the remaining bytes quantity that we should find in this packet.
-                let remaining_bytes = msg_len - already_received.len();
-
-                // case 0 stream len contains the end of the last incomplete + at least the start
-                // of the following message.
-
-                if remaining_bytes < total_packet_len {
-                    let remaining = data.split_off(remaining_bytes);
-                    already_received.extend(data);
-                    return Ok(HeaderParsing::OneMessageAndRemains(
-                        (msg_len, already_received),
-                        remaining,
-                    ));
-                }
-                if remaining_bytes == total_packet_len {
-                    already_received.extend(data);
-                    return Ok(HeaderParsing::Completed(msg_len, already_received));
+
+    // push the frames of `data`, which starts with a header
+    fn take_frames(
+        output: &mut Vec<ParsedStreamData>,
+        mut data: &[u8],
+        resync: bool,
+    ) -> Result<(), FrameError> {
+        while !data.is_empty() {
+            let Some((hdr, rest)) = data.split_first_chunk::<HDR_SIZE>() else {
+                output.push(ParsedStreamData::TruncatedHeader(data.to_vec()));
+                return Ok(());
+            };
+            match take_frame(output, hdr, rest) {
+                Ok(Some(rest)) => data = rest,
+                Ok(None) => return Ok(()),
+                Err(FrameError::ParsingError(_)) if resync => {
+                    data = skip_to_magic(data.get(1..).unwrap_or_default());
                 }
-                already_received.extend(data);
-                // Is extending the last packet 's data
-                Ok(HeaderParsing::StartWithNoHeaderAndIncompleted(
-                    msg_len,
-                    already_received,
-                ))
+                Err(e) => return Err(e),
             }
-            None => Err(data),
         }
+        Ok(())
     }
 
-    fn has_hdr_first(msg_len: usize, mut data: Vec<u8>) -> HeaderParsing {
-        // It starts directly with a header :
-        // case 0: total_len == body_len,
-        // case 1: total_len > body_len,
-        // case 2: total_len < body_len.
+    // `data` from its first magic prefix, or from the end of it that may start one
+    pub(crate) fn skip_to_magic(data: &[u8]) -> &[u8] {
+        let at = find_magic_prefix(data).unwrap_or_else(|| {
+            (data.len().saturating_sub(MAGIC_PREFIX.len() - 1)..data.len())
+                .find(|at| MAGIC_PREFIX.starts_with(data.get(*at..).unwrap_or_default()))
+                .unwrap_or(data.len())
+        });
+        data.get(at..).unwrap_or_default()
+    }
 
-        // case 0
-        if data.len() == msg_len {
-            return HeaderParsing::Completed(msg_len, data);
+    // push the frame `hdr` announces, returns the bytes after its body, or `None` if `data`
+    // ends inside it
+    fn take_frame<'a>(
+        output: &mut Vec<ParsedStreamData>,
+        hdr: &[u8; HDR_SIZE],
+        data: &'a [u8],
+    ) -> Result<Option<&'a [u8]>, FrameError> {
+        // neither a header nor the continuation of a frame : resyncing is up to the caller, or
+        // to `parse_frame_header_resyncing`
+        let Some(len_field) = decode_header(hdr) else {
+            return Err(FrameError::ParsingError(format!(
+                "no magic prefix in header [{hdr:?}]"
+            )));
+        };
+        match data.split_at_checked(body_len(len_field)) {
+            Some((body, rest)) => {
+                output.push(completed_frame(len_field, body.to_vec())?);
+                Ok(Some(rest))
+            }
+            None => {
+                // the rest of the body will be in the next packets
+                output.push(ParsedStreamData::Incompleted(
+                    AnnouncedLen::from_field(len_field),
+                    data.to_vec(),
+                ));
+                Ok(None)
+            }
         }
+    }
 
-        if data.len() > msg_len {
-            let remaining = data.split_off(msg_len);
-            return HeaderParsing::OneMessageAndRemains((msg_len, data), remaining);
+    fn validate_state(
+        last_incomplete_reception: &Option<(AnnouncedLen, Vec<u8>)>,
+        is_last_header_truncated: &Option<Vec<u8>>,
+    ) -> Result<(), FrameError> {
+        let invalid = |reason: String| Err(FrameError::InvalidState(reason));
+        match (last_incomplete_reception, is_last_header_truncated) {
+            (Some(_), Some(_)) => {
+                invalid("both an incomplete body and a truncated header".to_string())
+            }
+            (Some((announced, _)), None) if announced.body_len() > MAX_BODY_LEN => {
+                invalid(format!(
+                    "announced body len [{}] over MAX_BODY_LEN",
+                    announced.body_len()
+                ))
+            }
+            (Some((announced, received)), None) if received.len() > announced.body_len() => {
+                invalid(format!(
+                    "[{}] bytes received for a frame announcing [{}]",
+                    received.len(),
+                    announced.body_len()
+                ))
+            }
+            (None, Some(hdr)) if hdr.len() >= HDR_SIZE => invalid(format!(
+                "truncated header of [{}] bytes, a header is [{HDR_SIZE}]",
+                hdr.len()
+            )),
+            _ => Ok(()),
         }
+    }
 
-        if data.len() < msg_len {
-            return HeaderParsing::DataTooLittle(data);
+    // a plain frame body is handed as is, an extended one is decoded
+    fn completed_frame(len_field: usize, body: Vec<u8>) -> Result<ParsedStreamData, FrameError> {
+        if is_extended(len_field) {
+            decode_extended(body)
+        } else {
+            Ok(ParsedStreamData::Completed(body))
         }
-        HeaderParsing::None
     }
 }
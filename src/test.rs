@@ -4,7 +4,7 @@ mod test_cases {
     use core::panic;
 
     use crate::{
-        FrameParser, FrameWriter, ParsedStreamData,
+        AnnouncedLen, FrameParser, FrameWriter, ParsedStreamData,
         stream_frame::{HDR_SIZE, MAGIC_PREFIX},
     };
     #[test]
@@ -42,7 +42,7 @@ mod test_cases {
         let frame = test_body.prepend_frame().unwrap();
 
         let mut truncated_header: Option<Vec<u8>> = None;
-        let mut previous_incompleted_data: Option<(usize, Vec<u8>)> = None;
+        let mut previous_incompleted_data: Option<(AnnouncedLen, Vec<u8>)> = None;
 
         let res =
             frame.parse_frame_header(previous_incompleted_data.take(), truncated_header.take());
@@ -57,6 +57,7 @@ mod test_cases {
                 }
                 ParsedStreamData::Incompleted(_size, _data) => {}
                 ParsedStreamData::TruncatedHeader(_truncadeted_hdr) => {}
+                _ => {}
             }
         }
     }
@@ -113,7 +114,7 @@ mod test_cases {
         // client simulation :
 
         let thread_handle = std::thread::spawn(move || {
-            let mut previous_incompleted_data: Option<(usize, Vec<u8>)> = None;
+            let mut previous_incompleted_data: Option<(AnnouncedLen, Vec<u8>)> = None;
             let mut truncated_header: Option<Vec<u8>> = None;
 
             let mut messages_received: Vec<String> = vec![];
@@ -141,6 +142,9 @@ mod test_cases {
                         ParsedStreamData::TruncatedHeader(truncadted_hdr) => {
                             truncated_header = Some(truncadted_hdr);
                         }
+                        ParsedStreamData::Control(_control_frame) => {}
+                        ParsedStreamData::Tagged(_tags, _data) => {}
+                        ParsedStreamData::Chunk { .. } => {}
                     }
                 }
                 if loop_count == len {
@@ -212,7 +216,7 @@ mod test_cases {
         // client simulation :
 
         let thread_handle = std::thread::spawn(move || {
-            let mut previous_incompleted_data: Option<(usize, Vec<u8>)> = None;
+            let mut previous_incompleted_data: Option<(AnnouncedLen, Vec<u8>)> = None;
             let mut truncated_header: Option<Vec<u8>> = None;
 
             let mut messages_received: Vec<String> = vec![];
@@ -241,6 +245,9 @@ mod test_cases {
                         ParsedStreamData::TruncatedHeader(truncadted_hdr) => {
                             truncated_header = Some(truncadted_hdr);
                         }
+                        ParsedStreamData::Control(_control_frame) => {}
+                        ParsedStreamData::Tagged(_tags, _data) => {}
+                        ParsedStreamData::Chunk { .. } => {}
                     }
                 }
                 if loop_count == len {
@@ -308,7 +315,7 @@ mod test_cases {
         // client simulation :
 
         let thread_handle = std::thread::spawn(move || {
-            let mut previous_incompleted_data: Option<(usize, Vec<u8>)> = None;
+            let mut previous_incompleted_data: Option<(AnnouncedLen, Vec<u8>)> = None;
             let mut truncated_header: Option<Vec<u8>> = None;
 
             let mut messages_received: Vec<String> = vec![];
@@ -336,6 +343,9 @@ mod test_cases {
                         ParsedStreamData::TruncatedHeader(truncadted_hdr) => {
                             truncated_header = Some(truncadted_hdr);
                         }
+                        ParsedStreamData::Control(_control_frame) => {}
+                        ParsedStreamData::Tagged(_tags, _data) => {}
+                        ParsedStreamData::Chunk { .. } => {}
                     }
                 }
                 if loop_count == len {
@@ -412,7 +422,7 @@ mod test_cases {
         // client simulation :
 
         let thread_handle = std::thread::spawn(move || {
-            let mut previous_incompleted_data: Option<(usize, Vec<u8>)> = None;
+            let mut previous_incompleted_data: Option<(AnnouncedLen, Vec<u8>)> = None;
             let mut truncated_header: Option<Vec<u8>> = None;
 
             let mut messages_received: Vec<String> = vec![];
@@ -448,6 +458,9 @@ mod test_cases {
                         ParsedStreamData::TruncatedHeader(truncadted_hdr) => {
                             truncated_header = Some(truncadted_hdr);
                         }
+                        ParsedStreamData::Control(_control_frame) => {}
+                        ParsedStreamData::Tagged(_tags, _data) => {}
+                        ParsedStreamData::Chunk { .. } => {}
                     }
                 }
                 if loop_count == len {
@@ -524,7 +537,7 @@ mod test_cases {
         // client simulation :
 
         let thread_handle = std::thread::spawn(move || {
-            let mut previous_incompleted_data: Option<(usize, Vec<u8>)> = None;
+            let mut previous_incompleted_data: Option<(AnnouncedLen, Vec<u8>)> = None;
             let mut truncated_header: Option<Vec<u8>> = None;
 
             let mut messages_received: Vec<String> = vec![];
@@ -560,6 +573,9 @@ mod test_cases {
                         ParsedStreamData::TruncatedHeader(truncadted_hdr) => {
                             truncated_header = Some(truncadted_hdr);
                         }
+                        ParsedStreamData::Control(_control_frame) => {}
+                        ParsedStreamData::Tagged(_tags, _data) => {}
+                        ParsedStreamData::Chunk { .. } => {}
                     }
                 }
                 if loop_count == len {
@@ -636,7 +652,7 @@ mod test_cases {
         // client simulation :
 
         let thread_handle = std::thread::spawn(move || {
-            let mut previous_incompleted_data: Option<(usize, Vec<u8>)> = None;
+            let mut previous_incompleted_data: Option<(AnnouncedLen, Vec<u8>)> = None;
             let mut truncated_header: Option<Vec<u8>> = None;
 
             let mut messages_received: Vec<String> = vec![];
@@ -673,6 +689,9 @@ mod test_cases {
                         ParsedStreamData::TruncatedHeader(truncadted_hdr) => {
                             truncated_header = Some(truncadted_hdr);
                         }
+                        ParsedStreamData::Control(_control_frame) => {}
+                        ParsedStreamData::Tagged(_tags, _data) => {}
+                        ParsedStreamData::Chunk { .. } => {}
                     }
                 }
                 if loop_count == len {
@@ -749,7 +768,7 @@ mod test_cases {
         // client simulation :
 
         let thread_handle = std::thread::spawn(move || {
-            let mut previous_incompleted_data: Option<(usize, Vec<u8>)> = None;
+            let mut previous_incompleted_data: Option<(AnnouncedLen, Vec<u8>)> = None;
             let mut truncated_header: Option<Vec<u8>> = None;
 
             let mut messages_received: Vec<String> = vec![];
@@ -785,6 +804,9 @@ mod test_cases {
                         ParsedStreamData::TruncatedHeader(truncadted_hdr) => {
                             truncated_header = Some(truncadted_hdr);
                         }
+                        ParsedStreamData::Control(_control_frame) => {}
+                        ParsedStreamData::Tagged(_tags, _data) => {}
+                        ParsedStreamData::Chunk { .. } => {}
                     }
                 }
                 if loop_count == len {
@@ -861,7 +883,7 @@ mod test_cases {
         // client simulation :
 
         let thread_handle = std::thread::spawn(move || {
-            let mut previous_incompleted_data: Option<(usize, Vec<u8>)> = None;
+            let mut previous_incompleted_data: Option<(AnnouncedLen, Vec<u8>)> = None;
             let mut truncated_header: Option<Vec<u8>> = None;
 
             let mut messages_received: Vec<String> = vec![];
@@ -897,6 +919,9 @@ mod test_cases {
                         ParsedStreamData::TruncatedHeader(truncadted_hdr) => {
                             truncated_header = Some(truncadted_hdr);
                         }
+                        ParsedStreamData::Control(_control_frame) => {}
+                        ParsedStreamData::Tagged(_tags, _data) => {}
+                        ParsedStreamData::Chunk { .. } => {}
                     }
                 }
                 if loop_count == len {
@@ -973,7 +998,7 @@ mod test_cases {
         // client simulation :
 
         let thread_handle = std::thread::spawn(move || {
-            let mut previous_incompleted_data: Option<(usize, Vec<u8>)> = None;
+            let mut previous_incompleted_data: Option<(AnnouncedLen, Vec<u8>)> = None;
             let mut truncated_header: Option<Vec<u8>> = None;
 
             let mut messages_received: Vec<String> = vec![];
@@ -1009,6 +1034,9 @@ mod test_cases {
                         ParsedStreamData::TruncatedHeader(truncadted_hdr) => {
                             truncated_header = Some(truncadted_hdr);
                         }
+                        ParsedStreamData::Control(_control_frame) => {}
+                        ParsedStreamData::Tagged(_tags, _data) => {}
+                        ParsedStreamData::Chunk { .. } => {}
                     }
                 }
                 if loop_count == len {
@@ -1085,7 +1113,7 @@ mod test_cases {
         // client simulation :
 
         let thread_handle = std::thread::spawn(move || {
-            let mut previous_incompleted_data: Option<(usize, Vec<u8>)> = None;
+            let mut previous_incompleted_data: Option<(AnnouncedLen, Vec<u8>)> = None;
             let mut truncated_header: Option<Vec<u8>> = None;
 
             let mut messages_received: Vec<String> = vec![];
@@ -1121,6 +1149,9 @@ mod test_cases {
                         ParsedStreamData::TruncatedHeader(truncadted_hdr) => {
                             truncated_header = Some(truncadted_hdr);
                         }
+                        ParsedStreamData::Control(_control_frame) => {}
+                        ParsedStreamData::Tagged(_tags, _data) => {}
+                        ParsedStreamData::Chunk { .. } => {}
                     }
                 }
                 if loop_count == len {
@@ -1197,7 +1228,7 @@ mod test_cases {
         // client simulation :
 
         let thread_handle = std::thread::spawn(move || {
-            let mut previous_incompleted_data: Option<(usize, Vec<u8>)> = None;
+            let mut previous_incompleted_data: Option<(AnnouncedLen, Vec<u8>)> = None;
             let mut truncated_header: Option<Vec<u8>> = None;
 
             let mut messages_received: Vec<String> = vec![];
@@ -1233,6 +1264,9 @@ mod test_cases {
                         ParsedStreamData::TruncatedHeader(truncadted_hdr) => {
                             truncated_header = Some(truncadted_hdr);
                         }
+                        ParsedStreamData::Control(_control_frame) => {}
+                        ParsedStreamData::Tagged(_tags, _data) => {}
+                        ParsedStreamData::Chunk { .. } => {}
                     }
                 }
                 if loop_count == len {
@@ -1309,7 +1343,7 @@ mod test_cases {
         // client simulation :
 
         let thread_handle = std::thread::spawn(move || {
-            let mut previous_incompleted_data: Option<(usize, Vec<u8>)> = None;
+            let mut previous_incompleted_data: Option<(AnnouncedLen, Vec<u8>)> = None;
             let mut truncated_header: Option<Vec<u8>> = None;
 
             let mut messages_received: Vec<String> = vec![];
@@ -1345,6 +1379,9 @@ mod test_cases {
                         ParsedStreamData::TruncatedHeader(truncadted_hdr) => {
                             truncated_header = Some(truncadted_hdr);
                         }
+                        ParsedStreamData::Control(_control_frame) => {}
+                        ParsedStreamData::Tagged(_tags, _data) => {}
+                        ParsedStreamData::Chunk { .. } => {}
                     }
                 }
                 if loop_count == len {
@@ -1421,7 +1458,7 @@ mod test_cases {
         // client simulation :
 
         let thread_handle = std::thread::spawn(move || {
-            let mut previous_incompleted_data: Option<(usize, Vec<u8>)> = None;
+            let mut previous_incompleted_data: Option<(AnnouncedLen, Vec<u8>)> = None;
             let mut truncated_header: Option<Vec<u8>> = None;
 
             let mut messages_received: Vec<String> = vec![];
@@ -1457,6 +1494,9 @@ mod test_cases {
                         ParsedStreamData::TruncatedHeader(truncadted_hdr) => {
                             truncated_header = Some(truncadted_hdr);
                         }
+                        ParsedStreamData::Control(_control_frame) => {}
+                        ParsedStreamData::Tagged(_tags, _data) => {}
+                        ParsedStreamData::Chunk { .. } => {}
                     }
                 }
                 if loop_count == len {
@@ -1527,7 +1567,7 @@ mod test_cases {
         // client simulation :
 
         let thread_handle = std::thread::spawn(move || {
-            let mut previous_incompleted_data: Option<(usize, Vec<u8>)> = None;
+            let mut previous_incompleted_data: Option<(AnnouncedLen, Vec<u8>)> = None;
             let mut truncated_header: Option<Vec<u8>> = None;
 
             let mut messages_received: Vec<String> = vec![];
@@ -1556,6 +1596,9 @@ mod test_cases {
                         ParsedStreamData::TruncatedHeader(truncadted_hdr) => {
                             truncated_header = Some(truncadted_hdr);
                         }
+                        ParsedStreamData::Control(_control_frame) => {}
+                        ParsedStreamData::Tagged(_tags, _data) => {}
+                        ParsedStreamData::Chunk { .. } => {}
                     }
                 }
                 if loop_count == len {
@@ -1624,7 +1667,7 @@ mod test_cases {
             // client simulation :
 
             let thread_handle = std::thread::spawn(move || {
-                let mut previous_incompleted_data: Option<(usize, Vec<u8>)> = None;
+                let mut previous_incompleted_data: Option<(AnnouncedLen, Vec<u8>)> = None;
                 let mut truncated_header: Option<Vec<u8>> = None;
 
                 let mut messages_received: Vec<String> = vec![];
@@ -1654,6 +1697,9 @@ mod test_cases {
                             ParsedStreamData::TruncatedHeader(truncadted_hdr) => {
                                 truncated_header = Some(truncadted_hdr);
                             }
+                            ParsedStreamData::Control(_control_frame) => {}
+                            ParsedStreamData::Tagged(_tags, _data) => {}
+                            ParsedStreamData::Chunk { .. } => {}
                         }
                     }
                     if loop_count == len {
@@ -1669,3 +1715,3969 @@ mod test_cases {
         }
     }
 }
+
+#[cfg(test)]
+mod parser_state_cases {
+
+    use crate::{
+        FrameParser, FrameWriter, PARSER_STATE_VERSION, ParsedStreamData, ParserState,
+        stream_frame::HDR_SIZE,
+    };
+
+    fn framed_stream(messages: &[Vec<u8>]) -> Vec<u8> {
+        messages
+            .iter()
+            .map(|m| m.clone().prepend_frame().unwrap())
+            .collect::<Vec<Vec<u8>>>()
+            .concat()
+    }
+
+    #[test]
+    fn checkpoint_and_resume_at_every_packet() {
+        let messages: Vec<Vec<u8>> = (0..50)
+            .map(|i| {
+                format!("message number {i} ")
+                    .repeat(i % 7 + 1)
+                    .into_bytes()
+            })
+            .collect();
+        let stream = framed_stream(&messages);
+
+        for packet_size in [1, 3, 5, HDR_SIZE - 1, HDR_SIZE, HDR_SIZE + 1, 64, 1000] {
+            let mut received: Vec<Vec<u8>> = vec![];
+            let mut checkpoint = ParserState::new().to_bytes();
+
+            for packet in stream.chunks(packet_size) {
+                // simulate a process restart between each packet
+                let mut state = ParserState::from_bytes(&checkpoint).unwrap();
+
+                for parsed in state.parse(packet.to_vec()).unwrap() {
+                    match parsed {
+                        ParsedStreamData::Completed(data) => received.push(data),
+                        _ => panic!("only completed frames are returned"),
+                    }
+                }
+                checkpoint = state.to_bytes();
+            }
+
+            assert_eq!(received, messages);
+            assert!(ParserState::from_bytes(&checkpoint).unwrap().is_empty());
+        }
+    }
+
+    #[test]
+    fn state_accessors() {
+        let frame = vec![7u8; 40].prepend_frame().unwrap();
+        let mut state = ParserState::new();
+
+        assert!(state.parse(frame[..5].to_vec()).unwrap().is_empty());
+        assert_eq!(state.pending_header(), Some(&frame[..5]));
+        assert_eq!(state.announced_len(), None);
+
+        assert!(state.parse(frame[5..20].to_vec()).unwrap().is_empty());
+        assert_eq!(state.pending_header(), None);
+        assert_eq!(state.announced_len(), Some(40));
+        assert_eq!(state.received(), &frame[HDR_SIZE..20]);
+
+        // an empty packet changes nothing
+        assert!(state.parse(vec![]).unwrap().is_empty());
+        assert_eq!(state.announced_len(), Some(40));
+        assert_eq!(state.received().len(), 20 - HDR_SIZE);
+    }
+
+    #[test]
+    fn empty_packet_hands_back_the_state() {
+        let parsed = vec![]
+            .parse_frame_header(Some((10.into(), vec![1, 2, 3])), None)
+            .unwrap();
+
+        assert_eq!(parsed.len(), 1);
+        assert!(matches!(
+            &parsed[0],
+            ParsedStreamData::Incompleted(size, data) if size.body_len() == 10 && data == &[1, 2, 3]
+        ));
+    }
+
+    #[test]
+    fn reject_invalid_checkpoints() {
+        // empty, unknown version, unknown flags
+        assert!(ParserState::from_bytes(&[]).is_err());
+        assert!(ParserState::from_bytes(&[PARSER_STATE_VERSION + 1, 0]).is_err());
+        assert!(ParserState::from_bytes(&[PARSER_STATE_VERSION, 0b11]).is_err());
+        // trailing bytes
+        assert!(ParserState::from_bytes(&[PARSER_STATE_VERSION, 0, 0]).is_err());
+        // received more than announced
+        assert!(
+            ParserState::from_bytes(&[PARSER_STATE_VERSION, 1, 0, 0, 0, 1, 0, 0, 0, 2, 9, 9])
+                .is_err()
+        );
+        // truncated body
+        assert!(
+            ParserState::from_bytes(&[PARSER_STATE_VERSION, 1, 0, 0, 0, 4, 0, 0, 0, 2, 9]).is_err()
+        );
+        // header as long as a full header
+        let mut blob = vec![PARSER_STATE_VERSION, 2, HDR_SIZE as u8];
+        blob.extend_from_slice(&[0; HDR_SIZE]);
+        assert!(ParserState::from_bytes(&blob).is_err());
+
+        let valid = [PARSER_STATE_VERSION, 1, 0, 0, 0, 4, 0, 0, 0, 2, 9, 9];
+        let state = ParserState::from_bytes(&valid).unwrap();
+        assert_eq!(state.announced_len(), Some(4));
+        assert_eq!(state.to_bytes(), valid);
+    }
+}
+
+#[cfg(test)]
+mod slice_decoder_cases {
+
+    use crate::{
+        FrameError, FrameWriter, SliceFrameDecoder,
+        stream_frame::{HDR_SIZE, MAGIC_PREFIX},
+    };
+
+    #[test]
+    fn decode_messages_with_every_packet_size() {
+        let messages: Vec<Vec<u8>> = (0..40).map(|i| vec![i as u8; i * 3]).collect();
+        let stream = messages
+            .iter()
+            .map(|m| m.clone().prepend_frame().unwrap())
+            .collect::<Vec<Vec<u8>>>()
+            .concat();
+
+        for packet_size in 1..HDR_SIZE * 3 {
+            let mut buf = [0u8; 128];
+            let mut decoder = SliceFrameDecoder::new(&mut buf);
+            let mut received: Vec<Vec<u8>> = vec![];
+
+            for packet in stream.chunks(packet_size) {
+                decoder
+                    .decode(packet, |body| received.push(body.to_vec()))
+                    .unwrap();
+            }
+            assert_eq!(received, messages);
+            assert!(decoder.is_empty());
+        }
+    }
+
+    #[test]
+    fn frame_larger_than_buffer() {
+        let mut buf = [0u8; 8];
+        let mut decoder = SliceFrameDecoder::new(&mut buf);
+        let mut received = 0;
+
+        let too_large = vec![1u8; 9].prepend_frame().unwrap();
+        let res = decoder.decode(&too_large, |_| received += 1);
+        assert!(matches!(
+            res,
+            Err(FrameError::FrameTooLarge {
+                len: 9,
+                capacity: 8
+            })
+        ));
+
+        // the decoder is usable again on the next frame
+        let fits = vec![1u8; 8].prepend_frame().unwrap();
+        decoder.decode(&fits, |_| received += 1).unwrap();
+        assert_eq!(received, 1);
+    }
+
+    #[test]
+    fn bad_magic() {
+        let mut buf = [0u8; 8];
+        let mut decoder = SliceFrameDecoder::new(&mut buf);
+
+        let mut frame = vec![1u8; 4].prepend_frame().unwrap();
+        frame[MAGIC_PREFIX.len() - 1] ^= 0xFF;
+
+        assert!(matches!(
+            decoder.decode(&frame, |_| {}),
+            Err(FrameError::ParsingError(_))
+        ));
+        assert!(decoder.is_empty());
+    }
+
+    #[test]
+    fn resync_skips_garbage_between_frames() {
+        let messages = [b"first".to_vec(), b"second".to_vec(), b"third".to_vec()];
+        let frames: Vec<Vec<u8>> = messages
+            .iter()
+            .map(|m| m.clone().prepend_frame().unwrap())
+            .collect();
+        // a truncated magic prefix just before a frame, and an extended frame
+        let stream = [
+            &frames[0][..],
+            b"garbage",
+            &MAGIC_PREFIX[..5],
+            &frames[1],
+            &crate::ControlFrame::Ping(3).encode().unwrap(),
+            &MAGIC_PREFIX[..3],
+            &frames[2],
+        ]
+        .concat();
+
+        for packet_size in 1..=stream.len() {
+            let mut buf = [0u8; 16];
+            let mut decoder = SliceFrameDecoder::new(&mut buf).with_resync();
+            let mut received = vec![];
+            for packet in stream.chunks(packet_size) {
+                decoder
+                    .decode(packet, |body| received.push(body.to_vec()))
+                    .unwrap();
+            }
+            assert_eq!(received, messages, "packet size {packet_size}");
+            assert!(decoder.is_empty());
+        }
+    }
+}
+
+#[cfg(test)]
+mod encode_into_cases {
+
+    use crate::{FrameError, FrameWriter, HDR_SIZE, encode_into, encoded_len};
+
+    #[test]
+    fn encode_into_matches_prepend_frame() {
+        for len in [0, 1, 11, 12, 13, 500] {
+            let payload = vec![0xAB; len];
+            let mut out = vec![0u8; encoded_len(len) + 7];
+
+            let written = encode_into(&payload, &mut out).unwrap();
+
+            assert_eq!(written, HDR_SIZE + len);
+            assert_eq!(&out[..written], payload.clone().prepend_frame().unwrap());
+
+            let mut in_place = payload;
+            in_place.prepend_frame_in_place().unwrap();
+            assert_eq!(&out[..written], in_place);
+        }
+    }
+
+    #[test]
+    fn encode_into_buffer_too_small() {
+        let payload = [1u8; 10];
+        let mut out = [0u8; HDR_SIZE + 9];
+
+        assert!(matches!(
+            encode_into(&payload, &mut out),
+            Err(FrameError::BufferTooSmall { needed }) if needed == HDR_SIZE + 10
+        ));
+        // nothing written
+        assert_eq!(out, [0u8; HDR_SIZE + 9]);
+    }
+}
+
+#[cfg(test)]
+mod buffer_types_cases {
+
+    use std::{borrow::Cow, collections::VecDeque};
+
+    use crate::{FrameParser, FrameWriter, ParsedStreamData, ParserState};
+
+    const BODY: &[u8] = b"framing without converting to a Vec first";
+
+    fn expected_frame() -> Vec<u8> {
+        BODY.to_vec().prepend_frame().unwrap()
+    }
+
+    fn assert_writer<W: FrameWriter + Clone + AsRef<[u8]>>(buffer: W) {
+        assert_eq!(buffer.clone().prepend_frame().unwrap(), expected_frame());
+
+        let mut in_place = buffer;
+        in_place.prepend_frame_in_place().unwrap();
+        assert_eq!(in_place.as_ref(), expected_frame());
+    }
+
+    fn assert_parser<P: FrameParser>(packets: Vec<P>) {
+        let mut state = ParserState::new();
+        let mut received = vec![];
+        for packet in packets {
+            for parsed in state.parse(packet).unwrap() {
+                if let ParsedStreamData::Completed(data) = parsed {
+                    received.push(data);
+                }
+            }
+        }
+        assert_eq!(received, vec![BODY.to_vec()]);
+    }
+
+    #[test]
+    fn std_buffer_types() {
+        let frame = expected_frame();
+
+        assert_writer(Box::<[u8]>::from(BODY));
+        assert_writer(Cow::Borrowed(BODY));
+        assert_writer(Cow::<[u8]>::Owned(BODY.to_vec()));
+
+        assert_parser(frame.chunks(7).collect());
+        assert_parser(frame.chunks(7).map(Box::<[u8]>::from).collect());
+        assert_parser(frame.chunks(7).map(Cow::Borrowed).collect());
+        assert_parser(
+            frame
+                .chunks(7)
+                .map(|c| c.iter().copied().collect::<VecDeque<u8>>())
+                .collect(),
+        );
+    }
+
+    #[test]
+    fn vec_deque_wrapping_around() {
+        // pushing the start of the body at the front makes it wrap in the ring
+        let mut deque: VecDeque<u8> = BODY[10..].iter().copied().collect();
+        for byte in BODY[..10].iter().rev() {
+            deque.push_front(*byte);
+        }
+        assert_eq!(deque.clone().prepend_frame().unwrap(), expected_frame());
+
+        deque.prepend_frame_in_place().unwrap();
+        assert_eq!(deque.make_contiguous(), expected_frame());
+    }
+
+    #[cfg(feature = "bytes")]
+    #[test]
+    fn bytes_buffer_types() {
+        use bytes::{Bytes, BytesMut};
+
+        assert_writer(Bytes::from_static(BODY));
+        assert_writer(BytesMut::from(BODY));
+
+        let frame = expected_frame();
+        assert_parser(frame.chunks(5).map(Bytes::copy_from_slice).collect());
+        assert_parser(frame.chunks(5).map(BytesMut::from).collect());
+    }
+
+    #[cfg(feature = "smallvec")]
+    #[test]
+    fn smallvec_buffer_types() {
+        use smallvec::SmallVec;
+
+        assert_writer(SmallVec::<[u8; 8]>::from_slice(BODY));
+        assert_writer(SmallVec::<[u8; 128]>::from_slice(BODY));
+
+        let frame = expected_frame();
+        assert_parser(
+            frame
+                .chunks(5)
+                .map(SmallVec::<[u8; 16]>::from_slice)
+                .collect(),
+        );
+    }
+}
+
+#[cfg(test)]
+mod ring_decoder_cases {
+
+    use rand::prelude::*;
+
+    use crate::{FrameError, FrameWriter, HDR_SIZE, RingFrameDecoder};
+
+    #[test]
+    fn reassemble_with_backpressure() {
+        let mut rng = rand::rng();
+
+        let messages: Vec<Vec<u8>> = (0..2000)
+            .map(|i| vec![(i % 251) as u8; rng.random_range(0..100)])
+            .collect();
+        let stream = messages
+            .iter()
+            .map(|m| m.clone().prepend_frame().unwrap())
+            .collect::<Vec<Vec<u8>>>()
+            .concat();
+
+        let mut decoder = RingFrameDecoder::with_capacity(100 + HDR_SIZE);
+        let mut received: Vec<Vec<u8>> = vec![];
+        let mut wrapped = 0;
+
+        for mut packet in stream.chunks(rng.random_range(1..300)) {
+            while !packet.is_empty() {
+                let accepted = decoder.push(packet);
+                packet = &packet[accepted..];
+
+                while let Some(frame) = decoder.next_frame().unwrap() {
+                    if !frame.second.is_empty() {
+                        wrapped += 1;
+                    }
+                    received.push(frame.to_vec());
+                }
+            }
+        }
+
+        assert_eq!(received, messages);
+        assert!(decoder.is_empty());
+        assert!(wrapped > 0);
+    }
+
+    #[test]
+    fn full_ring_refuses_bytes() {
+        let frame = vec![3u8; 20].prepend_frame().unwrap();
+        let mut decoder = RingFrameDecoder::with_capacity(frame.len());
+
+        assert_eq!(decoder.push(&frame), frame.len());
+        assert!(decoder.is_full());
+        assert_eq!(decoder.push(&frame), 0);
+
+        assert_eq!(decoder.next_frame().unwrap().unwrap().len(), 20);
+        assert_eq!(decoder.free(), frame.len());
+    }
+
+    #[test]
+    fn frame_never_fitting_in_the_ring() {
+        let frame = vec![3u8; 21].prepend_frame().unwrap();
+        let mut decoder = RingFrameDecoder::with_capacity(20 + HDR_SIZE);
+
+        decoder.push(&frame);
+        assert!(matches!(
+            decoder.next_frame(),
+            Err(FrameError::FrameTooLarge {
+                len: 21,
+                capacity: 20
+            })
+        ));
+        assert!(decoder.is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "can't hold a [12] bytes header")]
+    fn ring_too_small_for_a_header() {
+        let _ = RingFrameDecoder::with_capacity(HDR_SIZE - 1);
+    }
+
+    #[test]
+    fn full_ring_rejects_a_frame_it_cant_fit() {
+        let frame = vec![3u8; 1].prepend_frame().unwrap();
+        let mut decoder = RingFrameDecoder::with_capacity(HDR_SIZE);
+
+        assert_eq!(decoder.push(&frame), HDR_SIZE);
+        assert!(decoder.is_full());
+        assert!(matches!(
+            decoder.next_frame(),
+            Err(FrameError::FrameTooLarge {
+                len: 1,
+                capacity: 0
+            })
+        ));
+        assert!(decoder.is_empty());
+    }
+
+    #[test]
+    fn resync_skips_garbage_between_frames() {
+        let messages = [b"first".to_vec(), b"second".to_vec(), b"third".to_vec()];
+        let frames: Vec<Vec<u8>> = messages
+            .iter()
+            .map(|m| m.clone().prepend_frame().unwrap())
+            .collect();
+        // a truncated magic prefix just before a frame, and an extended frame
+        let stream = [
+            &frames[0][..],
+            b"garbage",
+            &crate::stream_frame::MAGIC_PREFIX[..5],
+            &frames[1],
+            &crate::ControlFrame::Ping(3).encode().unwrap(),
+            &frames[2],
+        ]
+        .concat();
+
+        for capacity in [HDR_SIZE + 6, 20, 64] {
+            let mut decoder = RingFrameDecoder::with_capacity(capacity).with_resync();
+            let mut received = vec![];
+            let mut rest = &stream[..];
+            while !rest.is_empty() {
+                rest = &rest[decoder.push(rest)..];
+                while let Some(frame) = decoder.next_frame().unwrap() {
+                    received.push(frame.to_vec());
+                }
+            }
+            assert_eq!(received, messages, "capacity {capacity}");
+            assert!(decoder.is_empty());
+        }
+    }
+
+    #[test]
+    fn bad_magic_clears_the_ring() {
+        let mut decoder = RingFrameDecoder::with_capacity(64);
+
+        decoder.push(&[0xAA; HDR_SIZE + 4]);
+        assert!(matches!(
+            decoder.next_frame(),
+            Err(FrameError::ParsingError(_))
+        ));
+        assert!(decoder.is_empty());
+    }
+}
+
+#[cfg(test)]
+mod control_frame_cases {
+
+    use std::{cell::Cell, collections::VecDeque, rc::Rc, time::Duration};
+
+    use crate::{
+        AnnouncedLen, Clock, ControlFrame, EXTENDED_FLAG, FrameParser, FrameWriter, HDR_SIZE,
+        Heartbeat, HeartbeatAction, MAGIC_PREFIX, MAX_BODY_LEN, ParsedStreamData, ParserState,
+        SliceFrameDecoder, stream_frame::encode_header,
+    };
+
+    #[test]
+    fn incomplete_extended_frames_announce_their_body_len() {
+        let ping = ControlFrame::Ping(7).encode().unwrap();
+        let (first, second) = ping.split_at(HDR_SIZE + 3);
+
+        let mut parsed = first.parse_frame_header(None, None).unwrap();
+        let Some(ParsedStreamData::Incompleted(announced, received)) = parsed.pop() else {
+            panic!("expected an incomplete frame");
+        };
+        assert_eq!(announced.body_len(), ping.len() - HDR_SIZE);
+        assert!(announced.is_extended());
+        assert!(!AnnouncedLen::from(announced.body_len()).is_extended());
+
+        let mut parsed = second
+            .parse_frame_header(Some((announced, received)), None)
+            .unwrap();
+        assert!(matches!(
+            parsed.pop(),
+            Some(ParsedStreamData::Control(ControlFrame::Ping(7)))
+        ));
+    }
+
+    #[derive(Clone, Default)]
+    struct MockClock(Rc<Cell<Duration>>);
+
+    impl MockClock {
+        fn advance(&self, by: Duration) {
+            self.0.set(self.0.get() + by);
+        }
+    }
+
+    impl Clock for MockClock {
+        fn now(&self) -> Duration {
+            self.0.get()
+        }
+    }
+
+    #[derive(Debug, PartialEq)]
+    enum Received {
+        Data(Vec<u8>),
+        Control(ControlFrame),
+    }
+
+    #[test]
+    fn control_frames_interleaved_with_data() {
+        let mut sent = vec![];
+        let mut stream = vec![];
+        for i in 0..200u64 {
+            let data = vec![i as u8; (i as usize * 7) % 40];
+            stream.extend(data.clone().prepend_frame().unwrap());
+            sent.push(Received::Data(data));
+
+            let control = match i % 3 {
+                0 => ControlFrame::Ping(i),
+                1 => ControlFrame::Pong(i),
+                _ => ControlFrame::Close {
+                    code: i as u16,
+                    reason: "bye ".repeat(i as usize % 4),
+                },
+            };
+            stream.extend(control.encode().unwrap());
+            sent.push(Received::Control(control));
+        }
+
+        // the stateless api, ParserState would refuse the data following a close
+        for packet_size in [1, 2, 7, HDR_SIZE, HDR_SIZE + 3, 64, 4096] {
+            let mut previous_incompleted_data: Option<(AnnouncedLen, Vec<u8>)> = None;
+            let mut truncated_header: Option<Vec<u8>> = None;
+            let mut received = vec![];
+
+            for packet in stream.chunks(packet_size) {
+                let parsed = packet
+                    .to_vec()
+                    .parse_frame_header(previous_incompleted_data.take(), truncated_header.take())
+                    .unwrap();
+                for p in parsed {
+                    match p {
+                        ParsedStreamData::Completed(data) => received.push(Received::Data(data)),
+                        ParsedStreamData::Incompleted(message_size, data) => {
+                            previous_incompleted_data = Some((message_size, data));
+                        }
+                        ParsedStreamData::TruncatedHeader(truncadted_hdr) => {
+                            truncated_header = Some(truncadted_hdr);
+                        }
+                        ParsedStreamData::Control(control) => {
+                            received.push(Received::Control(control));
+                        }
+                        ParsedStreamData::Tagged(..) => panic!("no tagged frame sent"),
+                        ParsedStreamData::Chunk { .. } => panic!("no chunk sent"),
+                    }
+                }
+            }
+            assert_eq!(received, sent);
+        }
+    }
+
+    #[test]
+    fn control_frame_wire_format() {
+        let ping = ControlFrame::Ping(0x0102_0304_0506_0708).encode().unwrap();
+
+        assert_eq!(&ping[..MAGIC_PREFIX.len()], MAGIC_PREFIX);
+        assert_eq!(
+            ping[MAGIC_PREFIX.len()..HDR_SIZE],
+            (10 | EXTENDED_FLAG).to_be_bytes()
+        );
+        assert_eq!(ping[HDR_SIZE..], [1, 0, 1, 2, 3, 4, 5, 6, 7, 8]);
+    }
+
+    #[test]
+    fn malformed_control_frames() {
+        // ping without its nonce, unknown kind, unknown flags
+        for body in [
+            vec![1u8, 0, 1],
+            vec![200, 0],
+            vec![1, 0xFF, 0, 0, 0, 0, 0, 0, 0, 0],
+        ] {
+            let mut frame = MAGIC_PREFIX.to_vec();
+            frame.extend((body.len() as u32 | EXTENDED_FLAG).to_be_bytes());
+            frame.extend(body);
+
+            assert!(frame.parse_frame_header(None, None).is_err());
+        }
+    }
+
+    #[test]
+    fn plain_frames_above_max_body_len_are_refused() {
+        // the top bit of the length field is reserved to extended frames
+        assert!(encode_header(MAX_BODY_LEN).is_ok());
+        assert!(encode_header(MAX_BODY_LEN + 1).is_err());
+    }
+
+    #[test]
+    fn slice_decoder_rejects_control_frames() {
+        let mut buf = [0u8; 32];
+        let mut decoder = SliceFrameDecoder::new(&mut buf);
+
+        assert!(
+            decoder
+                .decode(&ControlFrame::Ping(1).encode().unwrap(), |_| {})
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn heartbeat_schedules_pings_and_detects_dead_peer() {
+        let clock = MockClock::default();
+        let mut heartbeat = Heartbeat::new(clock.clone(), Duration::from_secs(10), 3);
+
+        assert_eq!(heartbeat.poll(), HeartbeatAction::Idle);
+        clock.advance(Duration::from_secs(9));
+        assert_eq!(heartbeat.poll(), HeartbeatAction::Idle);
+
+        // first ping, answered
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(
+            heartbeat.poll(),
+            HeartbeatAction::SendPing(ControlFrame::Ping(0))
+        );
+        assert_eq!(heartbeat.on_control(&ControlFrame::Pong(0)), None);
+        assert_eq!(heartbeat.missed(), 0);
+
+        // the peer stops answering
+        for nonce in 1..=3 {
+            clock.advance(Duration::from_secs(10));
+            assert_eq!(
+                heartbeat.poll(),
+                HeartbeatAction::SendPing(ControlFrame::Ping(nonce))
+            );
+            assert_eq!(heartbeat.missed(), nonce as u32 - 1);
+        }
+        // a pong for a ping answered already doesn't count
+        heartbeat.on_control(&ControlFrame::Pong(0));
+
+        clock.advance(Duration::from_secs(10));
+        assert_eq!(heartbeat.poll(), HeartbeatAction::PeerDead);
+        assert!(heartbeat.is_peer_dead());
+        assert_eq!(heartbeat.poll(), HeartbeatAction::PeerDead);
+    }
+
+    #[test]
+    fn heartbeat_accepts_pongs_slower_than_the_interval() {
+        let clock = MockClock::default();
+        let mut heartbeat = Heartbeat::new(clock.clone(), Duration::from_secs(10), 2);
+        let mut in_flight = VecDeque::new();
+        let mut pongs = 0;
+
+        // a 15 s round trip : each pong comes back after the next ping was sent
+        for _ in 0..40 {
+            clock.advance(Duration::from_secs(5));
+            while let Some(&(nonce, sent_at)) = in_flight.front() {
+                if clock.now() - sent_at < Duration::from_secs(15) {
+                    break;
+                }
+                in_flight.pop_front();
+                heartbeat.on_control(&ControlFrame::Pong(nonce));
+                pongs += 1;
+            }
+            match heartbeat.poll() {
+                HeartbeatAction::SendPing(ControlFrame::Ping(nonce)) => {
+                    in_flight.push_back((nonce, clock.now()));
+                }
+                HeartbeatAction::Idle => {}
+                action => panic!("unexpected {action:?}"),
+            }
+        }
+        assert!(pongs > 5);
+        assert!(!heartbeat.is_peer_dead());
+    }
+
+    #[test]
+    fn heartbeat_only_pings_idle_streams() {
+        let clock = MockClock::default();
+        let mut heartbeat = Heartbeat::new(clock.clone(), Duration::from_secs(10), 1);
+
+        // traffic every 6 s : no ping
+        for _ in 0..5 {
+            clock.advance(Duration::from_secs(6));
+            assert_eq!(heartbeat.poll(), HeartbeatAction::Idle);
+            heartbeat.on_activity();
+        }
+        clock.advance(Duration::from_secs(10));
+        assert_eq!(
+            heartbeat.poll(),
+            HeartbeatAction::SendPing(ControlFrame::Ping(0))
+        );
+
+        // sending doesn't delay the pong deadline
+        clock.advance(Duration::from_secs(6));
+        heartbeat.on_activity();
+        clock.advance(Duration::from_secs(4));
+        assert_eq!(heartbeat.poll(), HeartbeatAction::PeerDead);
+    }
+
+    #[test]
+    fn heartbeat_answers_pings() {
+        let mut heartbeat = Heartbeat::new(MockClock::default(), Duration::from_secs(1), 1);
+
+        assert_eq!(
+            heartbeat.on_control(&ControlFrame::Ping(77)),
+            Some(ControlFrame::Pong(77))
+        );
+    }
+
+    #[test]
+    fn heartbeat_between_two_peers() {
+        let clock = MockClock::default();
+        let mut client = Heartbeat::new(clock.clone(), Duration::from_secs(5), 2);
+        let mut server = Heartbeat::new(clock.clone(), Duration::from_secs(60), 2);
+        let mut server_state = ParserState::new();
+        let mut client_state = ParserState::new();
+
+        for _ in 0..10 {
+            clock.advance(Duration::from_secs(5));
+            let HeartbeatAction::SendPing(ping) = client.poll() else {
+                panic!("a ping is due");
+            };
+
+            for parsed in server_state.parse(ping.encode().unwrap()).unwrap() {
+                let ParsedStreamData::Control(control) = parsed else {
+                    panic!("control frame expected");
+                };
+                let pong = server.on_control(&control).unwrap();
+
+                for parsed in client_state.parse(pong.encode().unwrap()).unwrap() {
+                    let ParsedStreamData::Control(control) = parsed else {
+                        panic!("control frame expected");
+                    };
+                    assert_eq!(client.on_control(&control), None);
+                }
+            }
+            assert_eq!(client.missed(), 0);
+        }
+    }
+}
+
+#[cfg(test)]
+mod close_frame_cases {
+
+    use crate::{ControlFrame, FrameError, FrameWriter, ParsedStreamData, ParserState};
+
+    #[test]
+    fn close_ends_the_stream() {
+        let mut stream = b"last message".to_vec().prepend_frame().unwrap();
+        stream.extend(
+            ControlFrame::close(ControlFrame::CLOSE_GOING_AWAY, "migrating")
+                .encode()
+                .unwrap(),
+        );
+        // control frames are still accepted after the close
+        stream.extend(ControlFrame::Pong(3).encode().unwrap());
+
+        for packet_size in [1, 5, 13, 100] {
+            let mut state = ParserState::new();
+            let mut events = vec![];
+            for packet in stream.chunks(packet_size) {
+                events.extend(state.parse(packet.to_vec()).unwrap());
+            }
+
+            assert!(state.is_closed());
+            assert!(matches!(&events[..], [
+                ParsedStreamData::Completed(data),
+                ParsedStreamData::Control(ControlFrame::Close { code: 1, reason }),
+                ParsedStreamData::Control(ControlFrame::Pong(3)),
+            ] if data == b"last message" && reason == "migrating"));
+        }
+    }
+
+    #[test]
+    fn data_after_close_is_refused() {
+        let mut stream = ControlFrame::close(ControlFrame::CLOSE_NORMAL, "")
+            .encode()
+            .unwrap();
+        stream.extend(b"too late".to_vec().prepend_frame().unwrap());
+
+        // refused across packets and checkpoints
+        let (close, data) = stream.split_at(stream.len() - 3);
+        let mut state = ParserState::new();
+        state.parse(close.to_vec()).unwrap();
+
+        let mut state = ParserState::from_bytes(&state.to_bytes()).unwrap();
+        assert!(state.is_closed());
+        assert!(matches!(
+            state.parse(data.to_vec()),
+            Err(FrameError::StreamClosed)
+        ));
+    }
+
+    #[test]
+    fn close_keeps_the_frames_before_it_in_the_same_packet() {
+        let mut stream = b"first".to_vec().prepend_frame().unwrap();
+        stream.extend(
+            ControlFrame::close(ControlFrame::CLOSE_NORMAL, "bye")
+                .encode()
+                .unwrap(),
+        );
+        stream.extend(b"dropped".to_vec().prepend_frame().unwrap());
+        stream.extend(ControlFrame::Pong(4).encode().unwrap());
+
+        let mut state = ParserState::new();
+        let events = state.parse(stream).unwrap();
+        assert!(state.is_closed());
+        assert!(matches!(&events[..], [
+            ParsedStreamData::Completed(data),
+            ParsedStreamData::Control(ControlFrame::Close { code: 0, reason }),
+            ParsedStreamData::Control(ControlFrame::Pong(4)),
+        ] if data == b"first" && reason == "bye"));
+
+        // later packets can't carry data anymore
+        assert!(matches!(
+            state.parse(b"later".to_vec().prepend_frame().unwrap()),
+            Err(FrameError::StreamClosed)
+        ));
+    }
+
+    #[test]
+    fn close_reason_must_be_utf8() {
+        let mut frame = ControlFrame::close(7, "ab").encode().unwrap();
+        let last = frame.len() - 1;
+        frame[last] = 0xFF;
+
+        assert!(ParserState::new().parse(frame).is_err());
+    }
+}
+
+#[cfg(test)]
+mod credit_cases {
+
+    use crate::{
+        ControlFrame, CreditReceiver, CreditSender, FrameError, OnNoCredit, ParsedStreamData,
+        ParserState,
+    };
+
+    #[test]
+    fn credit_frame_round_trip() {
+        let credit = ControlFrame::Credit {
+            channel: 3,
+            frames: 0xA0B0_C0D0,
+            bytes: 7,
+        };
+        let parsed = ParserState::new().parse(credit.encode().unwrap()).unwrap();
+        assert!(matches!(&parsed[..], [ParsedStreamData::Control(c)] if *c == credit));
+    }
+
+    #[test]
+    fn producer_never_outruns_consumer() {
+        let stream_channel = crossbeam::channel::unbounded::<Vec<u8>>();
+        let credit_channel = crossbeam::channel::unbounded::<Vec<u8>>();
+        let messages: Vec<Vec<u8>> = (0..3000).map(|i| vec![i as u8; i % 300]).collect();
+
+        let expected = messages.clone();
+        let (to_consumer, from_producer) = stream_channel;
+        let (to_producer, from_consumer) = credit_channel;
+
+        // producer : sends as fast as its credit allows
+        let producer = std::thread::spawn(move || {
+            let mut sender = CreditSender::new(OnNoCredit::Queue);
+            let mut credit_state = ParserState::new();
+
+            for message in messages {
+                if let Some(frame) = sender.send(0, message).unwrap() {
+                    to_consumer.send(frame).unwrap();
+                }
+                // blocked until the consumer grants more
+                while sender.queued(0) > 0 {
+                    let packet = from_consumer.recv().unwrap();
+                    for parsed in credit_state.parse(packet).unwrap() {
+                        let ParsedStreamData::Control(credit) = parsed else {
+                            panic!("credit frame expected");
+                        };
+                        for frame in sender.on_credit(&credit) {
+                            to_consumer.send(frame).unwrap();
+                        }
+                    }
+                }
+            }
+        });
+
+        // consumer : 8 frames or 1024 bytes in flight at most
+        let consumer = std::thread::spawn(move || {
+            let mut receiver = CreditReceiver::new(8, 1024);
+            let mut state = ParserState::new();
+            let mut received = vec![];
+
+            to_producer
+                .send(receiver.open(0).encode().unwrap())
+                .unwrap();
+
+            while received.len() < 3000 {
+                let packet = from_producer.recv().unwrap();
+                // split the frame to go through the reassembly too
+                let (first, second) = packet.split_at(packet.len() / 2);
+                for packet in [first, second] {
+                    for parsed in state.parse(packet.to_vec()).unwrap() {
+                        let ParsedStreamData::Completed(data) = parsed else {
+                            panic!("data frame expected");
+                        };
+                        receiver.on_received(0, data.len()).unwrap();
+                        if let Some(credit) = receiver.on_consumed(0, data.len()) {
+                            let _ = to_producer.send(credit.encode().unwrap());
+                        }
+                        received.push(data);
+                    }
+                }
+            }
+            received
+        });
+
+        assert!(producer.join().is_ok());
+        assert_eq!(consumer.join().unwrap(), expected);
+    }
+
+    #[test]
+    fn refuse_without_credit() {
+        let mut sender = CreditSender::new(OnNoCredit::Refuse);
+
+        assert!(matches!(
+            sender.send(1, vec![1; 10]),
+            Err(FrameError::NoCredit { channel: 1 })
+        ));
+
+        assert!(
+            sender
+                .on_credit(&ControlFrame::Credit {
+                    channel: 1,
+                    frames: 2,
+                    bytes: 15,
+                })
+                .is_empty()
+        );
+        assert!(sender.send(1, vec![1; 10]).unwrap().is_some());
+        // a frame credit left, but not enough bytes
+        assert!(sender.send(1, vec![1; 10]).is_err());
+        assert!(sender.send(1, vec![1; 5]).unwrap().is_some());
+        assert_eq!(sender.credit(1), (0, 0));
+    }
+
+    #[test]
+    fn channels_have_their_own_credit() {
+        let mut sender = CreditSender::new(OnNoCredit::Queue);
+        let mut receiver = CreditReceiver::new(2, 100);
+
+        assert!(sender.on_credit(&receiver.open(1)).is_empty());
+
+        assert!(sender.send(1, vec![0; 10]).unwrap().is_some());
+        assert!(sender.send(2, vec![0; 10]).unwrap().is_none());
+        assert_eq!(sender.queued(2), 1);
+        assert!(sender.send(1, vec![0; 10]).unwrap().is_some());
+        assert!(sender.send(1, vec![0; 10]).unwrap().is_none());
+
+        // channel 2 opens, its queued message goes, channel 1 stays blocked
+        let released = sender.on_credit(&receiver.open(2));
+        assert_eq!(released.len(), 1);
+        assert_eq!(sender.queued(1), 1);
+    }
+
+    #[test]
+    fn receiver_detects_credit_overrun() {
+        let mut receiver = CreditReceiver::new(1, 100);
+
+        assert!(matches!(
+            receiver.on_received(4, 1),
+            Err(FrameError::CreditExceeded { channel: 4 })
+        ));
+
+        receiver.open(4);
+        receiver.on_received(4, 100).unwrap();
+        assert!(receiver.on_received(4, 1).is_err());
+
+        // consuming the message renews the window
+        assert_eq!(
+            receiver.on_consumed(4, 100),
+            Some(ControlFrame::Credit {
+                channel: 4,
+                frames: 1,
+                bytes: 100
+            })
+        );
+        receiver.on_received(4, 1).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod priority_queue_cases {
+
+    use crate::{
+        ControlFrame, FrameError, HDR_SIZE, ParsedStreamData, ParserState, PriorityFrameQueue,
+    };
+
+    fn drain(queue: &mut PriorityFrameQueue, mtu: usize) -> Vec<Vec<u8>> {
+        let mut state = ParserState::new();
+        let mut received = vec![];
+        while let Some(packet) = queue.next_packet(mtu) {
+            assert!(packet.len() <= mtu);
+            for parsed in state.parse(packet).unwrap() {
+                if let ParsedStreamData::Completed(data) = parsed {
+                    received.push(data);
+                }
+            }
+        }
+        assert!(state.is_empty());
+        received
+    }
+
+    #[test]
+    fn urgent_frames_go_first() {
+        let mut queue = PriorityFrameQueue::new(3, 100);
+        queue.push(2, vec![2; 50]).unwrap();
+        queue.push(1, vec![1; 50]).unwrap();
+        queue.push(0, vec![0; 50]).unwrap();
+        queue.push(2, vec![2; 10]).unwrap();
+        queue.push(0, vec![0; 10]).unwrap();
+
+        let received = drain(&mut queue, 7);
+        let order: Vec<u8> = received.iter().map(|m| m[0]).collect();
+        assert_eq!(order, [0, 0, 1, 2, 2]);
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn urgent_frame_waits_for_the_current_frame_only() {
+        let mut queue = PriorityFrameQueue::new(2, 100);
+        queue.push(1, vec![1; 1000]).unwrap();
+        queue.push(1, vec![1; 1000]).unwrap();
+
+        // the first bulk frame starts
+        let first = queue.next_packet(100).unwrap();
+        queue.push(0, b"urgent".to_vec()).unwrap();
+
+        let mut stream = first;
+        while let Some(packet) = queue.next_packet(100) {
+            stream.extend(packet);
+        }
+
+        let mut state = ParserState::new();
+        let received: Vec<Vec<u8>> = state
+            .parse(stream)
+            .unwrap()
+            .into_iter()
+            .filter_map(|p| match p {
+                ParsedStreamData::Completed(data) => Some(data),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(received.len(), 3);
+        assert_eq!(received[1], b"urgent");
+    }
+
+    #[test]
+    fn starvation_protection() {
+        let mut queue = PriorityFrameQueue::new(2, 3);
+        for _ in 0..20 {
+            queue.push(0, vec![0; 5]).unwrap();
+        }
+        queue.push(1, vec![1; 5]).unwrap();
+
+        let received = drain(&mut queue, HDR_SIZE + 5);
+        let position = received.iter().position(|m| m[0] == 1).unwrap();
+        assert_eq!(position, 3);
+    }
+
+    #[test]
+    fn packets_are_the_framed_stream() {
+        let mut queue = PriorityFrameQueue::new(4, 2);
+        let mut sent = vec![];
+        for i in 0..500usize {
+            let message = vec![(i % 256) as u8; (i * 37) % 700];
+            queue.push(i % 4, message.clone()).unwrap();
+            sent.push(message);
+        }
+        queue
+            .push_frame(0, ControlFrame::Ping(1).encode().unwrap())
+            .unwrap();
+
+        for mtu in [1, 5, HDR_SIZE, 1200] {
+            let mut queue_copy = PriorityFrameQueue::new(1, 0);
+            for message in &sent {
+                queue_copy.push(0, message.clone()).unwrap();
+            }
+            assert_eq!(drain(&mut queue_copy, mtu), sent);
+        }
+
+        let mut received = drain(&mut queue, 1200);
+        received.sort();
+        sent.sort();
+        assert_eq!(received, sent);
+    }
+
+    #[test]
+    fn urgent_frame_goes_between_chunks() {
+        let mut queue = PriorityFrameQueue::new(2, 100).with_chunk_size(100);
+        queue.push(1, vec![1; 1000]).unwrap();
+        queue.push(1, vec![2; 1000]).unwrap();
+        assert_eq!(queue.len(), 2);
+
+        // the first chunk of the first bulk frame starts
+        let first = queue.next_packet(50).unwrap();
+        assert_eq!(queue.len(), 2);
+        queue.push(0, b"urgent".to_vec()).unwrap();
+
+        let mut state = ParserState::new();
+        let mut received = vec![];
+        for packet in core::iter::once(first).chain(core::iter::from_fn(|| queue.next_packet(50))) {
+            for parsed in state.parse(packet).unwrap() {
+                if let ParsedStreamData::Completed(data) = parsed {
+                    received.push(data);
+                }
+            }
+        }
+        assert!(state.is_empty());
+        assert!(queue.is_empty());
+        assert_eq!(received, [b"urgent".to_vec(), vec![1; 1000], vec![2; 1000]]);
+    }
+
+    #[test]
+    fn chunked_packets_carry_every_frame() {
+        for chunk_size in [1, 7, HDR_SIZE, 300, 10_000] {
+            let mut queue = PriorityFrameQueue::new(4, 2).with_chunk_size(chunk_size);
+            let mut sent = vec![];
+            for i in 0..200usize {
+                let message = vec![(i % 256) as u8; (i * 37) % 700];
+                queue.push(i % 4, message.clone()).unwrap();
+                sent.push(message);
+            }
+            assert_eq!(queue.len(), 200);
+
+            let mut received = drain(&mut queue, 1200);
+            received.sort();
+            sent.sort();
+            assert_eq!(received, sent);
+        }
+    }
+
+    #[test]
+    fn unknown_class() {
+        let mut queue = PriorityFrameQueue::new(2, 2);
+        assert!(matches!(
+            queue.push(2, vec![]),
+            Err(FrameError::TypeCapacity(_))
+        ));
+        assert_eq!(queue.next_packet(10), None);
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod rpc_cases {
+
+    use std::{
+        sync::{Arc, Barrier, Weak},
+        task::{Context, Poll, Wake},
+        thread::Thread,
+        time::Duration,
+    };
+
+    use crate::{
+        FrameError, FrameParser, FrameTags, ParsedStreamData, ParserState, RpcEndpoint, RpcEvent,
+        stream_frame::{EXTENDED_FLAG, MAGIC_PREFIX},
+    };
+
+    type Endpoint = RpcEndpoint<Box<dyn Fn(Vec<u8>) -> Result<(), FrameError> + Send + Sync>>;
+
+    fn endpoint(to_peer: crossbeam::channel::Sender<Vec<u8>>) -> Arc<Endpoint> {
+        Arc::new(RpcEndpoint::new(Box::new(move |frame| {
+            to_peer
+                .send(frame)
+                .map_err(|e| FrameError::ParsingError(e.to_string()))
+        })))
+    }
+
+    // feeds the client with the packets sent by the server, byte by byte to cross every boundary.
+    // Holds a weak ref, so that dropping the client closes the channels and ends both threads.
+    fn client_reader(
+        client: Weak<Endpoint>,
+        from_server: crossbeam::channel::Receiver<Vec<u8>>,
+    ) -> std::thread::JoinHandle<()> {
+        std::thread::spawn(move || {
+            let mut state = ParserState::new();
+            for frame in from_server {
+                for byte in frame {
+                    for parsed in state.parse(vec![byte]).unwrap() {
+                        let Some(client) = client.upgrade() else {
+                            return;
+                        };
+                        assert!(client.handle_incoming(parsed).is_none());
+                    }
+                }
+            }
+        })
+    }
+
+    #[test]
+    fn tags_round_trip() {
+        for tags in [
+            FrameTags::default(),
+            FrameTags {
+                correlation_id: Some(u64::MAX),
+                ..FrameTags::default()
+            },
+            FrameTags {
+                correlation_id: Some(0),
+                reply: true,
+                topic: None,
+            },
+        ] {
+            let frame = tags.encode(b"tagged").unwrap();
+            let parsed = ParserState::new().parse(frame).unwrap();
+
+            match &parsed[..] {
+                [ParsedStreamData::Completed(payload)] if tags.is_empty() => {
+                    assert_eq!(payload, b"tagged")
+                }
+                [ParsedStreamData::Tagged(parsed_tags, payload)] => {
+                    assert_eq!(*parsed_tags, tags);
+                    assert_eq!(payload, b"tagged");
+                }
+                _ => panic!("unexpected parsing for {tags:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn invalid_tags_are_refused() {
+        let orphan_reply = FrameTags {
+            reply: true,
+            ..FrameTags::default()
+        };
+        assert!(matches!(
+            orphan_reply.encode(b"x"),
+            Err(FrameError::ParsingError(_))
+        ));
+
+        let extended = |body: &[u8]| {
+            let mut frame = MAGIC_PREFIX.to_vec();
+            frame.extend_from_slice(&(body.len() as u32 | EXTENDED_FLAG).to_be_bytes());
+            frame.extend_from_slice(body);
+            frame
+        };
+        // reply flag without id, id cut short, unknown flag, ping with an id
+        for body in [
+            &[0u8, 0x02][..],
+            &[0, 0x01, 0, 0, 0, 1],
+            &[0, 0x80],
+            &[1, 0x01, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 9],
+        ] {
+            assert!(matches!(
+                extended(body).parse_frame_header(None, None),
+                Err(FrameError::ParsingError(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn replies_find_their_request() {
+        let (to_server, from_client) = crossbeam::channel::unbounded::<Vec<u8>>();
+        let (to_client, from_server) = crossbeam::channel::unbounded::<Vec<u8>>();
+
+        let client = endpoint(to_server);
+        let server = endpoint(to_client);
+        let reader = client_reader(Arc::downgrade(&client), from_server);
+
+        // answers the requests in reverse order, by batch of 8
+        let server_thread = std::thread::spawn(move || {
+            let mut state = ParserState::new();
+            let mut batch = vec![];
+            for packet in from_client {
+                for parsed in state.parse(packet).unwrap() {
+                    let Some(RpcEvent::Request { id, payload }) = server.handle_incoming(parsed)
+                    else {
+                        panic!("request expected");
+                    };
+                    batch.push((id, payload));
+                }
+                if batch.len() == 8 {
+                    for (id, payload) in batch.drain(..).rev() {
+                        server.respond(id, &payload.to_ascii_uppercase()).unwrap();
+                    }
+                }
+            }
+        });
+
+        let barrier = Arc::new(Barrier::new(8));
+        let callers: Vec<_> = (0..8)
+            .map(|caller| {
+                let client = client.clone();
+                let barrier = barrier.clone();
+                std::thread::spawn(move || {
+                    for i in 0..50 {
+                        barrier.wait();
+                        let request = format!("caller {caller} request {i}");
+                        let handle = client.request(request.as_bytes()).unwrap();
+                        assert_eq!(
+                            handle.wait_timeout(Duration::from_secs(10)).unwrap(),
+                            request.to_ascii_uppercase().as_bytes()
+                        );
+                    }
+                })
+            })
+            .collect();
+
+        for caller in callers {
+            caller.join().unwrap();
+        }
+        assert_eq!(client.pending(), 0);
+
+        drop(client);
+        reader.join().unwrap();
+        server_thread.join().unwrap();
+    }
+
+    #[test]
+    fn timed_out_request_drops_the_late_reply() {
+        let (to_server, from_client) = crossbeam::channel::unbounded::<Vec<u8>>();
+        let (to_client, from_server) = crossbeam::channel::unbounded::<Vec<u8>>();
+
+        let client = endpoint(to_server);
+        let server = endpoint(to_client);
+
+        let handle = client.request(b"slow").unwrap();
+        let id = handle.id();
+        assert!(matches!(
+            handle.wait_timeout(Duration::from_millis(20)),
+            Err(FrameError::Timeout)
+        ));
+        assert_eq!(client.pending(), 0);
+
+        // the server answers anyway
+        for parsed in from_client
+            .recv()
+            .unwrap()
+            .parse_frame_header(None, None)
+            .unwrap()
+        {
+            let Some(RpcEvent::Request { id: request_id, .. }) = server.handle_incoming(parsed)
+            else {
+                panic!("request expected");
+            };
+            assert_eq!(request_id, id);
+            server.respond(request_id, b"too late").unwrap();
+        }
+        for parsed in from_server
+            .recv()
+            .unwrap()
+            .parse_frame_header(None, None)
+            .unwrap()
+        {
+            assert!(client.handle_incoming(parsed).is_none());
+        }
+        assert_eq!(client.pending(), 0);
+        assert!(!client.cancel(id));
+    }
+
+    #[test]
+    fn cancel_wakes_the_waiter() {
+        let (to_server, _from_client) = crossbeam::channel::unbounded::<Vec<u8>>();
+        let client = endpoint(to_server);
+
+        let handle = client.request(b"never answered").unwrap();
+        let id = handle.id();
+        let waiter = std::thread::spawn(move || handle.wait());
+
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(client.cancel(id));
+        assert!(matches!(waiter.join().unwrap(), Err(FrameError::Cancelled)));
+        assert_eq!(client.pending(), 0);
+
+        // dropping the endpoint cancels what is left
+        let handle = client.request(b"orphan").unwrap();
+        drop(client);
+        assert!(matches!(handle.wait(), Err(FrameError::Cancelled)));
+    }
+
+    #[test]
+    fn failed_send_leaves_nothing_pending() {
+        let (to_server, from_client) = crossbeam::channel::unbounded::<Vec<u8>>();
+        let client = endpoint(to_server);
+        drop(from_client);
+
+        assert!(client.request(b"lost").is_err());
+        assert_eq!(client.pending(), 0);
+    }
+
+    struct ThreadWaker(Thread);
+
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    fn block_on<F: Future + Unpin>(mut future: F) -> F::Output {
+        let waker = Arc::new(ThreadWaker(std::thread::current())).into();
+        let mut cx = Context::from_waker(&waker);
+        loop {
+            if let Poll::Ready(output) = std::pin::Pin::new(&mut future).poll(&mut cx) {
+                return output;
+            }
+            std::thread::park();
+        }
+    }
+
+    #[test]
+    fn handle_is_a_future() {
+        let (to_server, from_client) = crossbeam::channel::unbounded::<Vec<u8>>();
+        let (to_client, from_server) = crossbeam::channel::unbounded::<Vec<u8>>();
+
+        let client = endpoint(to_server);
+        let server = endpoint(to_client);
+        let reader = client_reader(Arc::downgrade(&client), from_server);
+
+        let server_thread = std::thread::spawn(move || {
+            let mut state = ParserState::new();
+            for packet in from_client {
+                for parsed in state.parse(packet).unwrap() {
+                    if let Some(RpcEvent::Request { id, payload }) = server.handle_incoming(parsed)
+                    {
+                        std::thread::sleep(Duration::from_millis(10));
+                        server.respond(id, &payload.repeat(2)).unwrap();
+                    }
+                }
+            }
+        });
+
+        let handle = client.request(b"async").unwrap();
+        assert_eq!(block_on(handle).unwrap(), b"asyncasync");
+
+        drop(client);
+        reader.join().unwrap();
+        server_thread.join().unwrap();
+    }
+}
+
+#[cfg(test)]
+mod topic_cases {
+
+    use crate::{
+        FrameError, FrameTags, MAX_TOPIC_LEN, ParsedStreamData, ParserState, Topic, TopicRouter,
+    };
+
+    fn topic_frame(topic: Topic, payload: &[u8]) -> Vec<u8> {
+        FrameTags {
+            topic: Some(topic),
+            ..FrameTags::default()
+        }
+        .encode(payload)
+        .unwrap()
+    }
+
+    #[test]
+    fn topic_wire_format() {
+        let frame = topic_frame(Topic::Name("a/b".to_string()), b"xy");
+        assert_eq!(&frame[8..12], &[0x80, 0, 0, 8]);
+        assert_eq!(&frame[12..], &[0, 0x04, 3, b'a', b'/', b'b', b'x', b'y']);
+
+        // FNV-1a of "a"
+        let frame = topic_frame(Topic::hashed("a"), b"");
+        assert_eq!(&frame[12..], &[0, 0x08, 0xe4, 0x0c, 0x29, 0x2c]);
+    }
+
+    #[test]
+    fn topic_round_trip() {
+        for tags in [
+            FrameTags {
+                topic: Some(Topic::Name("sensors/kitchen/temp".to_string())),
+                ..FrameTags::default()
+            },
+            FrameTags {
+                topic: Some(Topic::Name("x".repeat(MAX_TOPIC_LEN))),
+                ..FrameTags::default()
+            },
+            FrameTags {
+                correlation_id: Some(9),
+                reply: false,
+                topic: Some(Topic::hashed("rpc/calls")),
+            },
+        ] {
+            let parsed = ParserState::new()
+                .parse(tags.encode(b"body").unwrap())
+                .unwrap();
+            match &parsed[..] {
+                [ParsedStreamData::Tagged(parsed_tags, payload)] => {
+                    assert_eq!(*parsed_tags, tags);
+                    assert_eq!(payload, b"body");
+                }
+                _ => panic!("tagged frame expected for {tags:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn invalid_topics_are_refused() {
+        for name in [
+            String::new(),
+            "x".repeat(MAX_TOPIC_LEN + 1),
+            "a/+".to_string(),
+            "#".to_string(),
+        ] {
+            assert!(matches!(
+                FrameTags {
+                    topic: Some(Topic::Name(name)),
+                    ..FrameTags::default()
+                }
+                .encode(b""),
+                Err(FrameError::ParsingError(_))
+            ));
+        }
+
+        let mut router = TopicRouter::new();
+        for filter in ["", "a/#/b", "a+/b", "a/b#", "##"] {
+            assert!(
+                router.subscribe(filter, |_, _| {}).is_err(),
+                "filter [{filter}]"
+            );
+        }
+
+        // both topic flags, truncated name, invalid UTF-8
+        for body in [
+            &[0u8, 0x0c, 1, b'a', 0, 0, 0, 1][..],
+            &[0, 0x04, 5, b'a'],
+            &[0, 0x04, 1, 0xff],
+        ] {
+            let mut frame = (body.len() as u32 | crate::EXTENDED_FLAG)
+                .to_be_bytes()
+                .to_vec();
+            frame.splice(0..0, crate::MAGIC_PREFIX);
+            frame.extend_from_slice(body);
+            assert!(matches!(
+                ParserState::new().parse(frame),
+                Err(FrameError::ParsingError(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn wildcard_matching() {
+        let filters = ["a/b/c", "a/+/c", "a/#", "#", "+", "+/+", "a/+", "b/#"];
+        let mut router = TopicRouter::new();
+        let (hits_sender, hits) = crossbeam::channel::unbounded();
+        for filter in filters {
+            let hits_sender = hits_sender.clone();
+            router
+                .subscribe(filter, move |_, _| hits_sender.send(filter).unwrap())
+                .unwrap();
+        }
+
+        let expected: [(&str, &[&str]); 6] = [
+            ("a/b/c", &["a/b/c", "a/+/c", "a/#", "#"]),
+            ("a/x/c", &["a/+/c", "a/#", "#"]),
+            ("a", &["a/#", "#", "+"]),
+            ("a/b", &["a/#", "#", "+/+", "a/+"]),
+            ("b", &["#", "+", "b/#"]),
+            ("c/b/a", &["#"]),
+        ];
+        for (topic, matching) in expected {
+            let delivered = router.route(&Topic::Name(topic.to_string()), b"");
+            let got: Vec<&str> = hits.try_iter().collect();
+            assert_eq!(got, matching, "topic [{topic}]");
+            assert_eq!(delivered, matching.len());
+        }
+
+        // a hashed topic only reaches the subscribers of the exact name
+        assert_eq!(router.route(&Topic::hashed("a/b/c"), b""), 1);
+        assert_eq!(hits.try_iter().collect::<Vec<_>>(), ["a/b/c"]);
+        assert_eq!(router.route(&Topic::hashed("a/x/c"), b""), 0);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn router_dispatches_a_stream() {
+        use crate::FrameWriter;
+
+        let (to_router, from_publisher) = crossbeam::channel::unbounded::<Vec<u8>>();
+        let (temp_sender, temps) = crossbeam::channel::unbounded();
+        let (alert_sender, alerts) = crossbeam::channel::unbounded();
+
+        let publisher = std::thread::spawn(move || {
+            let mut stream = vec![];
+            for i in 0..200u32 {
+                let room = ["kitchen", "garage"][i as usize % 2];
+                stream.extend(topic_frame(
+                    Topic::Name(format!("sensors/{room}/temp")),
+                    &i.to_be_bytes(),
+                ));
+                if i % 50 == 0 {
+                    stream.extend(topic_frame(Topic::hashed("alerts"), &i.to_be_bytes()));
+                    stream.extend(b"untagged".to_vec().prepend_frame().unwrap());
+                }
+            }
+            for packet in stream.chunks(37) {
+                to_router.send(packet.to_vec()).unwrap();
+            }
+        });
+
+        let router_thread = std::thread::spawn(move || {
+            let mut router = TopicRouter::new();
+            router
+                .subscribe("sensors/+/temp", move |topic, payload| {
+                    temp_sender.send((topic.clone(), payload.to_vec())).unwrap();
+                })
+                .unwrap();
+            router
+                .subscribe("alerts", move |_, payload| {
+                    alert_sender.send(payload.to_vec()).unwrap();
+                })
+                .unwrap();
+            let kitchen = router.subscribe_channel("sensors/kitchen/#").unwrap();
+
+            let mut state = ParserState::new();
+            let mut untagged = 0;
+            for packet in from_publisher {
+                for parsed in state.parse(packet).unwrap() {
+                    match router.dispatch(parsed) {
+                        Some(ParsedStreamData::Completed(data)) => {
+                            assert_eq!(data, b"untagged");
+                            untagged += 1;
+                        }
+                        None => {}
+                        Some(_) => panic!("unexpected output"),
+                    }
+                }
+            }
+            (untagged, kitchen.try_iter().count())
+        });
+
+        publisher.join().unwrap();
+        let (untagged, kitchen) = router_thread.join().unwrap();
+        assert_eq!(untagged, 4);
+        assert_eq!(kitchen, 100);
+
+        let temps: Vec<(Topic, Vec<u8>)> = temps.iter().collect();
+        assert_eq!(temps.len(), 200);
+        for (i, (topic, payload)) in temps.iter().enumerate() {
+            let room = ["kitchen", "garage"][i % 2];
+            assert_eq!(*topic, Topic::Name(format!("sensors/{room}/temp")));
+            assert_eq!(*payload, (i as u32).to_be_bytes());
+        }
+        assert_eq!(alerts.iter().count(), 4);
+    }
+
+    #[test]
+    fn unsubscribe_stops_delivery() {
+        let mut router = TopicRouter::new();
+        let id = router.subscribe("a", |_, _| {}).unwrap();
+        assert_eq!(router.route(&Topic::Name("a".to_string()), b""), 1);
+        assert!(router.unsubscribe(id));
+        assert!(!router.unsubscribe(id));
+        assert_eq!(router.route(&Topic::Name("a".to_string()), b""), 0);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn dropped_channel_is_unsubscribed() {
+        let mut router = TopicRouter::new();
+        let kept = router.subscribe_channel("a/#").unwrap();
+        let dropped = router.subscribe_channel("a/b").unwrap();
+        router.subscribe("c", |_, _| {}).unwrap();
+        assert_eq!(router.len(), 3);
+
+        drop(dropped);
+        // removed by the first frame it should have received
+        assert_eq!(router.route(&Topic::Name("c".to_string()), b""), 1);
+        assert_eq!(router.len(), 3);
+        assert_eq!(router.route(&Topic::Name("a/b".to_string()), b"x"), 1);
+        assert_eq!(router.len(), 2);
+        assert_eq!(kept.try_iter().count(), 1);
+
+        drop(kept);
+        assert_eq!(router.route(&Topic::Name("a/b".to_string()), b"x"), 0);
+        assert_eq!(router.len(), 1);
+    }
+
+    #[test]
+    fn unmatched_topic_frames_are_handed_back() {
+        let mut router = TopicRouter::new();
+        router.subscribe("a", |_, _| {}).unwrap();
+
+        let mut state = ParserState::new();
+        let mut parsed = state
+            .parse(topic_frame(Topic::Name("a".to_string()), b"routed"))
+            .unwrap();
+        assert!(router.dispatch(parsed.pop().unwrap()).is_none());
+
+        let mut parsed = state
+            .parse(topic_frame(Topic::Name("b".to_string()), b"unmatched"))
+            .unwrap();
+        assert!(matches!(
+            router.dispatch(parsed.pop().unwrap()),
+            Some(ParsedStreamData::Tagged(FrameTags { topic: Some(Topic::Name(name)), .. }, payload))
+                if name == "b" && payload == b"unmatched"
+        ));
+    }
+}
+
+#[cfg(test)]
+mod inspect_cases {
+
+    use crate::{
+        ControlFrame, FrameError, FrameTags, FrameWriter, HDR_SIZE, InspectEvent, ParsedStreamData,
+        inspect,
+    };
+
+    #[test]
+    fn clean_capture() {
+        let mut capture = b"one".to_vec().prepend_frame().unwrap();
+        capture.extend(ControlFrame::Ping(3).encode().unwrap());
+        capture.extend(Vec::new().prepend_frame().unwrap());
+        capture.extend(
+            FrameTags {
+                correlation_id: Some(1),
+                ..FrameTags::default()
+            }
+            .encode(b"two")
+            .unwrap(),
+        );
+
+        let events = inspect(&capture);
+        assert_eq!(events.len(), 4);
+        assert!(events.iter().all(|event| !event.is_anomaly()));
+        assert_eq!(
+            events.iter().map(InspectEvent::offset).collect::<Vec<_>>(),
+            [0, 15, 37, 49]
+        );
+        assert!(matches!(
+            &events[1],
+            InspectEvent::Frame {
+                len: 10,
+                parsed: Ok(ParsedStreamData::Control(ControlFrame::Ping(3))),
+                ..
+            }
+        ));
+        assert!(matches!(
+            &events[3],
+            InspectEvent::Frame { parsed: Ok(ParsedStreamData::Tagged(tags, payload)), .. }
+                if tags.correlation_id == Some(1) && payload == b"two"
+        ));
+    }
+
+    #[test]
+    fn garbage_and_truncation() {
+        let frame = b"payload".to_vec().prepend_frame().unwrap();
+
+        // garbage between frames, resync on the next magic prefix
+        let mut capture = b"noise".to_vec();
+        capture.extend(&frame);
+        let events = inspect(&capture);
+        assert!(matches!(
+            events[..],
+            [
+                InspectEvent::BadMagic { offset: 0 },
+                InspectEvent::Resync {
+                    offset: 5,
+                    skipped: 5
+                },
+                InspectEvent::Frame { offset: 5, .. },
+            ]
+        ));
+
+        // no magic prefix at all
+        let events = inspect(&[0xAA; 40]);
+        assert!(matches!(
+            events[..],
+            [
+                InspectEvent::BadMagic { offset: 0 },
+                InspectEvent::Unsynced { offset: 0, len: 40 }
+            ]
+        ));
+
+        // EOF inside the header, then inside the body
+        for cut in 1..frame.len() {
+            let events = inspect(&frame[..cut]);
+            match &events[..] {
+                [InspectEvent::TruncatedHeader { available, .. }] => {
+                    assert!(cut < HDR_SIZE);
+                    assert_eq!(*available, cut);
+                }
+                [InspectEvent::TruncatedBody { len, available, .. }] => {
+                    assert!(cut >= HDR_SIZE);
+                    assert_eq!((*len, *available), (7, cut - HDR_SIZE));
+                }
+                _ => panic!("truncation expected at {cut}"),
+            }
+        }
+    }
+
+    #[test]
+    fn parser_errors_are_reported() {
+        // extended frame with an unknown flag, the parser refuses it
+        let mut capture = crate::MAGIC_PREFIX.to_vec();
+        capture.extend_from_slice(&(2 | crate::EXTENDED_FLAG).to_be_bytes());
+        capture.extend_from_slice(&[0, 0x80]);
+        capture.extend(b"next".to_vec().prepend_frame().unwrap());
+
+        let events = inspect(&capture);
+        assert!(matches!(
+            &events[..],
+            [
+                InspectEvent::Frame {
+                    parsed: Err(FrameError::ParsingError(_)),
+                    ..
+                },
+                InspectEvent::Frame {
+                    offset: 14,
+                    parsed: Ok(ParsedStreamData::Completed(_)),
+                    ..
+                },
+            ]
+        ));
+        assert!(events[0].is_anomaly());
+    }
+}
+
+#[cfg(test)]
+mod mask_cases {
+
+    use proptest::prelude::*;
+
+    use crate::{
+        ControlFrame, FrameError, FrameParser, FrameTags, FrameWriter, HDR_SIZE, MAGIC_PREFIX,
+        ParsedStreamData, Topic, escape_frame, mask_frame,
+    };
+
+    const KEY: [u8; 4] = [0x37, 0xfa, 0x21, 0x3d];
+
+    type Parsed = (Option<FrameTags>, Option<ControlFrame>, Vec<u8>);
+
+    fn parsed(frame: &[u8]) -> Parsed {
+        let mut output = frame.parse_frame_header(None, None).unwrap();
+        assert_eq!(output.len(), 1);
+        match output.pop().unwrap() {
+            ParsedStreamData::Completed(payload) => (None, None, payload),
+            ParsedStreamData::Tagged(tags, payload) => (Some(tags), None, payload),
+            ParsedStreamData::Control(control) => (None, Some(control), Vec::new()),
+            _ => panic!("expected a whole frame"),
+        }
+    }
+
+    #[test]
+    fn fields_and_payload_are_masked() {
+        let tags = FrameTags {
+            correlation_id: Some(0x0102_0304_0506_0708),
+            topic: Some(Topic::Name("sensors/temp".to_string())),
+            ..Default::default()
+        };
+        let frame = tags.encode(b"21.5").unwrap();
+        let masked = mask_frame(&frame, KEY).unwrap();
+
+        // only the prefix, the length field, the kind and the flags are left in clear
+        assert_eq!(masked[..MAGIC_PREFIX.len()], MAGIC_PREFIX);
+        assert_eq!(masked[HDR_SIZE..HDR_SIZE + 2], [0, 0x01 | 0x04 | 0x10]);
+        assert!(!masked.windows(12).any(|window| window == b"sensors/temp"));
+        assert!(
+            !masked
+                .windows(8)
+                .any(|window| window == [1, 2, 3, 4, 5, 6, 7, 8])
+        );
+        assert!(!masked.windows(4).any(|window| window == b"21.5"));
+        assert_eq!(parsed(&masked), parsed(&frame));
+    }
+
+    #[test]
+    fn masked_frames_parse_as_the_frames_they_mask() {
+        let payload = vec![0xaa; 100];
+        let frames = [
+            payload.clone().prepend_frame().unwrap(),
+            FrameTags {
+                correlation_id: Some(3),
+                reply: true,
+                topic: Some(Topic::Name("a/b".into())),
+            }
+            .encode(&payload)
+            .unwrap(),
+            FrameTags {
+                topic: Some(Topic::hashed("a/b")),
+                ..FrameTags::default()
+            }
+            .encode(&payload)
+            .unwrap(),
+            ControlFrame::close(ControlFrame::CLOSE_GOING_AWAY, "bye")
+                .encode()
+                .unwrap(),
+            Vec::new().prepend_frame().unwrap(),
+        ];
+        for frame in frames {
+            let masked = mask_frame(&frame, KEY).unwrap();
+            assert_eq!(parsed(&masked), parsed(&frame));
+            // and it can be escaped too
+            assert_eq!(parsed(&escape_frame(&masked).unwrap()), parsed(&frame));
+        }
+
+        // the key is the first field, the other fields and the payload follow XORed with it
+        let masked = mask_frame(&payload.clone().prepend_frame().unwrap(), KEY).unwrap();
+        assert_eq!(
+            masked[HDR_SIZE..HDR_SIZE + 6],
+            [0, 0x10, 0x37, 0xfa, 0x21, 0x3d]
+        );
+        assert_eq!(
+            masked[HDR_SIZE + 6..HDR_SIZE + 10],
+            [0x9d, 0x50, 0x8b, 0x97]
+        );
+        let tagged = FrameTags {
+            correlation_id: Some(0),
+            ..Default::default()
+        };
+        let masked = mask_frame(&tagged.encode(&[]).unwrap(), KEY).unwrap();
+        assert_eq!(
+            masked[HDR_SIZE..],
+            [
+                0, 0x11, 0x37, 0xfa, 0x21, 0x3d, 0x37, 0xfa, 0x21, 0x3d, 0x37, 0xfa, 0x21, 0x3d
+            ]
+        );
+    }
+
+    #[test]
+    fn mask_frame_errors() {
+        let frame = b"payload".to_vec().prepend_frame().unwrap();
+        let masked = mask_frame(&frame, KEY).unwrap();
+        for invalid in [
+            &frame[..frame.len() - 1],
+            &masked,
+            &escape_frame(&frame).unwrap(),
+        ] {
+            assert!(matches!(
+                mask_frame(invalid, KEY),
+                Err(FrameError::ParsingError(_))
+            ));
+        }
+
+        // a masked frame cut before the end of its key
+        let mut truncated = masked[..HDR_SIZE + 4].to_vec();
+        truncated[8..12].copy_from_slice(&(4 | crate::EXTENDED_FLAG).to_be_bytes());
+        assert!(matches!(
+            truncated.parse_frame_header(None, None),
+            Err(FrameError::ParsingError(_))
+        ));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn random_masking_keys_differ() {
+        let keys: std::collections::HashSet<[u8; 4]> =
+            (0..100).map(|_| crate::random_masking_key()).collect();
+        assert!(keys.len() > 90);
+    }
+
+    proptest! {
+        #[test]
+        fn masking_round_trips(
+            payload in prop::collection::vec(any::<u8>(), 0..300),
+            key in any::<[u8; 4]>(),
+        ) {
+            let masked = mask_frame(&payload.clone().prepend_frame().unwrap(), key).unwrap();
+            prop_assert_eq!(parsed(&masked), (None, None, payload));
+        }
+    }
+}
+
+#[cfg(test)]
+mod padding_cases {
+
+    use proptest::prelude::*;
+
+    use crate::{
+        ControlFrame, FrameError, FrameFormat, FrameParser, FrameTags, FrameWriter, HDR_SIZE,
+        MAX_BODY_LEN, MagicLength, PaddingPolicy, ParsedStreamData, ParserState, Topic,
+        escape_frame, mask_frame, pad_frame,
+    };
+
+    type Parsed = (Option<FrameTags>, Option<ControlFrame>, Vec<u8>);
+
+    fn parsed(frame: &[u8]) -> Parsed {
+        let mut output = frame.parse_frame_header(None, None).unwrap();
+        assert_eq!(output.len(), 1);
+        match output.pop().unwrap() {
+            ParsedStreamData::Completed(payload) => (None, None, payload),
+            ParsedStreamData::Tagged(tags, payload) => (Some(tags), None, payload),
+            ParsedStreamData::Control(control) => (None, Some(control), Vec::new()),
+            _ => panic!("expected a whole frame"),
+        }
+    }
+
+    #[test]
+    fn padded_lens() {
+        for (policy, len, padded) in [
+            (PaddingPolicy::None, 13, Some(13)),
+            (PaddingPolicy::PowerOfTwo, 0, Some(1)),
+            (PaddingPolicy::PowerOfTwo, 13, Some(16)),
+            (PaddingPolicy::PowerOfTwo, 16, Some(16)),
+            (PaddingPolicy::PowerOfTwo, usize::MAX, None),
+            (PaddingPolicy::Block(0), 13, Some(13)),
+            (PaddingPolicy::Block(1), 13, Some(13)),
+            (PaddingPolicy::Block(10), 13, Some(20)),
+            (PaddingPolicy::Block(10), 20, Some(20)),
+            (PaddingPolicy::Block(10), usize::MAX, None),
+        ] {
+            assert_eq!(policy.padded_len(len), padded, "{policy:?} of {len}");
+        }
+    }
+
+    #[test]
+    fn padded_frames_parse_as_the_frames_they_pad() {
+        let payload = vec![0xaa; 100];
+        let frames = [
+            payload.clone().prepend_frame().unwrap(),
+            FrameTags {
+                correlation_id: Some(3),
+                reply: true,
+                topic: Some(Topic::Name("a/b".into())),
+            }
+            .encode(&payload)
+            .unwrap(),
+            ControlFrame::close(ControlFrame::CLOSE_GOING_AWAY, "bye")
+                .encode()
+                .unwrap(),
+            Vec::new().prepend_frame().unwrap(),
+        ];
+        for frame in frames {
+            for policy in [PaddingPolicy::PowerOfTwo, PaddingPolicy::Block(48)] {
+                let padded = pad_frame(&frame, policy).unwrap();
+                let body_len = padded.len() - HDR_SIZE;
+                assert_eq!(policy.padded_len(body_len), Some(body_len));
+                assert_eq!(parsed(&padded), parsed(&frame));
+                // then masked and escaped
+                let masked = mask_frame(&padded, [1, 2, 3, 4]).unwrap();
+                assert_eq!(parsed(&masked), parsed(&frame));
+                assert_eq!(parsed(&escape_frame(&masked).unwrap()), parsed(&frame));
+            }
+            assert_eq!(pad_frame(&frame, PaddingPolicy::None).unwrap(), frame);
+        }
+
+        // the payload len is the last field, zeros follow the payload
+        let padded = pad_frame(
+            &b"abc".to_vec().prepend_frame().unwrap(),
+            PaddingPolicy::Block(16),
+        )
+        .unwrap();
+        assert_eq!(
+            padded[HDR_SIZE..],
+            [0, 0x20, 0, 0, 0, 3, b'a', b'b', b'c', 0, 0, 0, 0, 0, 0, 0]
+        );
+    }
+
+    #[test]
+    fn pad_frame_errors() {
+        let frame = b"payload".to_vec().prepend_frame().unwrap();
+        let padded = pad_frame(&frame, PaddingPolicy::PowerOfTwo).unwrap();
+        for invalid in [
+            &frame[..frame.len() - 1],
+            &padded,
+            &mask_frame(&frame, [1, 2, 3, 4]).unwrap(),
+            &escape_frame(&frame).unwrap(),
+        ] {
+            assert!(matches!(
+                pad_frame(invalid, PaddingPolicy::PowerOfTwo),
+                Err(FrameError::ParsingError(_))
+            ));
+        }
+        assert!(matches!(
+            pad_frame(&frame, PaddingPolicy::Block(MAX_BODY_LEN + 1)),
+            Err(FrameError::TypeCapacity(_))
+        ));
+
+        // a payload len over what the body holds
+        let mut lying = padded.clone();
+        lying[HDR_SIZE + 2..HDR_SIZE + 6].copy_from_slice(&100u32.to_be_bytes());
+        assert!(matches!(
+            lying.parse_frame_header(None, None),
+            Err(FrameError::ParsingError(_))
+        ));
+    }
+
+    #[test]
+    fn prepend_frame_padded_pads_like_pad_frame() {
+        use alloc::{borrow::Cow, collections::VecDeque};
+
+        let payload = b"no thanks, maybe later".to_vec();
+        for policy in [
+            PaddingPolicy::None,
+            PaddingPolicy::PowerOfTwo,
+            PaddingPolicy::Block(64),
+        ] {
+            let expected = pad_frame(&payload.clone().prepend_frame().unwrap(), policy).unwrap();
+
+            // a deque whose content wraps around
+            let mut deque = VecDeque::with_capacity(payload.len());
+            deque.extend(&[0; 10]);
+            deque.extend(&payload[..10]);
+            deque.drain(..10);
+            deque.extend(&payload[10..]);
+            assert!(!deque.as_slices().1.is_empty());
+
+            for padded in [
+                payload.clone().prepend_frame_padded(policy).unwrap(),
+                payload
+                    .clone()
+                    .into_boxed_slice()
+                    .prepend_frame_padded(policy)
+                    .unwrap(),
+                Cow::Borrowed(&payload[..])
+                    .prepend_frame_padded(policy)
+                    .unwrap(),
+                deque.prepend_frame_padded(policy).unwrap(),
+            ] {
+                assert_eq!(padded, expected, "{policy:?}");
+            }
+
+            let mut encoded = b"before".to_vec();
+            MagicLength::with_padding(policy)
+                .encode(&payload, &mut encoded)
+                .unwrap();
+            assert_eq!(encoded[6..], expected, "{policy:?}");
+        }
+
+        assert!(matches!(
+            payload.prepend_frame_padded(PaddingPolicy::Block(MAX_BODY_LEN + 1)),
+            Err(FrameError::TypeCapacity(_))
+        ));
+    }
+
+    #[test]
+    fn writers_pad_with_their_policy() {
+        let messages: [&[u8]; 3] = [b"", b"yes", b"no thanks, maybe later"];
+        for (policy, sizes) in [
+            (PaddingPolicy::None, [12, 15, 34]),
+            (PaddingPolicy::PowerOfTwo, [20, 28, 44]),
+            (PaddingPolicy::Block(64), [76, 76, 76]),
+        ] {
+            let writer = MagicLength::with_padding(policy);
+            let mut stream = Vec::new();
+            for (message, size) in messages.iter().zip(sizes) {
+                let start = stream.len();
+                writer.encode(message, &mut stream).unwrap();
+                assert_eq!(stream.len() - start, size, "{policy:?}");
+            }
+
+            let mut received = Vec::new();
+            MagicLength::new()
+                .decode(&stream, &mut |message| received.push(message))
+                .unwrap();
+            assert_eq!(received, messages);
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn padding_round_trips(
+            payloads in prop::collection::vec(prop::collection::vec(any::<u8>(), 0..300), 1..10),
+            block in 0usize..100,
+            cut in 1usize..50,
+        ) {
+            let mut stream = Vec::new();
+            for payload in &payloads {
+                let frame = payload.clone().prepend_frame().unwrap();
+                for policy in [PaddingPolicy::PowerOfTwo, PaddingPolicy::Block(block)] {
+                    stream.extend(pad_frame(&frame, policy).unwrap());
+                }
+            }
+
+            let mut state = ParserState::new();
+            let mut received = Vec::new();
+            for packet in stream.chunks(cut) {
+                for parsed in state.parse(packet).unwrap() {
+                    match parsed {
+                        ParsedStreamData::Completed(payload) => received.push(payload),
+                        _ => prop_assert!(false, "expected data frames"),
+                    }
+                }
+            }
+            let expected: Vec<_> = payloads.iter().flat_map(|p| [p.clone(), p.clone()]).collect();
+            prop_assert_eq!(received, expected);
+        }
+    }
+}
+
+#[cfg(test)]
+mod chunk_cases {
+
+    use crate::{
+        ControlFrame, FrameError, FrameParser, FrameTags, FrameWriter, HDR_SIZE, PaddingPolicy,
+        ParsedStreamData, ParserState, chunk_frame,
+        extended::{FLAG_CONTINUED, encode_chunk},
+        mask_frame, pad_frame,
+    };
+
+    #[test]
+    fn raw_parser_hands_out_the_chunks() {
+        let frame = vec![3; 30].prepend_frame().unwrap();
+        let chunks = chunk_frame(&frame, 9, 20).unwrap();
+        assert_eq!(chunks.len(), 3);
+
+        let parsed = chunks.concat().parse_frame_header(None, None).unwrap();
+        let mut pieces = vec![];
+        for (i, p) in parsed.into_iter().enumerate() {
+            let ParsedStreamData::Chunk {
+                stream: 9,
+                continued,
+                piece,
+            } = p
+            else {
+                panic!("expected a chunk of stream 9");
+            };
+            assert_eq!(continued, i < 2);
+            pieces.extend(piece);
+        }
+        assert_eq!(pieces, frame);
+    }
+
+    #[test]
+    fn interleaved_streams_are_reassembled() {
+        let tags = FrameTags {
+            correlation_id: Some(5),
+            ..Default::default()
+        };
+        let tagged = chunk_frame(&tags.encode(&[1; 50]).unwrap(), 1, 16).unwrap();
+        // the close completes last, data after it would be refused
+        let close = chunk_frame(
+            &ControlFrame::close(ControlFrame::CLOSE_NORMAL, "done")
+                .encode()
+                .unwrap(),
+            2,
+            3,
+        )
+        .unwrap();
+
+        let mut stream = vec![];
+        for i in 0..tagged.len().max(close.len()) {
+            stream.extend(tagged.get(i).into_iter().flatten());
+            stream.extend(close.get(i).into_iter().flatten());
+            if i == 1 {
+                stream.extend(b"plain".to_vec().prepend_frame().unwrap());
+            }
+        }
+
+        for packet_size in [1, 7, 100, stream.len()] {
+            let mut state = ParserState::new();
+            let mut events = vec![];
+            for packet in stream.chunks(packet_size) {
+                // checkpoint between every packet
+                state = ParserState::from_bytes(&state.to_bytes()).unwrap();
+                events.extend(state.parse(packet).unwrap());
+            }
+            assert!(state.is_empty());
+            assert!(matches!(&events[..], [
+                ParsedStreamData::Completed(plain),
+                ParsedStreamData::Tagged(parsed_tags, payload),
+                ParsedStreamData::Control(ControlFrame::Close { code: 0, reason }),
+            ] if plain == b"plain" && *parsed_tags == tags && *payload == [1; 50] && reason == "done"));
+        }
+    }
+
+    #[test]
+    fn chunks_can_be_masked_and_padded() {
+        let frame = vec![8; 64].prepend_frame().unwrap();
+        let mut stream = vec![];
+        for chunk in chunk_frame(&frame, 0, 10).unwrap() {
+            let chunk = pad_frame(&chunk, PaddingPolicy::Block(32)).unwrap();
+            stream.extend(mask_frame(&chunk, [1, 2, 3, 4]).unwrap());
+        }
+
+        let mut state = ParserState::new();
+        assert!(matches!(
+            &state.parse(stream).unwrap()[..],
+            [ParsedStreamData::Completed(data)] if *data == [8; 64]
+        ));
+    }
+
+    #[test]
+    fn pending_chunks_keep_the_state_busy() {
+        let frame = vec![8; 64].prepend_frame().unwrap();
+        let chunks = chunk_frame(&frame, 4, 40).unwrap();
+
+        let mut state = ParserState::new();
+        assert!(state.parse(chunks[0].as_slice()).unwrap().is_empty());
+        assert!(!state.is_empty());
+
+        let restored = ParserState::from_bytes(&state.to_bytes()).unwrap();
+        assert_eq!(restored, state);
+    }
+
+    #[test]
+    fn chunk_reassembly_is_bounded() {
+        let frame = vec![8; 64].prepend_frame().unwrap();
+
+        // a new stream over the stream limit
+        let mut state = ParserState::new().with_chunk_limits(2, 1000);
+        for stream in 0..2 {
+            assert!(
+                state
+                    .parse(encode_chunk(stream, true, &frame[..10]))
+                    .unwrap()
+                    .is_empty()
+            );
+        }
+        assert!(matches!(
+            state.parse(encode_chunk(2, true, &frame[..10])),
+            Err(FrameError::ParsingError(_))
+        ));
+        assert!(state.is_empty());
+
+        // the open streams can still finish, and a stream whose only chunk is the last one is
+        // never buffered
+        let mut state = ParserState::new().with_chunk_limits(2, 1000);
+        for stream in 0..2 {
+            assert!(
+                state
+                    .parse(encode_chunk(stream, true, &frame[..10]))
+                    .unwrap()
+                    .is_empty()
+            );
+        }
+        assert_eq!(
+            state.parse(encode_chunk(2, false, &frame)).unwrap().len(),
+            1
+        );
+        assert_eq!(
+            state
+                .parse(encode_chunk(0, false, &frame[10..]))
+                .unwrap()
+                .len(),
+            1
+        );
+        assert!(
+            state
+                .parse(encode_chunk(3, true, &frame[..10]))
+                .unwrap()
+                .is_empty()
+        );
+
+        // the bytes buffered over all the streams
+        let mut state = ParserState::new().with_chunk_limits(10, 100);
+        let chunks = chunk_frame(&frame, 0, 40).unwrap();
+        assert!(state.parse(chunks[0].as_slice()).unwrap().is_empty());
+        assert!(
+            state
+                .parse(encode_chunk(1, true, &frame[..40]))
+                .unwrap()
+                .is_empty()
+        );
+        assert!(matches!(
+            state.parse(encode_chunk(1, true, &frame[40..])),
+            Err(FrameError::ParsingError(_))
+        ));
+        assert!(state.is_empty());
+
+        // completed frames free their bytes
+        let mut state = ParserState::new().with_chunk_limits(10, 100);
+        for _ in 0..10 {
+            for chunk in chunk_frame(&frame, 0, 40).unwrap() {
+                state.parse(chunk).unwrap();
+            }
+        }
+        assert!(state.is_empty());
+    }
+
+    #[test]
+    fn malformed_chunks_are_refused() {
+        let frame = b"data".to_vec().prepend_frame().unwrap();
+        let chunks = chunk_frame(&frame, 0, 4).unwrap();
+
+        assert!(matches!(
+            chunk_frame(&chunks[0], 1, 4),
+            Err(FrameError::ParsingError(_))
+        ));
+        assert!(matches!(
+            chunk_frame(&frame[..HDR_SIZE], 1, 4),
+            Err(FrameError::ParsingError(_))
+        ));
+
+        // two frames in one chunk stream
+        let mut two = frame.clone();
+        two.extend_from_slice(&frame);
+        let mut state = ParserState::new();
+        assert!(matches!(
+            state.parse(encode_chunk(0, false, &two)),
+            Err(FrameError::ParsingError(_))
+        ));
+        assert!(state.is_empty());
+
+        // a truncated frame
+        let mut truncated = chunks[..chunks.len() - 1].concat();
+        truncated.extend(encode_chunk(0, false, &[]));
+        let mut state = ParserState::new();
+        assert!(matches!(
+            state.parse(truncated),
+            Err(FrameError::ParsingError(_))
+        ));
+
+        // a chunk inside a chunk
+        let mut state = ParserState::new();
+        assert!(matches!(
+            state.parse(encode_chunk(1, false, &chunks[0])),
+            Err(FrameError::ParsingError(_))
+        ));
+
+        // the continued flag on a data frame
+        let mut continued = FrameTags::default().encode(b"x").unwrap();
+        continued[HDR_SIZE + 1] = FLAG_CONTINUED;
+        assert!(matches!(
+            continued.parse_frame_header(None, None),
+            Err(FrameError::ParsingError(_))
+        ));
+    }
+}
+
+#[cfg(test)]
+mod format_cases {
+
+    use proptest::prelude::*;
+
+    use crate::{
+        Cobs, ControlFrame, FrameError, FrameFormat, FrameTags, FrameWriter, LengthDelimited,
+        MagicLength, Netstring, NewlineDelimited, PaddingPolicy, Slip, VarintDelimited,
+    };
+
+    fn encode(format: &dyn FrameFormat, messages: &[&[u8]]) -> Vec<u8> {
+        let mut out = Vec::new();
+        for message in messages {
+            format.encode(message, &mut out).unwrap();
+        }
+        out
+    }
+
+    fn decode(
+        format: &mut dyn FrameFormat,
+        bytes: &[u8],
+    ) -> (Vec<Vec<u8>>, Result<(), FrameError>) {
+        let mut messages = Vec::new();
+        let result = format.decode(bytes, &mut |message| messages.push(message));
+        (messages, result)
+    }
+
+    #[test]
+    fn golden_vectors() {
+        let message = b"\x01\xc0\xdb\x00\n";
+        assert_eq!(
+            encode(&MagicLength::new(), &[message]),
+            message.to_vec().prepend_frame().unwrap()
+        );
+        assert_eq!(
+            encode(&Cobs::new(), &[message]),
+            b"\x04\x01\xc0\xdb\x02\n\x00"
+        );
+        // RFC 1055
+        assert_eq!(
+            encode(&Slip::new(), &[message]),
+            b"\x01\xdb\xdc\xdb\xdd\x00\n\xc0"
+        );
+        assert_eq!(
+            encode(&NewlineDelimited::new(), &[b"{\"a\": 1}", b"[]"]),
+            b"{\"a\": 1}\n[]\n"
+        );
+    }
+
+    #[test]
+    fn length_prefixed_golden_vectors() {
+        // the `tokio_util::codec::length_delimited` documentation example
+        assert_eq!(
+            encode(&LengthDelimited::new(), &[b"hello world"]),
+            b"\x00\x00\x00\x0bhello world"
+        );
+
+        for (len, prefix) in [
+            (0, &[0x00][..]),
+            (1, &[0x01]),
+            (127, &[0x7f]),
+            (128, &[0x80, 0x01]),
+            (300, &[0xac, 0x02]),
+            (16384, &[0x80, 0x80, 0x01]),
+        ] {
+            let message = vec![7; len];
+            let encoded = encode(&VarintDelimited::new(), &[&message]);
+            assert_eq!(&encoded[..prefix.len()], prefix, "varint of {len}");
+            assert_eq!(&encoded[prefix.len()..], message);
+        }
+
+        assert_eq!(
+            encode(&Netstring::new(), &[b"hello world!", b""]),
+            b"12:hello world!,0:,"
+        );
+    }
+
+    #[test]
+    fn length_prefixed_errors() {
+        // non-minimal varints are valid, 64 bits is the most
+        let (messages, result) = decode(&mut VarintDelimited::new(), b"\x80\x00\x81\x00a");
+        assert_eq!(messages, [b"".to_vec(), b"a".to_vec()]);
+        assert!(result.is_ok());
+        let mut too_long = vec![0xff; 9];
+        too_long.push(0x02);
+        assert!(matches!(
+            decode(&mut VarintDelimited::new(), &too_long).1,
+            Err(FrameError::ParsingError(_))
+        ));
+
+        for invalid in [
+            &b"05:hello,"[..],
+            b"5:hello!",
+            b":",
+            b"a:",
+            b"-1:",
+            b"99999999999",
+        ] {
+            let (messages, result) = decode(&mut Netstring::new(), invalid);
+            assert!(messages.is_empty());
+            assert!(
+                matches!(result, Err(FrameError::ParsingError(_))),
+                "{invalid:?}"
+            );
+        }
+
+        let mut small = LengthDelimited::with_max_len(4);
+        assert!(matches!(
+            small.encode(b"large", &mut Vec::new()),
+            Err(FrameError::FrameTooLarge {
+                len: 5,
+                capacity: 4
+            })
+        ));
+        assert!(matches!(
+            decode(&mut small, b"\x00\x00\x00\x05").1,
+            Err(FrameError::FrameTooLarge {
+                len: 5,
+                capacity: 4
+            })
+        ));
+        // the pending bytes were dropped, the next frame starts clean
+        let (messages, result) = decode(&mut small, b"\x00\x00\x00\x02ok");
+        assert_eq!(messages, [b"ok"]);
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn peers_flushing_or_using_crlf() {
+        let (messages, result) = decode(&mut Cobs::new(), b"\x00\x00\x02a\x00\x00\x01\x00");
+        assert_eq!(messages, [b"a".to_vec(), Vec::new()]);
+        assert!(result.is_ok());
+
+        let (messages, result) = decode(&mut Slip::new(), b"\xc0a\xc0\xc0b\xc0");
+        assert_eq!(messages, [b"a", b"b"]);
+        assert!(result.is_ok());
+
+        let (messages, result) = decode(&mut NewlineDelimited::new(), b"a\r\n\r\n\nb\n");
+        assert_eq!(messages, [b"a", b"b"]);
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn magic_length_hands_data_frames_only() {
+        let mut stream = b"plain".to_vec().prepend_frame().unwrap();
+        stream.extend(ControlFrame::Ping(1).encode().unwrap());
+        stream.extend(
+            FrameTags {
+                correlation_id: Some(1),
+                ..FrameTags::default()
+            }
+            .encode(b"tagged")
+            .unwrap(),
+        );
+        let (messages, result) = decode(&mut MagicLength::new(), &stream);
+        assert_eq!(messages, [b"plain".to_vec(), b"tagged".to_vec()]);
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn unencodable_messages() {
+        let mut out = Vec::new();
+        assert!(matches!(
+            Slip::new().encode(b"", &mut out),
+            Err(FrameError::MessageEmpty)
+        ));
+        assert!(matches!(
+            NewlineDelimited::new().encode(b"", &mut out),
+            Err(FrameError::MessageEmpty)
+        ));
+        for line in [&b"two\nlines"[..], b"cr\r"] {
+            assert!(matches!(
+                NewlineDelimited::new().encode(line, &mut out),
+                Err(FrameError::ParsingError(_))
+            ));
+        }
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn bad_frames_are_dropped_and_decoding_goes_on() {
+        // bad escape, then invalid COBS, between two good frames
+        let (messages, result) = decode(&mut Slip::new(), b"one\xc0b\xdbad\xc0two\xc0");
+        assert_eq!(messages, [b"one", b"two"]);
+        assert!(matches!(result, Err(FrameError::ParsingError(_))));
+
+        let (messages, result) = decode(&mut Cobs::new(), b"\x04one\x00\x09bad\x00\x04two\x00");
+        assert_eq!(messages, [b"one", b"two"]);
+        assert!(matches!(result, Err(FrameError::ParsingError(_))));
+
+        // an oversized line, cut across calls
+        let mut lines = NewlineDelimited::with_max_len(4);
+        let (messages, result) = decode(&mut lines, b"one\ntoo l");
+        assert_eq!(messages, [b"one"]);
+        assert!(matches!(
+            result,
+            Err(FrameError::FrameTooLarge {
+                len: 5,
+                capacity: 4
+            })
+        ));
+        let (messages, result) = decode(&mut lines, b"ong\ntwo\n");
+        assert_eq!(messages, [b"two"]);
+        assert!(result.is_ok());
+    }
+
+    fn formats() -> [Box<dyn FrameFormat>; 8] {
+        [
+            Box::new(MagicLength::new()),
+            Box::new(MagicLength::with_padding(PaddingPolicy::Block(32))),
+            Box::new(Cobs::new()),
+            Box::new(Slip::new()),
+            Box::new(NewlineDelimited::new()),
+            Box::new(LengthDelimited::new()),
+            Box::new(VarintDelimited::new()),
+            Box::new(Netstring::new()),
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn bridging_keeps_the_messages(
+            // non-empty messages without line break go through every format
+            messages in prop::collection::vec(
+                prop::collection::vec(
+                    prop_oneof![Just(0u8), Just(0xc0), Just(0xdb), Just(b'\r'), 0x0bu8..],
+                    1..200,
+                )
+                .prop_filter("ends with a \\r", |message| !message.ends_with(b"\r")),
+                0..8,
+            ),
+            cuts in prop::collection::vec(1usize..50, 1..10),
+        ) {
+            let messages: Vec<&[u8]> = messages.iter().map(Vec::as_slice).collect();
+            for mut from in formats() {
+                for to in formats() {
+                    let mut bridged = Vec::new();
+                    let mut rest = &encode(&*from, &messages)[..];
+                    // fed in packets of arbitrary sizes
+                    for cut in cuts.iter().cycle() {
+                        if rest.is_empty() {
+                            break;
+                        }
+                        let (packet, after) = rest.split_at((*cut).min(rest.len()));
+                        rest = after;
+                        let (decoded, result) = decode(&mut *from, packet);
+                        prop_assert!(result.is_ok());
+                        for message in decoded {
+                            to.encode(&message, &mut bridged).unwrap();
+                        }
+                    }
+                    prop_assert_eq!(&bridged, &encode(&*to, &messages));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod magic_search_cases {
+
+    use proptest::prelude::*;
+
+    use crate::{MAGIC_PREFIX, inspect::find_magic_prefix};
+
+    fn windows_search(data: &[u8]) -> Option<usize> {
+        data.windows(MAGIC_PREFIX.len())
+            .position(|window| window == MAGIC_PREFIX)
+    }
+
+    #[test]
+    fn edges() {
+        assert_eq!(find_magic_prefix(&[]), None);
+        assert_eq!(find_magic_prefix(&MAGIC_PREFIX), Some(0));
+        // cut before its last byte
+        assert_eq!(find_magic_prefix(&MAGIC_PREFIX[..7]), None);
+        // second byte first, or repeated
+        assert_eq!(find_magic_prefix(&[0xF1, 0xF1]), None);
+
+        let mut data = vec![0; 100];
+        data.extend_from_slice(&[0x00, 0xF1, 0x01]);
+        data.extend_from_slice(&MAGIC_PREFIX);
+        data.extend_from_slice(&MAGIC_PREFIX);
+        assert_eq!(find_magic_prefix(&data), Some(103));
+
+        let mut data = vec![0xF1; 100];
+        data.extend_from_slice(&MAGIC_PREFIX);
+        assert_eq!(find_magic_prefix(&data), Some(100));
+    }
+
+    proptest! {
+        #[test]
+        fn agrees_with_a_windows_search(
+            // magic bytes are frequent enough to make partial matches
+            mut data in prop::collection::vec(
+                prop_oneof![prop::sample::select(MAGIC_PREFIX.to_vec()), any::<u8>()],
+                0..200,
+            ),
+            insert in prop::option::of(any::<prop::sample::Index>()),
+        ) {
+            if let Some(at) = insert {
+                let at = at.index(data.len() + 1);
+                data.splice(at..at, MAGIC_PREFIX);
+            }
+            prop_assert_eq!(find_magic_prefix(&data), windows_search(&data));
+        }
+    }
+}
+
+#[cfg(test)]
+mod escape_cases {
+
+    use proptest::prelude::*;
+
+    use crate::{
+        ControlFrame, FrameError, FrameParser, FrameTags, FrameWriter, HDR_SIZE, InspectEvent,
+        MAGIC_PREFIX, ParsedStreamData, ParserState, Topic, cobs, escape_frame, inspect,
+    };
+
+    fn cobs_encode(data: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        cobs::encode_to(data, &mut out);
+        out
+    }
+
+    type Parsed = (Option<FrameTags>, Option<ControlFrame>, Vec<u8>);
+
+    fn parsed(mut output: Vec<ParsedStreamData>) -> Parsed {
+        assert_eq!(output.len(), 1);
+        match output.pop().unwrap() {
+            ParsedStreamData::Completed(payload) => (None, None, payload),
+            ParsedStreamData::Tagged(tags, payload) => (Some(tags), None, payload),
+            ParsedStreamData::Control(control) => (None, Some(control), Vec::new()),
+            _ => panic!("expected a whole frame"),
+        }
+    }
+
+    fn contains_magic(data: &[u8]) -> bool {
+        data.windows(MAGIC_PREFIX.len())
+            .any(|window| window == MAGIC_PREFIX)
+    }
+
+    #[test]
+    fn cobs_vectors() {
+        let run: Vec<u8> = (1..=0xfe).collect();
+        let vectors: Vec<(Vec<u8>, Vec<u8>)> = vec![
+            (vec![], vec![0x01]),
+            (vec![0x00], vec![0x01, 0x01]),
+            (vec![0x00, 0x00], vec![0x01, 0x01, 0x01]),
+            (vec![0x00, 0x11, 0x00], vec![0x01, 0x02, 0x11, 0x01]),
+            (
+                vec![0x11, 0x22, 0x00, 0x33],
+                vec![0x03, 0x11, 0x22, 0x02, 0x33],
+            ),
+            (
+                vec![0x11, 0x22, 0x33, 0x44],
+                vec![0x05, 0x11, 0x22, 0x33, 0x44],
+            ),
+            (
+                vec![0x11, 0x00, 0x00, 0x00],
+                vec![0x02, 0x11, 0x01, 0x01, 0x01],
+            ),
+            // a full block ending the data, then the same followed by one more byte
+            (run.clone(), [&[0xff][..], &run].concat()),
+            (
+                [&[0x00][..], &run].concat(),
+                [&[0x01, 0xff][..], &run].concat(),
+            ),
+            (
+                [&run[..], &[0xff]].concat(),
+                [&[0xff][..], &run, &[0x02, 0xff]].concat(),
+            ),
+        ];
+        for (data, encoded) in vectors {
+            assert_eq!(cobs_encode(&data), encoded, "{data:02x?}");
+            assert_eq!(cobs::decode(&encoded).unwrap(), data);
+            assert!(encoded.len() <= cobs::max_encoded_len(data.len()));
+        }
+
+        for invalid in [&[][..], &[0x00], &[0x03, 0x11], &[0x03, 0x11, 0x00]] {
+            assert!(matches!(
+                cobs::decode(invalid),
+                Err(FrameError::ParsingError(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn escaped_frames_parse_as_the_frames_they_wrap() {
+        let mut payload = b"holds ".to_vec();
+        payload.extend_from_slice(&MAGIC_PREFIX);
+        payload.extend_from_slice(&[0; 300]);
+
+        let frames = [
+            payload.clone().prepend_frame().unwrap(),
+            FrameTags {
+                correlation_id: Some(0),
+                topic: Some(Topic::Name("a/b".into())),
+                ..FrameTags::default()
+            }
+            .encode(&payload)
+            .unwrap(),
+            ControlFrame::Ping(0).encode().unwrap(),
+            ControlFrame::close(ControlFrame::CLOSE_NORMAL, "")
+                .encode()
+                .unwrap(),
+            Vec::new().prepend_frame().unwrap(),
+        ];
+        for frame in frames {
+            let escaped = escape_frame(&frame).unwrap();
+            assert!(!contains_magic(&escaped[1..]));
+            assert!(!escaped[crate::HDR_SIZE..].contains(&0));
+            assert_eq!(
+                parsed(escaped.parse_frame_header(None, None).unwrap()),
+                parsed(frame.parse_frame_header(None, None).unwrap()),
+            );
+        }
+    }
+
+    #[test]
+    fn escape_frame_errors() {
+        let frame = b"payload".to_vec().prepend_frame().unwrap();
+        let escaped = escape_frame(&frame).unwrap();
+        for invalid in [
+            &frame[..frame.len() - 1],
+            &frame[1..],
+            &[frame.clone(), frame.clone()].concat(),
+            &escaped,
+        ] {
+            assert!(matches!(
+                escape_frame(invalid),
+                Err(FrameError::ParsingError(_))
+            ));
+        }
+
+        // nested by hand, the parser refuses it too
+        let mut body = vec![5];
+        cobs::encode_to(&escaped[crate::HDR_SIZE..], &mut body);
+        let mut nested = MAGIC_PREFIX.to_vec();
+        nested.extend_from_slice(&(body.len() as u32 | crate::EXTENDED_FLAG).to_be_bytes());
+        nested.extend(body);
+        assert!(matches!(
+            nested.parse_frame_header(None, None),
+            Err(FrameError::ParsingError(e)) if e.contains("inside an escaped frame")
+        ));
+    }
+
+    #[test]
+    fn parser_state_resyncs_on_damaged_escaped_streams() {
+        let payloads: Vec<Vec<u8>> = (1..=10u8)
+            .map(|i| [&[i; 10][..], &MAGIC_PREFIX, &[i; 10]].concat())
+            .collect();
+        let frames: Vec<Vec<u8>> = payloads
+            .iter()
+            .map(|p| escape_frame(&p.clone().prepend_frame().unwrap()).unwrap())
+            .collect();
+        let frame_len = frames[0].len();
+        let stream = frames.concat();
+
+        let parse_all = |state: &mut ParserState, damaged: &[u8]| {
+            let mut payloads = vec![];
+            for packet in damaged.chunks(16) {
+                for parsed in state.parse(packet).unwrap() {
+                    let ParsedStreamData::Completed(payload) = parsed else {
+                        panic!("data frame expected");
+                    };
+                    payloads.push(payload);
+                }
+            }
+            payloads
+        };
+
+        // 15 bytes lost anywhere in the first two frames but in a length field (the frame would
+        // announce a body longer than what is left) : the frames before the damage come out,
+        // then every frame after it
+        for cut in (0..frame_len * 2).filter(|cut| !(8..HDR_SIZE).contains(&(cut % frame_len))) {
+            let mut damaged = stream[..cut].to_vec();
+            damaged.extend_from_slice(&stream[cut + 15..]);
+
+            let mut state = ParserState::new().with_resync();
+            let parsed = parse_all(&mut state, &damaged);
+            let before = &payloads[..cut / frame_len];
+            let after = &payloads[(cut + 15).div_ceil(frame_len)..];
+            assert!(parsed.starts_with(before), "cut {cut}");
+            assert!(parsed.ends_with(after), "cut {cut}");
+            assert!(parsed.len() <= before.len() + after.len() + 1, "cut {cut}");
+            assert!(state.is_empty());
+        }
+
+        // without resync, the damage fails every packet after it
+        let mut damaged = stream[..20].to_vec();
+        damaged.extend_from_slice(&stream[35..]);
+        let mut state = ParserState::new();
+        let errors = damaged
+            .chunks(16)
+            .filter(|packet| state.parse(*packet).is_err())
+            .count();
+        assert!(errors > 10);
+    }
+
+    #[test]
+    fn resync_on_escaped_frames_is_always_right() {
+        // payloads made of magic prefixes and zeroes, where plain frames resync wrongly
+        let payload = [&MAGIC_PREFIX[..], &[0; 5], &MAGIC_PREFIX].concat();
+        let frames: Vec<Vec<u8>> = (0..20)
+            .map(|_| escape_frame(&payload.clone().prepend_frame().unwrap()).unwrap())
+            .collect();
+        let starts: Vec<usize> = frames
+            .iter()
+            .scan(0, |offset, frame| {
+                let start = *offset;
+                *offset += frame.len();
+                Some(start)
+            })
+            .collect();
+        let stream = frames.concat();
+
+        // lose a run of bytes anywhere, every resync lands on a frame
+        for cut in 0..frames[0].len() * 2 {
+            for lost in 1..frames[0].len() {
+                let mut damaged = stream[..cut].to_vec();
+                damaged.extend_from_slice(&stream[cut + lost..]);
+                for event in inspect(&damaged) {
+                    if let InspectEvent::Resync { offset, .. } = event {
+                        assert!(starts.contains(&(offset + lost)), "cut {cut}, lost {lost}");
+                    }
+                }
+            }
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn escaping_round_trips(
+            payload in prop::collection::vec(
+                prop_oneof![prop::sample::select(MAGIC_PREFIX.to_vec()), any::<u8>()],
+                0..600,
+            ),
+        ) {
+            let escaped = escape_frame(&payload.clone().prepend_frame().unwrap()).unwrap();
+            prop_assert!(!contains_magic(&escaped[1..]));
+            prop_assert!(cobs::decode(&cobs_encode(&payload)).unwrap() == payload);
+            match escaped.parse_frame_header(None, None).unwrap().pop() {
+                Some(ParsedStreamData::Completed(parsed)) => prop_assert_eq!(parsed, payload),
+                _ => prop_assert!(false, "expected a data frame"),
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "pcap"))]
+mod pcap_cases {
+
+    use std::net::{IpAddr, SocketAddr};
+
+    use crate::{
+        Capture, ControlFrame, FlowKey, FrameError, FrameWriter, Gap, InspectEvent,
+        ParsedStreamData, Transport, inspect,
+    };
+
+    const ETHERNET: u32 = 1;
+    const LINUX_SLL: u32 = 113;
+
+    // fixture builder : the layers are written by hand, checksums are left at 0
+
+    fn ip_packet(src: IpAddr, dst: IpAddr, protocol: u8, payload: &[u8]) -> Vec<u8> {
+        match (src, dst) {
+            (IpAddr::V4(src), IpAddr::V4(dst)) => {
+                let mut packet = vec![0x45, 0];
+                packet.extend_from_slice(&(20 + payload.len() as u16).to_be_bytes());
+                // id, don't fragment, ttl
+                packet.extend_from_slice(&[0, 1, 0x40, 0, 64, protocol, 0, 0]);
+                packet.extend_from_slice(&src.octets());
+                packet.extend_from_slice(&dst.octets());
+                packet.extend_from_slice(payload);
+                packet
+            }
+            (IpAddr::V6(src), IpAddr::V6(dst)) => {
+                let mut packet = vec![0x60, 0, 0, 0];
+                packet.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+                packet.extend_from_slice(&[protocol, 64]);
+                packet.extend_from_slice(&src.octets());
+                packet.extend_from_slice(&dst.octets());
+                packet.extend_from_slice(payload);
+                packet
+            }
+            _ => unreachable!("mixed IP versions"),
+        }
+    }
+
+    fn tcp_packet(
+        src: SocketAddr,
+        dst: SocketAddr,
+        seq: u32,
+        syn: bool,
+        payload: &[u8],
+    ) -> Vec<u8> {
+        let mut segment = src.port().to_be_bytes().to_vec();
+        segment.extend_from_slice(&dst.port().to_be_bytes());
+        segment.extend_from_slice(&seq.to_be_bytes());
+        // ack, data offset 5, SYN or ACK|PSH, window, checksum, urgent
+        segment.extend_from_slice(&[0, 0, 0, 0, 0x50, if syn { 0x02 } else { 0x18 }, 0xff, 0xff]);
+        segment.extend_from_slice(&[0, 0, 0, 0]);
+        segment.extend_from_slice(payload);
+        ip_packet(src.ip(), dst.ip(), 6, &segment)
+    }
+
+    fn udp_packet(src: SocketAddr, dst: SocketAddr, payload: &[u8]) -> Vec<u8> {
+        let mut datagram = src.port().to_be_bytes().to_vec();
+        datagram.extend_from_slice(&dst.port().to_be_bytes());
+        datagram.extend_from_slice(&(8 + payload.len() as u16).to_be_bytes());
+        datagram.extend_from_slice(&[0, 0]);
+        datagram.extend_from_slice(payload);
+        ip_packet(src.ip(), dst.ip(), 17, &datagram)
+    }
+
+    fn ethernet(ip: &[u8], vlan: bool) -> Vec<u8> {
+        let mut frame = vec![2, 0, 0, 0, 0, 2, 2, 0, 0, 0, 0, 1];
+        if vlan {
+            frame.extend_from_slice(&[0x81, 0x00, 0x00, 0x2a]);
+        }
+        let ether_type: u16 = if ip[0] >> 4 == 4 { 0x0800 } else { 0x86dd };
+        frame.extend_from_slice(&ether_type.to_be_bytes());
+        frame.extend_from_slice(ip);
+        frame
+    }
+
+    fn linux_sll(ip: &[u8]) -> Vec<u8> {
+        let mut frame = vec![0, 0, 0, 1, 0, 6, 2, 0, 0, 0, 0, 1, 0, 0];
+        let ether_type: u16 = if ip[0] >> 4 == 4 { 0x0800 } else { 0x86dd };
+        frame.extend_from_slice(&ether_type.to_be_bytes());
+        frame.extend_from_slice(ip);
+        frame
+    }
+
+    fn pcap_file(link_type: u32, packets: &[Vec<u8>], big_endian: bool) -> Vec<u8> {
+        let u16_bytes = |v: u16| {
+            if big_endian {
+                v.to_be_bytes()
+            } else {
+                v.to_le_bytes()
+            }
+        };
+        let u32_bytes = |v: u32| {
+            if big_endian {
+                v.to_be_bytes()
+            } else {
+                v.to_le_bytes()
+            }
+        };
+
+        let mut file = u32_bytes(0xa1b2_c3d4).to_vec();
+        file.extend_from_slice(&u16_bytes(2));
+        file.extend_from_slice(&u16_bytes(4));
+        file.extend_from_slice(&[0; 8]);
+        file.extend_from_slice(&u32_bytes(65535));
+        file.extend_from_slice(&u32_bytes(link_type));
+        for (i, packet) in packets.iter().enumerate() {
+            file.extend_from_slice(&u32_bytes(1_700_000_000 + i as u32));
+            file.extend_from_slice(&u32_bytes(0));
+            file.extend_from_slice(&u32_bytes(packet.len() as u32));
+            file.extend_from_slice(&u32_bytes(packet.len() as u32));
+            file.extend_from_slice(packet);
+        }
+        file
+    }
+
+    fn pcapng_block(block_type: u32, body: &[u8]) -> Vec<u8> {
+        let padded = body.len().div_ceil(4) * 4;
+        let total = (12 + padded) as u32;
+        let mut block = block_type.to_le_bytes().to_vec();
+        block.extend_from_slice(&total.to_le_bytes());
+        block.extend_from_slice(body);
+        block.resize(8 + padded, 0);
+        block.extend_from_slice(&total.to_le_bytes());
+        block
+    }
+
+    fn pcapng_file(link_type: u16, packets: &[Vec<u8>]) -> Vec<u8> {
+        // section header : byte order magic, version 1.0, unknown section len
+        let mut shb = 0x1a2b_3c4d_u32.to_le_bytes().to_vec();
+        shb.extend_from_slice(&[1, 0, 0, 0]);
+        shb.extend_from_slice(&[0xff; 8]);
+        let mut file = pcapng_block(0x0a0d_0d0a, &shb);
+
+        let mut idb = link_type.to_le_bytes().to_vec();
+        idb.extend_from_slice(&[0, 0]);
+        idb.extend_from_slice(&65535_u32.to_le_bytes());
+        file.extend(pcapng_block(1, &idb));
+        // a block the reader doesn't know
+        file.extend(pcapng_block(0x0bad, b"custom"));
+
+        for packet in packets {
+            let mut epb = 0_u32.to_le_bytes().to_vec();
+            epb.extend_from_slice(&[0; 8]);
+            epb.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+            epb.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+            epb.extend_from_slice(packet);
+            file.extend(pcapng_block(6, &epb));
+        }
+        file
+    }
+
+    fn messages() -> Vec<Vec<u8>> {
+        (0..20)
+            .map(|i| format!("message {i} ").repeat(i + 1).into_bytes())
+            .collect()
+    }
+
+    fn framed_messages() -> Vec<u8> {
+        messages()
+            .into_iter()
+            .flat_map(|message| message.prepend_frame().unwrap())
+            .collect()
+    }
+
+    fn client() -> SocketAddr {
+        "10.0.0.1:40000".parse().unwrap()
+    }
+    fn server() -> SocketAddr {
+        "10.0.0.2:4433".parse().unwrap()
+    }
+
+    /// TCP over IPv4 over Ethernet : SYN, segments out of order, one retransmission, ACKs from
+    /// the server and an ARP packet.
+    fn tcp_capture() -> Vec<u8> {
+        let stream = framed_messages();
+        let isn = u32::MAX - 100; // the sequence numbers wrap
+        let mut packets = vec![ethernet(
+            &tcp_packet(client(), server(), isn, true, &[]),
+            false,
+        )];
+
+        let segments: Vec<(usize, &[u8])> = stream
+            .chunks(97)
+            .enumerate()
+            .map(|(i, chunk)| (i * 97, chunk))
+            .collect();
+        let mut order: Vec<usize> = (0..segments.len()).collect();
+        order.swap(2, 3);
+        order.insert(6, 4);
+        for i in order {
+            let (offset, chunk) = segments[i];
+            let seq = isn.wrapping_add(1).wrapping_add(offset as u32);
+            packets.push(ethernet(
+                &tcp_packet(client(), server(), seq, false, chunk),
+                i % 3 == 0,
+            ));
+            packets.push(ethernet(
+                &tcp_packet(server(), client(), 7, false, &[]),
+                false,
+            ));
+        }
+        // ARP request
+        let mut arp = vec![0xff; 6];
+        arp.extend_from_slice(&[2, 0, 0, 0, 0, 1, 0x08, 0x06]);
+        arp.extend_from_slice(&[0; 28]);
+        packets.push(arp);
+
+        pcap_file(ETHERNET, &packets, false)
+    }
+
+    /// UDP over IPv6 in a pcapng file, frames cut across datagrams, a ping on the way back.
+    fn udp_capture() -> Vec<u8> {
+        let src: SocketAddr = "[2001:db8::1]:5000".parse().unwrap();
+        let dst: SocketAddr = "[2001:db8::2]:6000".parse().unwrap();
+        let mut packets: Vec<Vec<u8>> = framed_messages()
+            .chunks(300)
+            .map(|datagram| ethernet(&udp_packet(src, dst, datagram), false))
+            .collect();
+        packets.insert(
+            3,
+            ethernet(
+                &udp_packet(dst, src, &ControlFrame::Ping(7).encode().unwrap()),
+                false,
+            ),
+        );
+        pcapng_file(ETHERNET as u16, &packets)
+    }
+
+    fn decoded_messages(parsed: Vec<ParsedStreamData>) -> Vec<Vec<u8>> {
+        parsed
+            .into_iter()
+            .map(|parsed| match parsed {
+                ParsedStreamData::Completed(message) => message,
+                _ => panic!("data frame expected"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn tcp_flow_is_reassembled() {
+        let capture = Capture::parse(&tcp_capture()).unwrap();
+        assert_eq!(capture.skipped(), 1);
+        assert_eq!(capture.flows().len(), 2);
+
+        let key = FlowKey {
+            transport: Transport::Tcp,
+            src: client(),
+            dst: server(),
+        };
+        let flow = capture.flow(&key).unwrap();
+        let stream = flow.stream();
+        assert!(stream.gaps.is_empty());
+        assert_eq!(stream.data, framed_messages());
+        assert!(flow.captured_bytes() > stream.data.len());
+
+        assert_eq!(decoded_messages(flow.decode().unwrap()), messages());
+
+        // the server only sent ACKs
+        let back = capture
+            .flows()
+            .iter()
+            .find(|flow| flow.key.src == server())
+            .unwrap();
+        assert!(back.packets().is_empty());
+        assert!(back.decode().unwrap().is_empty());
+    }
+
+    #[test]
+    fn udp_flow_feeds_datagrams() {
+        let capture = Capture::parse(&udp_capture()).unwrap();
+        assert_eq!(capture.skipped(), 0);
+        assert_eq!(capture.flows().len(), 2);
+
+        let flow = &capture.flows()[0];
+        assert_eq!(flow.key.transport, Transport::Udp);
+        assert_eq!(flow.key.dst.port(), 6000);
+        assert_eq!(decoded_messages(flow.decode().unwrap()), messages());
+
+        let pings = capture.flows()[1].decode().unwrap();
+        assert!(matches!(
+            pings[..],
+            [ParsedStreamData::Control(ControlFrame::Ping(7))]
+        ));
+    }
+
+    #[test]
+    fn link_types_and_byte_orders() {
+        let frame = b"over every link".to_vec().prepend_frame().unwrap();
+        let src: SocketAddr = "192.168.1.1:1".parse().unwrap();
+        let dst: SocketAddr = "192.168.1.2:2".parse().unwrap();
+        let ip = udp_packet(src, dst, &frame);
+
+        let mut loopback = 2_u32.to_le_bytes().to_vec();
+        loopback.extend_from_slice(&ip);
+        let files = [
+            pcap_file(ETHERNET, &[ethernet(&ip, true)], true),
+            pcap_file(LINUX_SLL, &[linux_sll(&ip)], false),
+            pcap_file(0, &[loopback], true),
+            pcap_file(101, std::slice::from_ref(&ip), false),
+            pcapng_file(228, &[ip]),
+        ];
+        for file in files {
+            let capture = Capture::parse(&file).unwrap();
+            assert_eq!(capture.flows().len(), 1);
+            assert_eq!(capture.flows()[0].key.src, src);
+            assert_eq!(
+                decoded_messages(capture.flows()[0].decode().unwrap()),
+                [b"over every link"]
+            );
+        }
+    }
+
+    #[test]
+    fn missing_segment_leaves_a_gap() {
+        let stream = framed_messages();
+        let mut packets = vec![];
+        for (i, chunk) in stream.chunks(50).enumerate() {
+            if i != 4 {
+                let seq = 1000 + (i * 50) as u32;
+                packets.push(tcp_packet(client(), server(), seq, false, chunk));
+            }
+        }
+        let capture = Capture::parse(&pcap_file(101, &packets, false)).unwrap();
+        let flow = &capture.flows()[0];
+
+        let flow_stream = flow.stream();
+        assert_eq!(
+            flow_stream.gaps,
+            [Gap {
+                offset: 200,
+                missing: 50
+            }]
+        );
+        assert!(matches!(flow.decode(), Err(FrameError::ParsingError(_))));
+
+        // the inspector resyncs after the hole
+        let events = inspect(&flow_stream.data);
+        assert!(
+            events
+                .iter()
+                .any(|event| matches!(event, InspectEvent::Resync { .. }))
+        );
+    }
+
+    #[test]
+    fn malformed_files_are_refused() {
+        let file = tcp_capture();
+        for bad in [
+            &b"nope"[..],
+            &file[..10],
+            &file[..file.len() - 3],
+            &[0u8; 24],
+        ] {
+            assert!(matches!(
+                Capture::parse(bad),
+                Err(FrameError::ParsingError(_))
+            ));
+        }
+        let file = udp_capture();
+        assert!(matches!(
+            Capture::parse(&file[..file.len() - 3]),
+            Err(FrameError::ParsingError(_))
+        ));
+    }
+
+    /// The CLI tests read these files. `STREAM_FRAMER_BLESS=1 cargo test --all-features`
+    /// regenerates them after a change of the builder.
+    #[test]
+    fn fixtures_are_up_to_date() {
+        let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+        for (name, content) in [
+            ("tcp_frames.pcap", tcp_capture()),
+            ("udp_frames.pcapng", udp_capture()),
+        ] {
+            let path = dir.join(name);
+            if std::env::var_os("STREAM_FRAMER_BLESS").is_some() {
+                std::fs::create_dir_all(&dir).unwrap();
+                std::fs::write(&path, &content).unwrap();
+            }
+            assert!(
+                std::fs::read(&path).unwrap() == content,
+                "{name} is stale, regenerate it with STREAM_FRAMER_BLESS=1"
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod dissector_cases {
+
+    use crate::{
+        ControlFrame, FrameTags, HDR_SIZE, Topic,
+        extended::EXT_HDR_SIZE,
+        spec::{FLAGS, FieldType, KINDS, known_flags},
+        wireshark_dissector,
+    };
+
+    #[test]
+    fn kinds_match_the_control_frames() {
+        for frame in [
+            ControlFrame::Ping(1),
+            ControlFrame::Pong(2),
+            ControlFrame::close(ControlFrame::CLOSE_GOING_AWAY, "bye"),
+            ControlFrame::Credit {
+                channel: 1,
+                frames: 2,
+                bytes: 3,
+            },
+        ] {
+            let encoded = frame.encode().unwrap();
+            let kind = KINDS
+                .iter()
+                .find(|kind| kind.kind == encoded[HDR_SIZE])
+                .unwrap();
+            // fixed size fields, then at most one field up to the end of the frame
+            let fixed: usize = kind.payload.iter().filter_map(|f| f.ty.size()).sum();
+            let payload_len = encoded.len() - HDR_SIZE - EXT_HDR_SIZE;
+            if kind.payload.iter().all(|f| f.ty.size().is_some()) {
+                assert_eq!(fixed, payload_len, "{} payload", kind.name);
+            } else {
+                assert!(fixed <= payload_len, "{} payload", kind.name);
+            }
+        }
+    }
+
+    #[test]
+    fn flags_match_the_tags() {
+        assert_eq!(known_flags(), 0x7f);
+        assert!(FLAGS.windows(2).all(|pair| pair[0].bit < pair[1].bit));
+
+        let tags = [
+            FrameTags {
+                correlation_id: Some(9),
+                reply: true,
+                topic: Some(Topic::Name("a/b".into())),
+            },
+            FrameTags {
+                correlation_id: Some(9),
+                topic: Some(Topic::hashed("a/b")),
+                ..FrameTags::default()
+            },
+        ];
+        for tags in tags {
+            let encoded = tags.encode(b"payload").unwrap();
+            let flags = encoded[HDR_SIZE + 1];
+            let fields_len: usize = FLAGS
+                .iter()
+                .filter(|flag| flags & flag.bit != 0)
+                .filter_map(|flag| flag.field.as_ref())
+                .map(|field| match field.ty {
+                    FieldType::ShortString => 1 + encoded[HDR_SIZE + EXT_HDR_SIZE + 8] as usize,
+                    _ => field.ty.size().unwrap(),
+                })
+                .sum();
+            assert_eq!(
+                HDR_SIZE + EXT_HDR_SIZE + fields_len + b"payload".len(),
+                encoded.len()
+            );
+        }
+    }
+
+    #[test]
+    fn dissector_describes_every_field() {
+        let lua = wireshark_dissector();
+        assert!(lua.contains(r#"local MAGIC = ByteArray.new("00f101e402ff03dd")"#));
+        for flag in FLAGS {
+            assert!(lua.contains(&format!("f.flag_{} = ", flag.name)));
+        }
+        for kind in KINDS {
+            assert!(lua.contains(&format!("    [{}] = \"{}\",", kind.kind, kind.name)));
+            for field in kind.payload {
+                assert!(lua.contains(&format!("\"stream_framer.{}.{}\"", kind.name, field.name)));
+            }
+        }
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn dissector_is_up_to_date() {
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures/stream_framer.lua");
+        let lua = wireshark_dissector();
+        if std::env::var_os("STREAM_FRAMER_BLESS").is_some() {
+            std::fs::write(&path, &lua).unwrap();
+        }
+        assert!(
+            std::fs::read_to_string(&path).unwrap() == lua,
+            "stream_framer.lua is stale, regenerate it with STREAM_FRAMER_BLESS=1"
+        );
+    }
+}
+
+#[cfg(test)]
+mod proptest_cases {
+
+    use proptest::prelude::*;
+
+    use crate::{
+        ControlFrame, FrameError, FrameParser, FrameTags, FrameWriter, HDR_SIZE, ParsedStreamData,
+        ParserState, RingFrameDecoder, SliceFrameDecoder, Topic, inspect,
+    };
+
+    /// What was sent, and what the decoder output should be turned back into.
+    #[derive(Debug, Clone, PartialEq)]
+    enum Sent {
+        Data(Vec<u8>),
+        Tagged(FrameTags, Vec<u8>),
+        Control(ControlFrame),
+    }
+
+    impl Sent {
+        fn encode(&self) -> Vec<u8> {
+            match self {
+                Sent::Data(payload) => payload.clone().prepend_frame().unwrap(),
+                Sent::Tagged(tags, payload) => tags.encode(payload).unwrap(),
+                Sent::Control(control) => control.encode().unwrap(),
+            }
+        }
+    }
+
+    fn received(parsed: ParsedStreamData) -> Sent {
+        match parsed {
+            ParsedStreamData::Completed(payload) => Sent::Data(payload),
+            ParsedStreamData::Tagged(tags, payload) => Sent::Tagged(tags, payload),
+            ParsedStreamData::Control(control) => Sent::Control(control),
+            ParsedStreamData::Incompleted(..)
+            | ParsedStreamData::TruncatedHeader(_)
+            | ParsedStreamData::Chunk { .. } => {
+                panic!("partial frame handed out")
+            }
+        }
+    }
+
+    fn payload() -> impl Strategy<Value = Vec<u8>> {
+        prop::collection::vec(any::<u8>(), 0..300)
+    }
+
+    fn tags() -> impl Strategy<Value = FrameTags> {
+        (
+            prop::option::of(any::<u64>()),
+            any::<bool>(),
+            prop::option::of(prop_oneof![
+                "[a-z]{1,8}(/[a-z]{1,8}){0,3}".prop_map(Topic::Name),
+                any::<u32>().prop_map(Topic::Id),
+            ]),
+        )
+            .prop_map(|(correlation_id, reply, topic)| FrameTags {
+                correlation_id,
+                reply: reply && correlation_id.is_some(),
+                topic,
+            })
+            .prop_filter("empty tags are sent as plain frames", |tags| {
+                !tags.is_empty()
+            })
+    }
+
+    fn sent() -> impl Strategy<Value = Sent> {
+        prop_oneof![
+            4 => payload().prop_map(Sent::Data),
+            2 => (tags(), payload()).prop_map(|(tags, payload)| Sent::Tagged(tags, payload)),
+            1 => prop_oneof![
+                any::<u64>().prop_map(ControlFrame::Ping),
+                any::<u64>().prop_map(ControlFrame::Pong),
+                (any::<u32>(), any::<u32>(), any::<u32>()).prop_map(|(channel, frames, bytes)| {
+                    ControlFrame::Credit {
+                        channel,
+                        frames,
+                        bytes,
+                    }
+                }),
+            ]
+            .prop_map(Sent::Control),
+        ]
+    }
+
+    /// Cut `stream` in packets, the sizes being cycled through.
+    fn split(stream: &[u8], sizes: &[usize]) -> Vec<Vec<u8>> {
+        let mut packets = vec![];
+        let mut rest = stream;
+        for size in sizes.iter().cycle() {
+            if rest.is_empty() {
+                break;
+            }
+            let (packet, tail) = rest.split_at((*size).min(rest.len()));
+            packets.push(packet.to_vec());
+            rest = tail;
+        }
+        packets
+    }
+
+    fn packet_sizes() -> impl Strategy<Value = Vec<usize>> {
+        prop::collection::vec(1usize..(3 * HDR_SIZE), 1..16)
+    }
+
+    proptest! {
+        #[test]
+        fn caller_held_state_round_trip(
+            messages in prop::collection::vec(sent(), 1..20),
+            sizes in packet_sizes(),
+        ) {
+            let stream = messages.iter().map(Sent::encode).collect::<Vec<_>>().concat();
+
+            let mut previous_incompleted_data = None;
+            let mut truncated_header = None;
+            let mut output = vec![];
+            for packet in split(&stream, &sizes) {
+                for parsed in packet
+                    .parse_frame_header(previous_incompleted_data.take(), truncated_header.take())
+                    .unwrap()
+                {
+                    match parsed {
+                        ParsedStreamData::Incompleted(size, data) => {
+                            previous_incompleted_data = Some((size, data));
+                        }
+                        ParsedStreamData::TruncatedHeader(hdr) => truncated_header = Some(hdr),
+                        parsed => output.push(received(parsed)),
+                    }
+                }
+            }
+            prop_assert_eq!(output, messages);
+            prop_assert!(previous_incompleted_data.is_none() && truncated_header.is_none());
+        }
+
+        #[test]
+        fn parser_state_round_trip(
+            messages in prop::collection::vec(sent(), 1..20),
+            sizes in packet_sizes(),
+        ) {
+            let stream = messages.iter().map(Sent::encode).collect::<Vec<_>>().concat();
+
+            let mut state = ParserState::new();
+            let mut output = vec![];
+            for packet in split(&stream, &sizes) {
+                output.extend(state.parse(packet).unwrap().into_iter().map(received));
+            }
+            prop_assert_eq!(output, messages);
+            prop_assert!(state.is_empty());
+        }
+
+        #[test]
+        fn slice_and_ring_decoders_round_trip(
+            messages in prop::collection::vec(payload(), 1..20),
+            sizes in packet_sizes(),
+        ) {
+            let stream = messages
+                .iter()
+                .map(|m| m.clone().prepend_frame().unwrap())
+                .collect::<Vec<_>>()
+                .concat();
+
+            let mut buf = [0u8; 300];
+            let mut slice_decoder = SliceFrameDecoder::new(&mut buf);
+            let mut ring_decoder = RingFrameDecoder::with_capacity(300 + HDR_SIZE);
+            let mut from_slice = vec![];
+            let mut from_ring = vec![];
+            for packet in split(&stream, &sizes) {
+                slice_decoder
+                    .decode(&packet, |frame| from_slice.push(frame.to_vec()))
+                    .unwrap();
+
+                let mut packet = &packet[..];
+                while !packet.is_empty() {
+                    packet = &packet[ring_decoder.push(packet)..];
+                    while let Some(frame) = ring_decoder.next_frame().unwrap() {
+                        from_ring.push(frame.to_vec());
+                    }
+                }
+            }
+            prop_assert_eq!(&from_slice, &messages);
+            prop_assert_eq!(&from_ring, &messages);
+        }
+
+        #[test]
+        fn garbage_never_panics(packets in prop::collection::vec(
+            prop::collection::vec(any::<u8>(), 0..64),
+            1..16,
+        )) {
+            feed_everywhere(&packets);
+        }
+
+        #[test]
+        fn corrupted_stream_never_panics(
+            messages in prop::collection::vec(sent(), 1..10),
+            sizes in packet_sizes(),
+            corruptions in prop::collection::vec((any::<prop::sample::Index>(), any::<u8>()), 1..8),
+        ) {
+            let mut stream = messages.iter().map(Sent::encode).collect::<Vec<_>>().concat();
+            for (at, byte) in corruptions {
+                let at = at.index(stream.len());
+                stream[at] = byte;
+            }
+            feed_everywhere(&split(&stream, &sizes));
+        }
+    }
+
+    #[test]
+    fn garbage_start_is_an_error() {
+        // used to loop forever looking for a header that never comes
+        assert!(matches!(
+            b"not a frame at all"
+                .to_vec()
+                .parse_frame_header(None, None),
+            Err(FrameError::ParsingError(_))
+        ));
+    }
+
+    #[test]
+    fn overfull_incomplete_reception_is_an_error() {
+        // used to underflow on `msg_len - already_received.len()`
+        assert!(matches!(
+            b"rest"
+                .to_vec()
+                .parse_frame_header(Some((2.into(), vec![0; 5])), None),
+            Err(FrameError::InvalidState(_))
+        ));
+    }
+
+    // Errors are expected, only panics (and hangs) fail the test.
+    fn feed_everywhere(packets: &[Vec<u8>]) {
+        for resync in [false, true] {
+            let mut state = ParserState::new();
+            let mut buf = [0u8; 64];
+            let mut slice_decoder = SliceFrameDecoder::new(&mut buf);
+            let mut ring_decoder = RingFrameDecoder::with_capacity(64 + HDR_SIZE);
+            if resync {
+                state = state.with_resync();
+                slice_decoder = slice_decoder.with_resync();
+                ring_decoder = ring_decoder.with_resync();
+            }
+
+            for packet in packets {
+                let _ = state.parse(packet.clone());
+                let _ = slice_decoder.decode(packet, |_| {});
+
+                let mut packet = &packet[..];
+                while !packet.is_empty() {
+                    packet = &packet[ring_decoder.push(packet)..];
+                    while let Ok(Some(_)) = ring_decoder.next_frame() {}
+                }
+            }
+        }
+        let _ = inspect(&packets.concat());
+    }
+}
+
+#[cfg(test)]
+mod adversarial_cases {
+
+    use proptest::prelude::*;
+
+    use crate::{
+        AnnouncedLen, EXTENDED_FLAG, FrameError, FrameParser, FrameWriter, HDR_SIZE, MAGIC_PREFIX,
+        MAX_BODY_LEN, PARSER_STATE_VERSION, ParsedStreamData, ParserState, RingFrameDecoder,
+        SliceFrameDecoder, inspect,
+    };
+
+    fn header(len_field: u32) -> Vec<u8> {
+        let mut hdr = MAGIC_PREFIX.to_vec();
+        hdr.extend_from_slice(&len_field.to_be_bytes());
+        hdr
+    }
+
+    fn is_parsing_error<T>(result: Result<T, FrameError>) -> bool {
+        matches!(result, Err(FrameError::ParsingError(_)))
+    }
+
+    #[test]
+    fn truncated_header_longer_than_the_packet_needs() {
+        // a whole header passed as "truncated", then a packet too short for the body offset
+        let result = b"abc"
+            .to_vec()
+            .parse_frame_header(None, Some(header(3).repeat(2)));
+        assert!(matches!(result, Err(FrameError::InvalidState(_))));
+    }
+
+    #[test]
+    fn packet_too_short_to_complete_the_header() {
+        let result = b"xy"
+            .to_vec()
+            .parse_frame_header(None, Some(MAGIC_PREFIX[..1].to_vec()));
+        assert!(matches!(result, Ok(parsed)
+            if matches!(&parsed[..], [ParsedStreamData::TruncatedHeader(hdr)] if hdr.len() == 3)));
+
+        // both a pending body and a pending header
+        let result = b"xy"
+            .to_vec()
+            .parse_frame_header(Some((10.into(), vec![])), Some(MAGIC_PREFIX[..4].to_vec()));
+        assert!(matches!(result, Err(FrameError::InvalidState(_))));
+    }
+
+    // (last_incomplete_reception, is_last_header_truncated)
+    type CallerState = (Option<(AnnouncedLen, Vec<u8>)>, Option<Vec<u8>>);
+
+    #[test]
+    fn inconsistent_caller_state_is_rejected() {
+        let invalid_states: [CallerState; 5] = [
+            (Some((4.into(), vec![0; 5])), None),
+            (
+                Some((
+                    AnnouncedLen::from_field(EXTENDED_FLAG as usize | 4),
+                    vec![0; 5],
+                )),
+                None,
+            ),
+            (Some(((MAX_BODY_LEN + 1).into(), vec![])), None),
+            (Some(((u32::MAX as usize + 1).into(), vec![])), None),
+            (None, Some(header(0))),
+        ];
+        for (incomplete, truncated) in invalid_states {
+            // even for an empty packet, which hands the state back
+            for packet in [vec![], b"0123456789abcdef".to_vec()] {
+                assert!(matches!(
+                    packet.parse_frame_header(incomplete.clone(), truncated.clone()),
+                    Err(FrameError::InvalidState(_))
+                ));
+            }
+        }
+
+        // the states the parser hands out are all accepted
+        let frame = b"payload".to_vec().prepend_frame().unwrap();
+        for cut in 1..frame.len() {
+            let (first, second) = frame.split_at(cut);
+            let (mut incomplete, mut truncated) = (None, None);
+            for parsed in first.to_vec().parse_frame_header(None, None).unwrap() {
+                match parsed {
+                    ParsedStreamData::Incompleted(size, data) => incomplete = Some((size, data)),
+                    ParsedStreamData::TruncatedHeader(hdr) => truncated = Some(hdr),
+                    _ => panic!("the frame can't be complete"),
+                }
+            }
+            let parsed = second
+                .to_vec()
+                .parse_frame_header(incomplete, truncated)
+                .unwrap();
+            assert!(
+                matches!(&parsed[..], [ParsedStreamData::Completed(data)] if data == b"payload")
+            );
+        }
+    }
+
+    #[test]
+    fn inconsistent_checkpoint_is_an_invalid_state() {
+        // more received than announced
+        let blob = [PARSER_STATE_VERSION, 1, 0, 0, 0, 1, 0, 0, 0, 2, 9, 9];
+        assert!(matches!(
+            ParserState::from_bytes(&blob),
+            Err(FrameError::InvalidState(_))
+        ));
+        // truncated blob
+        assert!(matches!(
+            ParserState::from_bytes(&blob[..11]),
+            Err(FrameError::ParsingError(_))
+        ));
+    }
+
+    #[test]
+    fn extended_frames_with_garbage_bodies() {
+        for body in [
+            &[][..],
+            &[0],
+            &[0, 0x10],
+            &[0, 0x01, 1, 2, 3],
+            &[0, 0x04, 200, b'a'],
+            &[0, 0x04, 2, 0xff, 0xfe],
+            &[0, 0x0c, 1, b'a', 0, 0, 0, 1],
+            &[1, 0, 1, 2],
+            &[3],
+            &[3, 0, 0],
+            &[9, 0],
+        ] {
+            let mut frame = header(body.len() as u32 | EXTENDED_FLAG);
+            frame.extend_from_slice(body);
+            assert!(is_parsing_error(frame.parse_frame_header(None, None)));
+        }
+    }
+
+    #[test]
+    fn announced_len_larger_than_anything_sent() {
+        let mut packet = header(MAX_BODY_LEN as u32);
+        packet.extend_from_slice(b"some");
+        let mut state = ParserState::new();
+        assert!(state.parse(packet).unwrap().is_empty());
+        assert_eq!(state.announced_len(), Some(MAX_BODY_LEN));
+    }
+
+    proptest! {
+        #[test]
+        fn any_caller_state_is_an_output_or_an_error(
+            data in prop::collection::vec(any::<u8>(), 0..64),
+            incomplete in prop::option::of((any::<usize>(), prop::collection::vec(any::<u8>(), 0..32))),
+            truncated in prop::option::of(prop::collection::vec(any::<u8>(), 0..32)),
+            with_magic in any::<bool>(),
+            extended in any::<bool>(),
+        ) {
+            let incomplete = incomplete.map(|(len, received)| {
+                let announced = if extended { AnnouncedLen::from_field(len) } else { len.into() };
+                (announced, received)
+            });
+            let data = if with_magic { [&MAGIC_PREFIX[..], &data].concat() } else { data };
+            let truncated = truncated.map(|hdr| {
+                // often a real prefix of a header
+                if with_magic { MAGIC_PREFIX[..hdr.len().min(MAGIC_PREFIX.len())].to_vec() } else { hdr }
+            });
+            let _ = data.parse_frame_header(incomplete, truncated);
+        }
+
+        #[test]
+        fn any_checkpoint_is_restored_or_rejected(
+            blob in prop::collection::vec(any::<u8>(), 0..48),
+            packet in prop::collection::vec(any::<u8>(), 0..48),
+        ) {
+            if let Ok(mut state) = ParserState::from_bytes(&blob) {
+                let _ = state.parse(packet);
+            }
+        }
+
+        #[test]
+        fn any_extended_body_is_decoded_or_rejected(
+            body in prop::collection::vec(any::<u8>(), 0..48),
+            sizes in prop::collection::vec(1usize..20, 1..8),
+        ) {
+            let mut stream = header(body.len() as u32 | EXTENDED_FLAG);
+            stream.extend_from_slice(&body);
+            let mut state = ParserState::new();
+            let mut rest = &stream[..];
+            for size in sizes.iter().cycle() {
+                if rest.is_empty() {
+                    break;
+                }
+                let (packet, tail) = rest.split_at((*size).min(rest.len()));
+                let _ = state.parse(packet.to_vec());
+                rest = tail;
+            }
+        }
+
+        #[test]
+        fn other_decoders_survive_anything(
+            packets in prop::collection::vec(prop::collection::vec(any::<u8>(), 0..40), 1..10),
+            capacity in 0usize..40,
+        ) {
+            let mut buf = vec![0u8; capacity];
+            let mut slice_decoder = SliceFrameDecoder::new(&mut buf);
+            let mut ring_decoder = RingFrameDecoder::with_capacity(capacity.max(HDR_SIZE));
+            for packet in &packets {
+                let _ = slice_decoder.decode(packet, |_| {});
+                let accepted = ring_decoder.push(packet);
+                while let Ok(Some(_)) = ring_decoder.next_frame() {}
+                let _ = ring_decoder.push(&packet[accepted..]);
+            }
+            let _ = inspect(&packets.concat());
+        }
+    }
+
+    #[cfg(feature = "pcap")]
+    proptest! {
+        #[test]
+        fn any_capture_is_read_or_rejected(
+            file in prop::collection::vec(any::<u8>(), 0..256),
+            pcapng in any::<bool>(),
+        ) {
+            let mut file = file;
+            // get past the magic number check most of the time
+            let magic: [u8; 4] = if pcapng { [0x0a, 0x0d, 0x0d, 0x0a] } else { [0xd4, 0xc3, 0xb2, 0xa1] };
+            file.splice(0..file.len().min(4), magic);
+            if let Ok(capture) = crate::Capture::parse(&file) {
+                for flow in capture.flows() {
+                    let _ = flow.stream();
+                    let _ = flow.decode();
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,250 @@
+//! Topic routing.
+//!
+//! A data frame can carry a topic (see `FrameTags`), either as a name made of `/` separated
+//! levels (`sensors/kitchen/temp`) or as the 32 bits hash of such a name, cheaper on the wire.
+//! [`TopicRouter`] hands the frames to the subscribers whose filter matches their topic.
+
+use alloc::{
+    boxed::Box,
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+
+use crate::{error::FrameError, stream_frame::ParsedStreamData};
+
+/// Longest topic name, its length is written on one byte.
+pub const MAX_TOPIC_LEN: usize = u8::MAX as usize;
+
+/// Topic of a data frame.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Topic {
+    Name(String),
+    /// Hash of a name, see [`Topic::hashed`].
+    Id(u32),
+}
+
+impl Topic {
+    /// `Topic::Id` of `name`, matched by the subscribers of the same name (but not by wildcard
+    /// filters).
+    #[must_use]
+    pub fn hashed(name: &str) -> Self {
+        Topic::Id(topic_hash(name))
+    }
+
+    pub(crate) fn validate(&self) -> Result<(), FrameError> {
+        let Topic::Name(name) = self else {
+            return Ok(());
+        };
+        if name.is_empty() || name.len() > MAX_TOPIC_LEN {
+            return Err(FrameError::ParsingError(format!(
+                "topic len [{}] out of range",
+                name.len()
+            )));
+        }
+        if name.contains(['+', '#']) {
+            return Err(FrameError::ParsingError(format!(
+                "wildcard in topic name [{name}]"
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// FNV-1a, stable across builds and platforms so that every peer computes the same ids.
+fn topic_hash(name: &str) -> u32 {
+    name.bytes().fold(0x811c_9dc5, |hash, byte| {
+        (hash ^ u32::from(byte)).wrapping_mul(0x0100_0193)
+    })
+}
+
+// returns false once the subscriber is gone, the subscription is then removed
+type Callback = Box<dyn FnMut(&Topic, &[u8]) -> bool + Send>;
+
+struct Subscription {
+    id: u64,
+    levels: Vec<String>,
+    // hash of the filter when it has no wildcard, to match `Topic::Id`
+    hash: Option<u32>,
+    callback: Callback,
+}
+
+impl Subscription {
+    fn matches(&self, topic: &Topic) -> bool {
+        match topic {
+            Topic::Id(id) => self.hash == Some(*id),
+            Topic::Name(name) => {
+                let mut levels = name.split('/');
+                for filter in &self.levels {
+                    match (filter.as_str(), levels.next()) {
+                        ("#", _) => return true,
+                        (_, None) => return false,
+                        ("+", Some(_)) => {}
+                        (filter, Some(level)) if filter == level => {}
+                        _ => return false,
+                    }
+                }
+                levels.next().is_none()
+            }
+        }
+    }
+}
+
+/// Dispatches the topic frames to the callbacks subscribed with a matching filter.
+///
+/// Filters follow the MQTT rules : `+` matches exactly one level, `#` (last level only) matches
+/// any number of levels, none included (`a/#` matches `a`, `a/b` and `a/b/c`).
+///
+/// ```rust
+/// use stream_framer::{FrameParser, FrameTags, Topic, TopicRouter};
+/// use std::sync::{Arc, Mutex};
+///
+/// let received = Arc::new(Mutex::new(vec![]));
+/// let mut router = TopicRouter::new();
+///
+/// let sink = received.clone();
+/// router
+///     .subscribe("sensors/+/temp", move |_topic, payload| {
+///         sink.lock().unwrap().push(payload.to_vec())
+///     })
+///     .unwrap();
+///
+/// let tags = FrameTags {
+///     topic: Some(Topic::Name("sensors/kitchen/temp".into())),
+///     ..Default::default()
+/// };
+/// let frame = tags.encode(b"21.5").unwrap();
+///
+/// for parsed in frame.parse_frame_header(None, None).unwrap() {
+///     assert!(router.dispatch(parsed).is_none());
+/// }
+/// assert_eq!(*received.lock().unwrap(), [b"21.5"]);
+/// ```
+#[derive(Default)]
+pub struct TopicRouter {
+    subscriptions: Vec<Subscription>,
+    next_id: u64,
+}
+
+impl TopicRouter {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Call `callback` with the topic and payload of every frame matching `filter`. Returns the
+    /// id to unsubscribe with.
+    /// # Errors
+    /// `ParsingError` if the filter is empty or misuses a wildcard (`a+/b`, `#/a`...).
+    pub fn subscribe<F>(&mut self, filter: &str, mut callback: F) -> Result<u64, FrameError>
+    where
+        F: FnMut(&Topic, &[u8]) + Send + 'static,
+    {
+        self.add_subscription(
+            filter,
+            Box::new(move |topic, payload| {
+                callback(topic, payload);
+                true
+            }),
+        )
+    }
+
+    fn add_subscription(&mut self, filter: &str, callback: Callback) -> Result<u64, FrameError> {
+        let invalid = || FrameError::ParsingError(format!("invalid topic filter [{filter}]"));
+        if filter.is_empty() {
+            return Err(invalid());
+        }
+        let levels: Vec<String> = filter.split('/').map(ToString::to_string).collect();
+        let last = levels.len() - 1;
+        for (i, level) in levels.iter().enumerate() {
+            let misplaced_wildcard = level.len() > 1 && level.contains(['+', '#']);
+            if misplaced_wildcard || (level == "#" && i != last) {
+                return Err(invalid());
+            }
+        }
+        let hash = (!filter.contains(['+', '#'])).then(|| topic_hash(filter));
+
+        let id = self.next_id;
+        self.next_id += 1;
+        self.subscriptions.push(Subscription {
+            id,
+            levels,
+            hash,
+            callback,
+        });
+        Ok(id)
+    }
+
+    /// Subscribe an mpsc channel to `filter`, the subscription ends with the receiver : it is
+    /// removed when the first frame routed to it after the receiver is dropped can't be sent.
+    /// # Errors
+    /// The `subscribe` errors.
+    #[cfg(feature = "std")]
+    pub fn subscribe_channel(
+        &mut self,
+        filter: &str,
+    ) -> Result<std::sync::mpsc::Receiver<(Topic, Vec<u8>)>, FrameError> {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        self.add_subscription(
+            filter,
+            Box::new(move |topic, payload| sender.send((topic.clone(), payload.to_vec())).is_ok()),
+        )?;
+        Ok(receiver)
+    }
+
+    /// Subscriptions, the channels whose receiver was dropped included until a frame is routed
+    /// to them.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.subscriptions.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.subscriptions.is_empty()
+    }
+
+    /// Returns false if `id` was not subscribed.
+    pub fn unsubscribe(&mut self, id: u64) -> bool {
+        let len = self.subscriptions.len();
+        self.subscriptions
+            .retain(|subscription| subscription.id != id);
+        self.subscriptions.len() != len
+    }
+
+    /// Deliver `payload` to the subscribers of `topic`, returns how many there were. The
+    /// channel subscriptions whose receiver was dropped are removed and not counted.
+    pub fn route(&mut self, topic: &Topic, payload: &[u8]) -> usize {
+        let mut delivered = 0;
+        self.subscriptions.retain_mut(|subscription| {
+            if !subscription.matches(topic) {
+                return true;
+            }
+            let alive = (subscription.callback)(topic, payload);
+            delivered += usize::from(alive);
+            alive
+        });
+        delivered
+    }
+
+    /// Route a parser output carrying a topic. Anything else (plain data, control frames,
+    /// partial frames) is handed back, as well as the topic frames no subscriber matched.
+    pub fn dispatch(&mut self, parsed: ParsedStreamData) -> Option<ParsedStreamData> {
+        match parsed {
+            ParsedStreamData::Tagged(tags, payload) => match &tags.topic {
+                Some(topic) if self.route(topic, &payload) > 0 => None,
+                _ => Some(ParsedStreamData::Tagged(tags, payload)),
+            },
+            other => Some(other),
+        }
+    }
+}
+
+impl core::fmt::Debug for TopicRouter {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("TopicRouter")
+            .field("subscriptions", &self.subscriptions.len())
+            .field("next_id", &self.next_id)
+            .finish()
+    }
+}
@@ -0,0 +1,282 @@
+//! Allocations made by the framing and parsing paths, counted by a test-time global allocator.
+//!
+//! The counts are exact, measured once and broken down in the constants below : a failure means a
+//! change added (or saved) allocations on a hot path, either fix it or update the breakdown
+//! knowingly. The streams are cut so that every frame falls the same way over the packets, each
+//! frame and each packet then costs the same.
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    borrow::Cow,
+    cell::Cell,
+    collections::VecDeque,
+};
+
+use stream_framer::{
+    FrameFormat, FrameParser, FrameWriter, HDR_SIZE, MagicLength, PaddingPolicy, ParsedStreamData,
+    ParserState, RingFrameDecoder, SliceFrameDecoder,
+};
+
+struct CountingAllocator;
+
+thread_local! {
+    // only the allocations of the thread running `allocations` are counted, the tests run in
+    // parallel
+    static COUNT: Cell<Option<usize>> = const { Cell::new(None) };
+}
+
+fn count_one() {
+    let _ = COUNT.try_with(|count| {
+        if let Some(n) = count.get() {
+            count.set(Some(n + 1));
+        }
+    });
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        count_one();
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        count_one();
+        unsafe { System.alloc_zeroed(layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        count_one();
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// Run `f`, returning its output and the number of allocations (and reallocations) it made.
+fn allocations<T>(f: impl FnOnce() -> T) -> (T, usize) {
+    COUNT.with(|count| count.set(Some(0)));
+    let output = f();
+    let count = COUNT.with(|count| count.replace(None)).unwrap();
+    (output, count)
+}
+
+const FRAMES: usize = 1000;
+
+/// A completed body is copied out of the packet.
+const BODY: usize = 1;
+
+/// The output vec of a packet grows by doubling from 4 slots : 1 allocation for up to 4 outputs,
+/// 3 for 10 (4, 8 then 16 slots), 9 for 1000 (4, 8, ..., 1024 slots).
+const OUTPUT_UP_TO_4: usize = 1;
+const OUTPUT_10: usize = 3;
+const OUTPUT_1000: usize = 9;
+
+/// 112 bytes frames in 7 bytes packets, each frame spans exactly 16 packets. The header is cut
+/// after 7 bytes : copied out as a `TruncatedHeader`, then grown once to hold its 12 bytes.
+const SPLIT_HEADER: usize = 2;
+/// The first 2 body bytes are copied out as an `Incompleted` body, which grows by doubling as
+/// each packet adds 7 bytes : 9, 18, 36, 72 then 144 bytes.
+const SMALL_BODY_IN_7_BYTES_PACKETS: usize = 1 + 5;
+/// 4000 bytes frames in 1000 bytes packets, each frame spans exactly 4 packets. The first 988
+/// body bytes are copied out, the body grows by doubling as each packet adds 1000 bytes : 1988,
+/// 3976 then 7952 bytes.
+const LARGE_BODY_IN_1000_BYTES_PACKETS: usize = 1 + 3;
+
+fn stream(message_size: usize) -> Vec<u8> {
+    (0..FRAMES)
+        .flat_map(|i| vec![i as u8; message_size].prepend_frame().unwrap())
+        .collect()
+}
+
+fn packets(message_size: usize, packet_size: usize) -> Vec<Vec<u8>> {
+    let stream = stream(message_size);
+    // every frame falls the same way over the packets
+    let frame_size = message_size + HDR_SIZE;
+    assert_eq!(frame_size.max(packet_size) % frame_size.min(packet_size), 0);
+    assert_eq!(stream.len() % packet_size, 0);
+    stream.chunks(packet_size).map(<[u8]>::to_vec).collect()
+}
+
+/// Feed `packets` to the caller held state API, returns the number of completed frames.
+fn parse_all(packets: &[Vec<u8>]) -> usize {
+    let mut incomplete = None;
+    let mut truncated = None;
+    let mut completed = 0;
+    for packet in packets {
+        for parsed in packet
+            .as_slice()
+            .parse_frame_header(incomplete.take(), truncated.take())
+            .unwrap()
+        {
+            match parsed {
+                ParsedStreamData::Incompleted(size, data) => incomplete = Some((size, data)),
+                ParsedStreamData::TruncatedHeader(hdr) => truncated = Some(hdr),
+                _ => completed += 1,
+            }
+        }
+    }
+    completed
+}
+
+#[test]
+fn prepend_frame_allocates_once() {
+    let message = vec![1u8; 1000];
+    let (frame, count) = allocations(|| message.prepend_frame().unwrap());
+    assert_eq!(frame.len(), 1000 + HDR_SIZE);
+    assert_eq!(count, 1);
+}
+
+#[test]
+fn padded_frames_are_written_in_one_allocation() {
+    let message = vec![1u8; 1000];
+    let (frame, count) = allocations(|| {
+        message
+            .prepend_frame_padded(PaddingPolicy::Block(256))
+            .unwrap()
+    });
+    assert_eq!(frame.len(), HDR_SIZE + 1024);
+    assert_eq!(count, 1);
+
+    let writer = MagicLength::with_padding(PaddingPolicy::Block(256));
+    let message = vec![1u8; 1000];
+    let mut out = Vec::with_capacity(HDR_SIZE + 1024);
+    let ((), count) = allocations(|| writer.encode(&message, &mut out).unwrap());
+    assert_eq!(out.len(), HDR_SIZE + 1024);
+    assert_eq!(count, 0);
+}
+
+#[test]
+fn prepend_frame_in_place_reuses_spare_capacity() {
+    let mut message = Vec::with_capacity(100 + HDR_SIZE);
+    message.extend_from_slice(&[1u8; 100]);
+    let ((), count) = allocations(|| message.prepend_frame_in_place().unwrap());
+    assert_eq!(message.len(), 100 + HDR_SIZE);
+    assert_eq!(count, 0);
+}
+
+#[test]
+fn whole_frames_in_one_packet() {
+    let packet = [stream(100)];
+    let (completed, count) = allocations(|| parse_all(&packet));
+    assert_eq!(completed, FRAMES);
+    assert_eq!(count, FRAMES * BODY + OUTPUT_1000);
+}
+
+#[test]
+fn frames_split_across_packets() {
+    for (message_size, packet_size, per_frame, per_packet) in [
+        (
+            100,
+            7,
+            SPLIT_HEADER + SMALL_BODY_IN_7_BYTES_PACKETS,
+            OUTPUT_UP_TO_4,
+        ),
+        // 10 frames of 100 bytes per packet
+        (88, 1000, BODY, OUTPUT_10),
+        (3988, 1000, LARGE_BODY_IN_1000_BYTES_PACKETS, OUTPUT_UP_TO_4),
+    ] {
+        let packets = packets(message_size, packet_size);
+        let packet_count = packets.len();
+        let (completed, count) = allocations(|| parse_all(&packets));
+        assert_eq!(completed, FRAMES);
+        assert_eq!(
+            count,
+            FRAMES * per_frame + packet_count * per_packet,
+            "{FRAMES} frames of {message_size} bytes in {packet_count} packets of \
+             {packet_size} bytes"
+        );
+    }
+}
+
+#[test]
+fn parser_state_adds_one_allocation_per_packet() {
+    let packets = packets(88, 1000);
+    let (raw, raw_count) = allocations(|| parse_all(&packets));
+    let packet_count = packets.len();
+    let (with_state, state_count) = allocations(|| {
+        let mut state = ParserState::new();
+        packets
+            .into_iter()
+            .map(|packet| state.parse(packet).unwrap().len())
+            .sum::<usize>()
+    });
+    assert_eq!(raw, with_state);
+    // the vec of the finished frames, the parser output is consumed
+    assert_eq!(state_count, raw_count + packet_count);
+}
+
+#[test]
+fn no_buffer_type_copies_the_packet() {
+    let packet = stream(100);
+    let parsed_len = |parsed: Result<Vec<ParsedStreamData>, _>| parsed.unwrap().len();
+
+    let owned = packet.clone();
+    let (completed, vec_count) = allocations(|| parsed_len(owned.parse_frame_header(None, None)));
+    assert_eq!(completed, FRAMES);
+    assert_eq!(vec_count, FRAMES * BODY + OUTPUT_1000);
+
+    let boxed = packet.clone().into_boxed_slice();
+    let deque = VecDeque::from(packet.clone());
+    for (name, (completed, count)) in [
+        (
+            "&[u8]",
+            allocations(|| parsed_len(packet.as_slice().parse_frame_header(None, None))),
+        ),
+        (
+            "Cow::Borrowed",
+            allocations(|| parsed_len(Cow::Borrowed(&packet[..]).parse_frame_header(None, None))),
+        ),
+        (
+            "Box<[u8]>",
+            allocations(|| parsed_len(boxed.parse_frame_header(None, None))),
+        ),
+        (
+            "VecDeque<u8>",
+            allocations(|| parsed_len(deque.parse_frame_header(None, None))),
+        ),
+    ] {
+        assert_eq!(completed, FRAMES, "{name}");
+        assert_eq!(count, vec_count, "{name} allocates more than Vec<u8>");
+    }
+}
+
+#[test]
+fn fixed_buffer_decoders_never_allocate() {
+    let packets = packets(88, 1000);
+
+    let mut buf = [0u8; 100];
+    let mut slice_decoder = SliceFrameDecoder::new(&mut buf);
+    let (completed, count) = allocations(|| {
+        let mut completed = 0;
+        for packet in &packets {
+            slice_decoder
+                .decode(packet, |_frame| completed += 1)
+                .unwrap();
+        }
+        completed
+    });
+    assert_eq!(completed, FRAMES);
+    assert_eq!(count, 0);
+
+    let mut ring_decoder = RingFrameDecoder::with_capacity(1200);
+    let (completed, count) = allocations(|| {
+        let mut completed = 0;
+        for packet in &packets {
+            let mut packet = &packet[..];
+            while !packet.is_empty() {
+                packet = &packet[ring_decoder.push(packet)..];
+                while let Some(_frame) = ring_decoder.next_frame().unwrap() {
+                    completed += 1;
+                }
+            }
+        }
+        completed
+    });
+    assert_eq!(completed, FRAMES);
+    assert_eq!(count, 0);
+}
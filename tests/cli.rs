@@ -0,0 +1,222 @@
+#![cfg(feature = "cli")]
+
+use std::{
+    io::Write,
+    process::{Command, Output, Stdio},
+};
+
+use stream_framer::{ControlFrame, FrameWriter};
+
+fn stream_framer(args: &[&str], stdin: &[u8]) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_stream-framer"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(stdin).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+fn inspect_reports_frames_and_anomalies() {
+    let mut capture = b"hello".to_vec().prepend_frame().unwrap();
+    capture.extend(ControlFrame::Ping(7).encode().unwrap());
+    capture.extend_from_slice(b"junk");
+    capture.extend(b"cut short".to_vec().prepend_frame().unwrap()[..15].to_vec());
+
+    let output = stream_framer(&["inspect", "--json", "-"], &capture);
+    assert_eq!(output.status.code(), Some(1));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(
+        lines,
+        [
+            r#"{"event":"frame","offset":0,"header":"00f101e402ff03dd00000005","len":5,"kind":"data","preview_hex":"68656c6c6f","preview_utf8":"hello","preview_truncated":false}"#,
+            r#"{"event":"frame","offset":17,"header":"00f101e402ff03dd8000000a","len":10,"kind":"control","detail":"ping nonce=7"}"#,
+            r#"{"event":"bad_magic","offset":39}"#,
+            r#"{"event":"resync","offset":43,"skipped":4}"#,
+            r#"{"event":"truncated_body","offset":43,"len":9,"available":3}"#,
+        ]
+    );
+
+    let output = stream_framer(&["inspect"], &b"ok".to_vec().prepend_frame().unwrap());
+    assert_eq!(output.status.code(), Some(0));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("len 2  data  6f6b  |ok|"), "{stdout}");
+    assert!(
+        stdout.ends_with("14 bytes, 1 frames, 0 anomalies\n"),
+        "{stdout}"
+    );
+}
+
+#[test]
+fn usage_errors() {
+    for args in [
+        &[][..],
+        &["nope"],
+        &["inspect", "--wat"],
+        &["inspect", "a", "b"],
+    ] {
+        let output = stream_framer(args, b"");
+        assert_eq!(output.status.code(), Some(2), "{args:?}");
+        assert!(String::from_utf8_lossy(&output.stderr).contains("usage:"));
+    }
+}
+
+// a fresh directory under the system temp dir
+fn scratch_dir(name: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("stream-framer-{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn encode_split_join_round_trip() {
+    let dir = scratch_dir("round-trip");
+    let lines = "first line\nsecond\r\n\nlast line, no newline";
+
+    let output = stream_framer(&["encode", "--lines"], lines.as_bytes());
+    assert_eq!(output.status.code(), Some(0));
+    let framed = output.stdout;
+    assert!(framed.starts_with(&b"first line".to_vec().prepend_frame().unwrap()));
+
+    for mtu in ["1", "5", "13", "1000"] {
+        let packets = dir.join(format!("mtu-{mtu}"));
+        let output = stream_framer(
+            &[
+                "split",
+                "--mtu",
+                mtu,
+                "--out-dir",
+                packets.to_str().unwrap(),
+            ],
+            &framed,
+        );
+        assert_eq!(output.status.code(), Some(0));
+
+        let output = stream_framer(&["join", "--lines", packets.to_str().unwrap()], b"");
+        assert_eq!(output.status.code(), Some(0), "mtu {mtu}");
+        assert_eq!(
+            String::from_utf8(output.stdout).unwrap(),
+            "first line\nsecond\n\nlast line, no newline\n"
+        );
+    }
+
+    // one message per input file, joined as one file per message
+    let (a, b) = (dir.join("a.bin"), dir.join("b.bin"));
+    std::fs::write(&a, [0u8, 1, 2]).unwrap();
+    std::fs::write(&b, b"").unwrap();
+    let framed = dir.join("framed.bin");
+    let output = stream_framer(
+        &[
+            "encode",
+            "-o",
+            framed.to_str().unwrap(),
+            a.to_str().unwrap(),
+            b.to_str().unwrap(),
+        ],
+        b"",
+    );
+    assert_eq!(output.status.code(), Some(0));
+
+    let messages = dir.join("messages");
+    let output = stream_framer(
+        &[
+            "join",
+            "--out-dir",
+            messages.to_str().unwrap(),
+            framed.to_str().unwrap(),
+        ],
+        b"",
+    );
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        std::fs::read(messages.join("message-000000.bin")).unwrap(),
+        [0, 1, 2]
+    );
+    assert_eq!(
+        std::fs::read(messages.join("message-000001.bin")).unwrap(),
+        b""
+    );
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn join_reports_a_truncated_stream() {
+    let dir = scratch_dir("truncated");
+    let frame = b"truncated".to_vec().prepend_frame().unwrap();
+    std::fs::write(dir.join("packet-0.bin"), &frame[..15]).unwrap();
+
+    let output = stream_framer(&["join", dir.to_str().unwrap()], b"");
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("3 body bytes of 9"));
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+// generated by the pcap cases of the crate tests
+const TCP_FIXTURE: &str = concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/tests/fixtures/tcp_frames.pcap"
+);
+const UDP_FIXTURE: &str = concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/tests/fixtures/udp_frames.pcapng"
+);
+
+#[test]
+fn pcap_lists_and_inspects_flows() {
+    let output = stream_framer(&["pcap", "--json", TCP_FIXTURE], b"");
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        concat!(
+            r#"{"flow":0,"transport":"tcp","src":"10.0.0.1:40000","dst":"10.0.0.2:4433","packets":27,"bytes":2495,"gaps":[]}"#,
+            "\n",
+            r#"{"flow":1,"transport":"tcp","src":"10.0.0.2:4433","dst":"10.0.0.1:40000","packets":0,"bytes":0,"gaps":[]}"#,
+            "\n"
+        )
+    );
+
+    let output = stream_framer(&["pcap", "--flow", "0", TCP_FIXTURE], b"");
+    assert_eq!(output.status.code(), Some(0));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        stdout.ends_with("2495 bytes, 20 frames, 0 anomalies\n"),
+        "{stdout}"
+    );
+
+    // from stdin, pcapng
+    let output = stream_framer(
+        &["pcap", "--flow", "1"],
+        &std::fs::read(UDP_FIXTURE).unwrap(),
+    );
+    assert_eq!(output.status.code(), Some(0));
+    assert!(
+        String::from_utf8(output.stdout)
+            .unwrap()
+            .contains("control  ping nonce=7")
+    );
+
+    let output = stream_framer(&["pcap", "--flow", "9", UDP_FIXTURE], b"");
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn dissector_matches_the_golden_file() {
+    let output = stream_framer(&["dissector"], b"");
+    assert_eq!(output.status.code(), Some(0));
+    let golden = std::fs::read(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/fixtures/stream_framer.lua"
+    ))
+    .unwrap();
+    assert!(output.stdout == golden);
+
+    let output = stream_framer(&["dissector", "--port", "4433"], b"");
+    assert_eq!(output.status.code(), Some(2));
+}
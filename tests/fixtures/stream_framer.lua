@@ -0,0 +1,256 @@
+-- Wireshark dissector for stream_framer frames.
+--
+-- Generated from the crate's format definition (`stream-framer dissector`), regenerate
+-- it rather than editing it.
+
+local proto = Proto("stream_framer", "Stream Framer")
+
+local MAGIC = ByteArray.new("00f101e402ff03dd")
+local HDR_SIZE = 12
+local EXTENDED_FLAG = 0x80000000
+local MAX_BODY_LEN = 0x7fffffff
+local EXT_HDR_SIZE = 2
+local KNOWN_FLAGS = 0x7f
+
+local kinds = {
+    [0] = "data",
+    [1] = "ping",
+    [2] = "pong",
+    [3] = "close",
+    [4] = "credit",
+    [5] = "escaped",
+    [6] = "chunk",
+}
+
+local close_code_names = {
+    [0] = "normal",
+    [1] = "going away",
+    [2] = "protocol error",
+    [3] = "internal error",
+}
+
+local f = proto.fields
+f.magic = ProtoField.bytes("stream_framer.magic", "Magic prefix")
+f.extended = ProtoField.bool("stream_framer.extended", "Extended", 32, nil, EXTENDED_FLAG)
+f.body_len = ProtoField.uint32("stream_framer.body_len", "Body length", base.DEC, nil, MAX_BODY_LEN)
+f.kind = ProtoField.uint8("stream_framer.kind", "Kind", base.DEC, kinds)
+f.flags = ProtoField.uint8("stream_framer.flags", "Flags", base.HEX)
+f.flag_correlation_id = ProtoField.bool("stream_framer.flags.correlation_id", "Correlation id", 8, nil, 0x01)
+f.flag_reply = ProtoField.bool("stream_framer.flags.reply", "Reply", 8, nil, 0x02)
+f.flag_topic_name = ProtoField.bool("stream_framer.flags.topic_name", "Topic name", 8, nil, 0x04)
+f.flag_topic_id = ProtoField.bool("stream_framer.flags.topic_id", "Topic id", 8, nil, 0x08)
+f.flag_masked = ProtoField.bool("stream_framer.flags.masked", "Masked", 8, nil, 0x10)
+f.flag_padded = ProtoField.bool("stream_framer.flags.padded", "Padded", 8, nil, 0x20)
+f.flag_continued = ProtoField.bool("stream_framer.flags.continued", "Continued", 8, nil, 0x40)
+f.correlation_id = ProtoField.uint64("stream_framer.correlation_id", "Correlation id", base.DEC)
+f.topic_name_len = ProtoField.uint8("stream_framer.topic_name_len", "Topic name length", base.DEC)
+f.topic_name = ProtoField.string("stream_framer.topic_name", "Topic name")
+f.topic_id = ProtoField.uint32("stream_framer.topic_id", "Topic id", base.HEX)
+f.masking_key = ProtoField.uint32("stream_framer.masking_key", "Masking key", base.HEX)
+f.payload_len = ProtoField.uint32("stream_framer.payload_len", "Payload length", base.DEC)
+f.masked_body = ProtoField.bytes("stream_framer.masked_body", "Masked fields and payload")
+f.padding = ProtoField.bytes("stream_framer.padding", "Padding")
+f.data_payload = ProtoField.bytes("stream_framer.data.payload", "Payload")
+f.ping_nonce = ProtoField.uint64("stream_framer.ping.nonce", "Nonce", base.DEC)
+f.pong_nonce = ProtoField.uint64("stream_framer.pong.nonce", "Nonce", base.DEC)
+f.close_code = ProtoField.uint16("stream_framer.close.code", "Code", base.DEC, close_code_names)
+f.close_reason = ProtoField.string("stream_framer.close.reason", "Reason")
+f.credit_channel = ProtoField.uint32("stream_framer.credit.channel", "Channel", base.DEC)
+f.credit_frames = ProtoField.uint32("stream_framer.credit.frames", "Frames", base.DEC)
+f.credit_bytes = ProtoField.uint32("stream_framer.credit.bytes", "Bytes", base.DEC)
+f.escaped_body = ProtoField.bytes("stream_framer.escaped.body", "Escaped body (COBS)")
+f.chunk_stream = ProtoField.uint32("stream_framer.chunk.stream", "Chunk stream", base.DEC)
+f.chunk_piece = ProtoField.bytes("stream_framer.chunk.piece", "Frame piece")
+
+local malformed = ProtoExpert.new("stream_framer.malformed", "Malformed frame", expert.group.MALFORMED, expert.severity.ERROR)
+local truncated = ProtoExpert.new("stream_framer.truncated", "Truncated frame", expert.group.MALFORMED, expert.severity.WARN)
+proto.experts = { malformed, truncated }
+
+-- Dissect the frame filling `tvb` (header included), returns its length.
+local function dissect_frame(tvb, pinfo, tree)
+    local len_field = tvb(8, 4):uint()
+    local frame_len = HDR_SIZE + bit.band(len_field, MAX_BODY_LEN)
+    local frame = tree:add(proto, tvb(0, frame_len))
+    frame:add(f.magic, tvb(0, 8))
+    if tvb(0, 8):bytes() ~= MAGIC then
+        frame:add_proto_expert_info(malformed, "No magic prefix")
+        return frame_len
+    end
+    frame:add(f.extended, tvb(8, 4))
+    frame:add(f.body_len, tvb(8, 4))
+
+    local kind = 0
+    local masked = false
+    local payload_end = frame_len
+    local offset = HDR_SIZE
+    if bit.band(len_field, EXTENDED_FLAG) ~= 0 then
+        if frame_len < HDR_SIZE + EXT_HDR_SIZE then
+            frame:add_proto_expert_info(malformed, "Shorter than its extension header")
+            return frame_len
+        end
+        kind = tvb(offset, 1):uint()
+        frame:add(f.kind, tvb(offset, 1))
+    end
+    -- an escaped frame has no flags, its body is another extended body, COBS encoded
+    if kind == 5 then
+        offset = offset + 1
+    elseif bit.band(len_field, EXTENDED_FLAG) ~= 0 then
+        local flags = tvb(offset + 1, 1):uint()
+        local flags_item = frame:add(f.flags, tvb(offset + 1, 1))
+        flags_item:add(f.flag_correlation_id, tvb(offset + 1, 1))
+        flags_item:add(f.flag_reply, tvb(offset + 1, 1))
+        flags_item:add(f.flag_topic_name, tvb(offset + 1, 1))
+        flags_item:add(f.flag_topic_id, tvb(offset + 1, 1))
+        flags_item:add(f.flag_masked, tvb(offset + 1, 1))
+        flags_item:add(f.flag_padded, tvb(offset + 1, 1))
+        flags_item:add(f.flag_continued, tvb(offset + 1, 1))
+        if bit.band(flags, bit.bnot(KNOWN_FLAGS)) ~= 0 then
+            flags_item:add_proto_expert_info(malformed, "Unknown flags")
+        end
+        offset = offset + EXT_HDR_SIZE
+        masked = bit.band(flags, 0x10) ~= 0
+        -- the masking key comes first, the other fields are masked with the payload
+        if masked then
+            frame:add(f.masking_key, tvb(offset, 4))
+            offset = offset + 4
+        else
+            -- the fields come in the order of the flag bits
+            if bit.band(flags, 0x01) ~= 0 then
+                frame:add(f.correlation_id, tvb(offset, 8))
+                offset = offset + 8
+            end
+            if bit.band(flags, 0x04) ~= 0 then
+                local len = tvb(offset, 1):uint()
+                frame:add(f.topic_name_len, tvb(offset, 1))
+                frame:add(f.topic_name, tvb(offset + 1, len))
+                offset = offset + 1 + len
+            end
+            if bit.band(flags, 0x08) ~= 0 then
+                frame:add(f.topic_id, tvb(offset, 4))
+                offset = offset + 4
+            end
+            if bit.band(flags, 0x20) ~= 0 then
+                frame:add(f.payload_len, tvb(offset, 4))
+                offset = offset + 4
+            end
+            -- the payload length is the last field, the padding follows the payload
+            if bit.band(flags, 0x20) ~= 0 then
+                payload_end = math.min(offset + tvb(offset - 4, 4):uint(), frame_len)
+                if payload_end < frame_len then
+                    frame:add(f.padding, tvb(payload_end, frame_len - payload_end))
+                end
+            end
+        end
+    end
+
+    local name = kinds[kind] or "unknown kind"
+    frame:append_text(", " .. name)
+    pinfo.cols.info:append(name .. " ")
+    if masked then
+        if offset < payload_end then
+            frame:add(f.masked_body, tvb(offset, payload_end - offset))
+        end
+        offset = payload_end
+    elseif kind == 0 then
+        if offset < payload_end then
+            frame:add(f.data_payload, tvb(offset, payload_end - offset))
+        end
+        offset = payload_end
+    elseif kind == 1 then
+        frame:add(f.ping_nonce, tvb(offset, 8))
+        offset = offset + 8
+    elseif kind == 2 then
+        frame:add(f.pong_nonce, tvb(offset, 8))
+        offset = offset + 8
+    elseif kind == 3 then
+        frame:add(f.close_code, tvb(offset, 2))
+        offset = offset + 2
+        if offset < payload_end then
+            frame:add(f.close_reason, tvb(offset, payload_end - offset))
+        end
+        offset = payload_end
+    elseif kind == 4 then
+        frame:add(f.credit_channel, tvb(offset, 4))
+        offset = offset + 4
+        frame:add(f.credit_frames, tvb(offset, 4))
+        offset = offset + 4
+        frame:add(f.credit_bytes, tvb(offset, 4))
+        offset = offset + 4
+    elseif kind == 5 then
+        if offset < payload_end then
+            frame:add(f.escaped_body, tvb(offset, payload_end - offset))
+        end
+        offset = payload_end
+    elseif kind == 6 then
+        frame:add(f.chunk_stream, tvb(offset, 4))
+        offset = offset + 4
+        if offset < payload_end then
+            frame:add(f.chunk_piece, tvb(offset, payload_end - offset))
+        end
+        offset = payload_end
+    else
+        frame:add_proto_expert_info(malformed, "Unknown kind")
+        offset = payload_end
+    end
+    if offset ~= payload_end then
+        frame:add_proto_expert_info(malformed, "Payload length doesn't match its kind")
+    end
+    return frame_len
+end
+
+local function frame_len(tvb, pinfo, offset)
+    return HDR_SIZE + bit.band(tvb(offset + 8, 4):uint(), MAX_BODY_LEN)
+end
+
+local function set_columns(pinfo)
+    pinfo.cols.protocol = "STREAM_FRAMER"
+    pinfo.cols.info:clear()
+end
+
+-- TCP, frames are reassembled across segments
+function proto.dissector(tvb, pinfo, tree)
+    set_columns(pinfo)
+    dissect_tcp_pdus(tvb, tree, HDR_SIZE, frame_len, dissect_frame)
+end
+
+-- UDP, a frame continued in the next datagram is only flagged
+local function dissect_datagram(tvb, pinfo, tree)
+    set_columns(pinfo)
+    local offset = 0
+    while offset < tvb:len() do
+        local rest = tvb:len() - offset
+        if rest < HDR_SIZE or frame_len(tvb, pinfo, offset) > rest then
+            local item = tree:add(proto, tvb(offset))
+            item:add_proto_expert_info(truncated, "Frame continued in the next datagram")
+            break
+        end
+        offset = offset + dissect_frame(tvb(offset):tvb(), pinfo, tree)
+    end
+    return tvb:len()
+end
+
+local function starts_with_magic(tvb)
+    return tvb:len() >= HDR_SIZE and tvb(0, 8):bytes() == MAGIC
+end
+
+local function heuristic_tcp(tvb, pinfo, tree)
+    if not starts_with_magic(tvb) then
+        return false
+    end
+    -- later segments of the connection don't necessarily start with a frame
+    pinfo.conversation = proto
+    proto.dissector(tvb, pinfo, tree)
+    return true
+end
+
+local function heuristic_udp(tvb, pinfo, tree)
+    if not starts_with_magic(tvb) then
+        return false
+    end
+    dissect_datagram(tvb, pinfo, tree)
+    return true
+end
+
+proto:register_heuristic("tcp", heuristic_tcp)
+proto:register_heuristic("udp", heuristic_udp)
+DissectorTable.get("tcp.port"):add_for_decode_as(proto)
@@ -0,0 +1,46 @@
+//! Byte-for-byte compatibility of `LengthDelimited` with the `tokio_util` codec it mirrors (the
+//! varint and netstring golden vectors are with the unit tests).
+
+use proptest::prelude::*;
+use stream_framer::{FrameFormat, LengthDelimited};
+use tokio_util::{
+    bytes::{Bytes, BytesMut},
+    codec::{Decoder, Encoder, LengthDelimitedCodec},
+};
+
+proptest! {
+    #[test]
+    fn length_delimited_matches_tokio_util(
+        messages in prop::collection::vec(prop::collection::vec(any::<u8>(), 0..2000), 0..10),
+        cut in 1usize..100,
+    ) {
+        let mut ours = Vec::new();
+        let mut theirs = BytesMut::new();
+        let mut codec = LengthDelimitedCodec::new();
+        for message in &messages {
+            LengthDelimited::new().encode(message, &mut ours).unwrap();
+            codec.encode(Bytes::from(message.clone()), &mut theirs).unwrap();
+        }
+        prop_assert_eq!(&ours, &theirs[..]);
+
+        // each decodes what the other one wrote, in packets
+        let mut decoded = Vec::new();
+        let mut decoder = LengthDelimited::new();
+        for packet in theirs.chunks(cut) {
+            decoder
+                .decode(packet, &mut |message| decoded.push(message))
+                .unwrap();
+        }
+        prop_assert_eq!(&decoded, &messages);
+
+        let mut decoded = Vec::new();
+        let mut buf = BytesMut::new();
+        for packet in ours.chunks(cut) {
+            buf.extend_from_slice(packet);
+            while let Some(message) = codec.decode(&mut buf).unwrap() {
+                decoded.push(message.to_vec());
+            }
+        }
+        prop_assert_eq!(&decoded, &messages);
+    }
+}